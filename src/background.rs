@@ -0,0 +1,180 @@
+use crate::*;
+
+/// Where the camera is looking when layers line up with the level as written.
+/// Layers are moved from here by how far the camera has moved, scaled by their parallax.
+const LAYER_ORIGIN: Vector3 = Vector3 {
+    x: 1.0,
+    y: 1.0,
+    z: 0.0,
+};
+
+/// How far past the level's bounds stars are placed, so zooming out doesn't show where they end.
+const STAR_MARGIN: f32 = 1.0;
+/// More than any level needs, so a typo can't allocate millions of them.
+pub const MAX_STARS: u32 = 5000;
+/// Per silhouette.
+pub const MAX_SILHOUETTE_POINTS: usize = 1000;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Star {
+    pub position: Vector3,
+    pub radius: f32,
+    /// 0.0 is fixed to the screen, 1.0 moves with the level.
+    pub parallax: f32,
+    /// Where in its twinkle the star starts, in radians.
+    pub phase: f32,
+    /// Radians per tick.
+    pub twinkle_speed: f32,
+}
+
+/// A filled shape from a line down to the bottom of the screen, like a far off mountain range.
+#[derive(Debug, Clone)]
+pub struct Silhouette {
+    /// Left to right.
+    pub points: Vec<Vector3>,
+    pub color: Color,
+    /// 0.0 is fixed to the screen, 1.0 moves with the level.
+    pub parallax: f32,
+}
+
+/// A textured rectangle, loaded from `web_build` by its file name before any level starts.
+#[derive(Debug, Clone)]
+pub struct Decal {
+    pub image: String,
+    pub center: Vector3,
+    pub size: Vector2,
+    /// 0.0 is fixed to the screen, 1.0 moves with the level.
+    pub parallax: f32,
+    /// How the image is sampled. An image is only loaded once,
+    /// so every decal using it is sampled the way the first one loaded asks for.
+    pub parameters: TextureParameters,
+    /// How many times the image is drawn across and up the decal, more than once only if it wraps.
+    pub repeat: Vector2,
+}
+
+impl Decal {
+    /// Where the decal is drawn with the camera looking at `visible`,
+    /// counter-clockwise from the bottom left like `ShapeBatch::push_quad`.
+    pub fn corners(&self, visible: &Bounds) -> [Vector3; 4] {
+        let offset = (visible.center() - LAYER_ORIGIN) * (1.0 - self.parallax);
+        let center = self.center + offset;
+        let (x, y) = (self.size.x / 2.0, self.size.y / 2.0);
+        [
+            center + Vector3::new(-x, -y, 0.0),
+            center + Vector3::new(x, -y, 0.0),
+            center + Vector3::new(x, y, 0.0),
+            center + Vector3::new(-x, y, 0.0),
+        ]
+    }
+
+    /// Where each of `corners` is in the image, which starts at its top left.
+    pub fn uvs(&self) -> [Vector2; 4] {
+        let (x, y) = (self.repeat.x, self.repeat.y);
+        [
+            Vector2::new(0.0, y),
+            Vector2::new(x, y),
+            Vector2::new(x, 0.0),
+            Vector2::new(0.0, 0.0),
+        ]
+    }
+}
+
+/// Everything drawn behind a level's lines, back to front: the theme's gradient,
+/// decals, stars and then silhouettes, each in the order they're declared.
+#[derive(Debug, Clone)]
+pub struct Background {
+    pub decals: Vec<Decal>,
+    pub star_count: u32,
+    pub stars: Vec<Star>,
+    pub silhouettes: Vec<Silhouette>,
+    /// Ticks since the level loaded, for twinkling.
+    pub tick: u32,
+}
+
+impl Background {
+    pub fn new() -> Self {
+        Self {
+            decals: Vec::new(),
+            star_count: 0,
+            stars: Vec::new(),
+            silhouettes: Vec::new(),
+            tick: 0,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::new();
+    }
+
+    /// Scatters `star_count` stars over `bounds`.
+    /// They're placed from their own generator started from `seed`, rather than a shared stream,
+    /// so a level's stars are the same however many levels were loaded before it.
+    pub fn place_stars(&mut self, bounds: &Bounds, seed: u64) {
+        let margin = Vector3::new(STAR_MARGIN, STAR_MARGIN, 0.0);
+        let (min, max) = (bounds.min - margin, bounds.max + margin);
+        let mut rng = Rng::with_stream(seed, RngStream::Procedural as u64);
+        self.stars = (0..self.star_count)
+            .map(|_| Star {
+                position: Vector3::new(rng.range(min.x, max.x), rng.range(min.y, max.y), 0.0),
+                radius: rng.range(0.002, 0.007),
+                parallax: rng.range(0.02, 0.15),
+                phase: rng.range(0.0, PI * 2.0),
+                twinkle_speed: rng.range(0.02, 0.08),
+            })
+            .collect();
+    }
+
+    /// Call once per tick.
+    pub fn update(&mut self) {
+        self.tick += 1;
+    }
+
+    /// Pushes the theme's gradient, drawn before anything else.
+    pub fn push_gradient(&self, batch: &mut ShapeBatch, theme: &Theme, visible: &Bounds) {
+        let Bounds { min, max } = *visible;
+        if theme.background_top != theme.background_bottom {
+            batch.push_quad(
+                [
+                    min,
+                    Vector3::new(max.x, min.y, 0.0),
+                    max,
+                    Vector3::new(min.x, max.y, 0.0),
+                ],
+                [
+                    theme.background_bottom,
+                    theme.background_bottom,
+                    theme.background_top,
+                    theme.background_top,
+                ],
+            );
+        }
+    }
+
+    /// Pushes the stars and silhouettes, drawn after the decals and before the level's lines.
+    pub fn push_layers(&self, batch: &mut ShapeBatch, visible: &Bounds) {
+        let min = visible.min;
+        let camera_offset = visible.center() - LAYER_ORIGIN;
+        for star in &self.stars {
+            let position = star.position + camera_offset * (1.0 - star.parallax);
+            let twinkle = 0.65 + 0.35 * (star.phase + self.tick as f32 * star.twinkle_speed).sin();
+            batch.push_circle(position, star.radius, Color::new(1.0, 1.0, 1.0, twinkle));
+        }
+
+        for silhouette in &self.silhouettes {
+            let offset = camera_offset * (1.0 - silhouette.parallax);
+            let color = silhouette.color;
+            for pair in silhouette.points.windows(2) {
+                let (left, right) = (pair[0] + offset, pair[1] + offset);
+                batch.push_quad(
+                    [
+                        Vector3::new(left.x, min.y, 0.0),
+                        Vector3::new(right.x, min.y, 0.0),
+                        right,
+                        left,
+                    ],
+                    [color; 4],
+                );
+            }
+        }
+    }
+}
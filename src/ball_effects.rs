@@ -0,0 +1,225 @@
+use crate::*;
+
+/// How the ball's motion is shown, set per level.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BallStyle {
+    /// Ticks of positions kept for the trail, 0 hides it.
+    pub trail_length: usize,
+    /// The trail's width where it meets the ball, relative to the ball's radius.
+    pub trail_width: f32,
+    /// Multiplies the ball's alpha where the trail meets the ball.
+    pub trail_alpha: f32,
+    /// How much the ball squashes on impact and stretches when moving fast, 0.0 keeps it round.
+    pub squash: f32,
+}
+
+impl Default for BallStyle {
+    fn default() -> Self {
+        Self {
+            trail_length: 20,
+            trail_width: 0.7,
+            trail_alpha: 0.3,
+            squash: 1.0,
+        }
+    }
+}
+
+/// The ball's past positions, oldest first once it's full.
+#[derive(Debug, Clone)]
+pub struct Trail {
+    positions: Vec<Vector3>,
+    // Where the next position is written once `positions` is full.
+    next: usize,
+}
+
+impl Trail {
+    pub fn new() -> Self {
+        Self {
+            positions: Vec::new(),
+            next: 0,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.positions.clear();
+        self.next = 0;
+    }
+
+    /// Keeps at most `length` positions, replacing the oldest.
+    pub fn push(&mut self, position: Vector3, length: usize) {
+        // A wrapped ring can't change size in place, so unwrap it first.
+        if self.positions.len() != length && self.next != 0 {
+            self.positions = self.ordered();
+            self.next = 0;
+        }
+        if self.positions.len() > length {
+            self.positions.drain(..self.positions.len() - length);
+        }
+        if length == 0 {
+            return;
+        }
+
+        if self.positions.len() < length {
+            self.positions.push(position);
+        } else {
+            self.positions[self.next] = position;
+            self.next = (self.next + 1) % length;
+        }
+    }
+
+    /// Oldest to newest.
+    pub fn ordered(&self) -> Vec<Vector3> {
+        let mut positions = self.positions[self.next..].to_vec();
+        positions.extend_from_slice(&self.positions[..self.next]);
+        positions
+    }
+
+    /// Pushes a ribbon that tapers and fades from `radius` at the newest position to nothing at the oldest.
+    pub fn push_to_batch(
+        &self,
+        batch: &mut ShapeBatch,
+        radius: f32,
+        color: Color,
+        style: &BallStyle,
+    ) {
+        let points = self.ordered();
+        let count = points.len();
+        if count < 2 {
+            return;
+        }
+        let amount = |i: usize| (i + 1) as f32 / count as f32;
+
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+        let sources = tessellate_polyline(
+            &points,
+            |i| radius * style.trail_width * amount(i),
+            &LineStyle::default(),
+            &mut vertices,
+            &mut indices,
+        );
+
+        let vertices: Vec<ColorVertex> = vertices
+            .iter()
+            .zip(&sources)
+            .map(|(v, &i)| ColorVertex {
+                position: v.position,
+                color: Color::new(
+                    color.r,
+                    color.g,
+                    color.b,
+                    color.a * style.trail_alpha * amount(i),
+                ),
+                edge: v.edge,
+            })
+            .collect();
+        let indices: Vec<u32> = indices.iter().flatten().copied().collect();
+        batch.push_triangles(&vertices, &indices);
+    }
+}
+
+/// Squashes along this much per unit of impact speed over `HARD_IMPACT_SPEED`.
+const SQUASH_PER_IMPACT: f32 = 0.1;
+const MAX_SQUASH: f32 = 0.35;
+/// Stretches along the direction of motion this much per world unit per tick.
+const STRETCH_PER_SPEED: f32 = 12.0;
+const MAX_STRETCH: f32 = 0.2;
+
+/// Springs the ball's shape back to round after an impact.
+#[derive(Debug, Clone, Copy)]
+pub struct Squash {
+    /// The impact's normal, the ball is squashed along it.
+    pub normal: Vector3,
+    /// 0.0 is round, positive is flatter against the line.
+    pub amount: f32,
+    velocity: f32,
+}
+
+impl Squash {
+    pub fn new() -> Self {
+        Self {
+            normal: Vector3::UP,
+            amount: 0.0,
+            velocity: 0.0,
+        }
+    }
+
+    pub fn hit(&mut self, impact: &Impact, strength: f32) {
+        let amount =
+            (impact.speed / HARD_IMPACT_SPEED * SQUASH_PER_IMPACT * strength).min(MAX_SQUASH);
+        if amount > self.amount {
+            self.normal = impact.normal;
+            self.amount = amount;
+            self.velocity = 0.0;
+        }
+    }
+
+    /// Call once per tick.
+    pub fn update(&mut self) {
+        self.velocity -= self.amount * 0.3;
+        self.velocity *= 0.7;
+        self.amount += self.velocity;
+        if self.amount.abs() < 0.001 && self.velocity.abs() < 0.001 {
+            self.amount = 0.0;
+            self.velocity = 0.0;
+        }
+    }
+
+    /// The ball's radii as vectors, for `ShapeBatch::push_ellipse`.
+    /// Squashed along the last impact's normal, otherwise stretched along `velocity`.
+    pub fn axes(&self, velocity: Vector3, radius: f32, style: &BallStyle) -> (Vector3, Vector3) {
+        let speed = velocity.length();
+        let stretch = (speed * STRETCH_PER_SPEED * style.squash).min(MAX_STRETCH);
+        let (axis, scale) = if self.amount.abs() > stretch {
+            (self.normal, 1.0 - self.amount)
+        } else if speed > 0.0 {
+            (velocity / speed, 1.0 + stretch)
+        } else {
+            (Vector3::UP, 1.0)
+        };
+
+        // Keeps the area the same so the ball doesn't look like it's growing.
+        let side = Vector3::new(-axis.y, axis.x, 0.0);
+        (axis * radius * scale, side * radius / scale)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn push_all(trail: &mut Trail, xs: impl IntoIterator<Item = i32>, length: usize) {
+        for x in xs {
+            trail.push(Vector3::new(x as f32, 0.0, 0.0), length);
+        }
+    }
+
+    fn xs(trail: &Trail) -> Vec<i32> {
+        trail.ordered().iter().map(|p| p.x as i32).collect()
+    }
+
+    #[test]
+    fn keeps_the_newest_positions_in_order() {
+        let mut trail = Trail::new();
+        push_all(&mut trail, 0..5, 3);
+        assert_eq!(xs(&trail), [2, 3, 4]);
+    }
+
+    #[test]
+    fn growing_a_wrapped_trail_keeps_it_in_order() {
+        let mut trail = Trail::new();
+        push_all(&mut trail, 0..5, 3);
+        push_all(&mut trail, 5..7, 4);
+        assert_eq!(xs(&trail), [3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn shrinking_a_trail_drops_the_oldest() {
+        let mut trail = Trail::new();
+        push_all(&mut trail, 0..5, 4);
+        push_all(&mut trail, 5..6, 2);
+        assert_eq!(xs(&trail), [4, 5]);
+        push_all(&mut trail, 6..7, 0);
+        assert!(trail.ordered().is_empty());
+    }
+}
@@ -0,0 +1,185 @@
+use crate::*;
+
+/// A vertex with its colour baked in so many shapes can share one draw call.
+/// `edge` is the distance to the shape's edge, the same as `MeshVertex::edge`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ColorVertex {
+    pub position: Vector3,
+    pub color: Color,
+    pub edge: f32,
+}
+
+impl Vertex for ColorVertex {
+    const LAYOUT: VertexLayout =
+        VertexLayout(&[Attribute::Position, Attribute::Color, Attribute::Edge]);
+}
+
+/// Edge distance for shapes that shouldn't be anti-aliased.
+pub const NO_EDGE: f32 = 1000.0;
+
+/// Accumulates pre-transformed shapes into a single dynamic buffer.
+/// Call `clear` at the start of a frame, push shapes, then `draw` once.
+pub struct ShapeBatch {
+    pub vertices: Vec<ColorVertex>,
+    pub indices: Vec<u32>,
+    // Created on first draw so batches can be filled without a GL context.
+    buffers: Option<(Buffer, Buffer)>,
+    // See `resources::generation`.
+    generation: u32,
+    // Allocated buffer sizes in bytes.
+    vertex_capacity: usize,
+    index_capacity: usize,
+    // Unit circle outline shared by every circle pushed into the batch.
+    circle: Vec<Vector3>,
+}
+
+impl ShapeBatch {
+    pub fn new(circle_resolution: u32) -> Self {
+        let increment = (crate::zmath::PI * 2.0) / circle_resolution as f32;
+        let circle = (0..circle_resolution)
+            .map(|i| {
+                let angle = increment * i as f32;
+                Vector3::new(angle.cos(), angle.sin(), 0.0)
+            })
+            .collect();
+
+        Self {
+            vertices: Vec::new(),
+            indices: Vec::new(),
+            buffers: None,
+            generation: 0,
+            vertex_capacity: 0,
+            index_capacity: 0,
+            circle,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.vertices.clear();
+        self.indices.clear();
+    }
+
+    pub fn push_circle(&mut self, center: Vector3, radius: f32, color: Color) {
+        self.push_ellipse(
+            center,
+            Vector3::new(radius, 0.0, 0.0),
+            Vector3::new(0.0, radius, 0.0),
+            color,
+        );
+    }
+
+    /// `x_axis` and `y_axis` are the ellipse's radii as vectors, they don't need to line up with the world's axes.
+    pub fn push_ellipse(
+        &mut self,
+        center: Vector3,
+        x_axis: Vector3,
+        y_axis: Vector3,
+        color: Color,
+    ) {
+        let start = self.vertices.len() as u32;
+        self.vertices.push(ColorVertex {
+            position: center,
+            color,
+            edge: x_axis.length().min(y_axis.length()),
+        });
+        for direction in &self.circle {
+            self.vertices.push(ColorVertex {
+                position: center + x_axis * direction.x + y_axis * direction.y,
+                color,
+                edge: 0.0,
+            });
+        }
+
+        let sides = self.circle.len() as u32;
+        for i in 0..sides {
+            self.indices
+                .extend_from_slice(&[start, start + 1 + i, start + 1 + (i + 1) % sides]);
+        }
+    }
+
+    /// Corners are expected in counter-clockwise order.
+    pub fn push_quad(&mut self, corners: [Vector3; 4], colors: [Color; 4]) {
+        let start = self.vertices.len() as u32;
+        for i in 0..4 {
+            self.vertices.push(ColorVertex {
+                position: corners[i],
+                color: colors[i],
+                edge: NO_EDGE,
+            });
+        }
+        self.indices
+            .extend_from_slice(&[start, start + 1, start + 2, start, start + 2, start + 3]);
+    }
+
+    pub fn push_triangles(&mut self, vertices: &[ColorVertex], indices: &[u32]) {
+        let start = self.vertices.len() as u32;
+        self.vertices.extend_from_slice(vertices);
+        self.indices.extend(indices.iter().map(|i| start + i));
+    }
+
+    /// Uploads everything pushed since the last `clear` and draws it.
+    /// Expects a shader with `position`, `color` and `edge` where `ShaderOptions::default` puts them.
+    /// Draws nothing, and logs why, if the vertices can't be indexed.
+    pub fn draw(&mut self, gl: &GL) {
+        if self.indices.is_empty() {
+            return;
+        }
+        let index_type = match index_type(self.vertices.len()) {
+            Ok(index_type) => index_type,
+            Err(error) => {
+                log!("{}", error);
+                return;
+            }
+        };
+
+        if self.generation != generation() {
+            // The buffers went with a lost context.
+            self.buffers = None;
+        }
+        unsafe {
+            if self.buffers.is_none() {
+                self.generation = generation();
+                self.vertex_capacity = 0;
+                self.index_capacity = 0;
+            }
+            let (vertex_buffer, index_buffer) = *self
+                .buffers
+                .get_or_insert_with(|| (gl.create_buffer().unwrap(), gl.create_buffer().unwrap()));
+
+            gl.bind_buffer(ARRAY_BUFFER, Some(vertex_buffer));
+            let vertex_bytes = slice_to_bytes(&self.vertices);
+            if vertex_bytes.len() > self.vertex_capacity {
+                // Grow to double what's needed so steady frames only use sub data uploads.
+                self.vertex_capacity = vertex_bytes.len() * 2;
+                gl.buffer_data_size(ARRAY_BUFFER, self.vertex_capacity as i32, DYNAMIC_DRAW);
+            }
+            gl.buffer_sub_data_u8_slice(ARRAY_BUFFER, 0, vertex_bytes);
+
+            gl.bind_buffer(ELEMENT_ARRAY_BUFFER, Some(index_buffer));
+            let index_bytes = index_bytes(&self.indices, index_type);
+            if index_bytes.len() > self.index_capacity {
+                self.index_capacity = index_bytes.len() * 2;
+                gl.buffer_data_size(
+                    ELEMENT_ARRAY_BUFFER,
+                    self.index_capacity as i32,
+                    DYNAMIC_DRAW,
+                );
+            }
+            gl.buffer_sub_data_u8_slice(ELEMENT_ARRAY_BUFFER, 0, &index_bytes);
+
+            ColorVertex::LAYOUT.enable(gl);
+            gl.draw_elements(TRIANGLES, self.indices.len() as i32, index_type, 0);
+            ColorVertex::LAYOUT.disable(gl);
+        }
+    }
+}
+
+impl Drop for ShapeBatch {
+    fn drop(&mut self) {
+        if let Some((vertex_buffer, index_buffer)) = self.buffers.take() {
+            free(self.generation, Resource::Buffer(vertex_buffer));
+            free(self.generation, Resource::Buffer(index_buffer));
+        }
+    }
+}
@@ -0,0 +1,480 @@
+use crate::*;
+
+/// How the designed area of the world is fit to a screen of a different shape.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScalingPolicy {
+    /// Shows exactly the designed area with bars on the sides or top and bottom.
+    Letterbox,
+    /// Shows the full designed height, more or less of the width depending on the screen.
+    FitHeight,
+    /// Shows the full designed width, more or less of the height depending on the screen.
+    FitWidth,
+}
+
+impl ScalingPolicy {
+    /// Parses `letterbox`, `fit_height` or `fit_width`.
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        match name.trim() {
+            "letterbox" => Ok(ScalingPolicy::Letterbox),
+            "fit_height" => Ok(ScalingPolicy::FitHeight),
+            "fit_width" => Ok(ScalingPolicy::FitWidth),
+            name => Err(format!(
+                "Unknown scaling {}, expected letterbox, fit_height or fit_width",
+                name
+            )),
+        }
+    }
+
+    /// The name `from_name` reads.
+    pub fn name(self) -> &'static str {
+        match self {
+            ScalingPolicy::Letterbox => "letterbox",
+            ScalingPolicy::FitHeight => "fit_height",
+            ScalingPolicy::FitWidth => "fit_width",
+        }
+    }
+}
+
+/// The scaling asked for with `?scaling=fit_height` after the page's address,
+/// or natively with the `SCALING` environment variable. `Letterbox` if neither is set.
+pub fn scaling_setting() -> ScalingPolicy {
+    match setting("scaling").as_deref().map(ScalingPolicy::from_name) {
+        Some(Ok(policy)) => policy,
+        Some(Err(error)) => {
+            log!("{}", error);
+            ScalingPolicy::Letterbox
+        }
+        None => ScalingPolicy::Letterbox,
+    }
+}
+
+/// The size of the world the levels are designed for, in world units.
+#[derive(Debug, Clone, Copy)]
+pub struct DesignResolution {
+    pub width: f32,
+    pub height: f32,
+    pub policy: ScalingPolicy,
+}
+
+impl DesignResolution {
+    pub fn new(width: f32, height: f32, policy: ScalingPolicy) -> Self {
+        Self {
+            width,
+            height,
+            policy,
+        }
+    }
+
+    /// Returns the part of the screen to draw to and the half width and height of the world shown in it.
+    pub fn fit(&self, screen_width: u32, screen_height: u32) -> (Viewport, f32, f32) {
+        let (screen_width, screen_height) = (screen_width.max(1), screen_height.max(1));
+        let screen_aspect = screen_width as f32 / screen_height as f32;
+        let design_aspect = self.width / self.height;
+        let full = Viewport::new(0, 0, screen_width, screen_height);
+
+        match self.policy {
+            ScalingPolicy::Letterbox => {
+                let viewport = if screen_aspect > design_aspect {
+                    // Bars on the sides
+                    let width = ((screen_height as f32 * design_aspect).round() as u32).max(1);
+                    Viewport::new((screen_width - width) / 2, 0, width, screen_height)
+                } else {
+                    // Bars on the top and bottom
+                    let height = ((screen_width as f32 / design_aspect).round() as u32).max(1);
+                    Viewport::new(0, (screen_height - height) / 2, screen_width, height)
+                };
+                (viewport, self.width * 0.5, self.height * 0.5)
+            }
+            ScalingPolicy::FitHeight => {
+                let half_height = self.height * 0.5;
+                (full, half_height * screen_aspect, half_height)
+            }
+            ScalingPolicy::FitWidth => {
+                let half_width = self.width * 0.5;
+                (full, half_width, half_width / screen_aspect)
+            }
+        }
+    }
+}
+
+impl Default for DesignResolution {
+    /// The 0 to 2 square every level is made for.
+    fn default() -> Self {
+        Self::new(2.0, 2.0, ScalingPolicy::Letterbox)
+    }
+}
+
+/// A rectangle of the screen in pixels, with y starting at the bottom like GL.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Viewport {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Viewport {
+    pub fn new(x: u32, y: u32, width: u32, height: u32) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+}
+
+pub struct Camera {
+    pub projection: Matrix4x4,
+    pub view: Matrix4x4,
+    pub inverse_projection: Matrix4x4,
+    pub inverse_view: Matrix4x4,
+    pub design: DesignResolution,
+    pub viewport: Viewport,
+    /// Half of the world's width and height the projection shows.
+    pub half_width: f32,
+    pub half_height: f32,
+}
+
+impl Camera {
+    pub fn new(projection: Matrix4x4, view: Matrix4x4) -> Self {
+        Self {
+            projection,
+            inverse_projection: projection.inverse(),
+            view,
+            inverse_view: view.inverse(),
+            design: DesignResolution::default(),
+            viewport: Viewport::new(0, 0, 1, 1),
+            half_width: 1.0,
+            half_height: 1.0,
+        }
+    }
+
+    /// The game's camera, where 0,0 is the bottom left and 2,2 is the upper right.
+    pub fn game(screen_width: u32, screen_height: u32) -> Self {
+        let mut camera = Camera::new(
+            mat4_orthographic(-1.0, 1.0, -1.0, 1.0, 0.0, 1.0),
+            Matrix4x4::IDENTITY,
+        );
+
+        // Camera shifts everything down and to the left to make 0,0 bottom left.
+        // 2.0, 2.0 is upper right
+        camera.set_view(mat4_from_trs(
+            Vector3::new(-1.0, -1.0, 0.0),
+            Quaternion::IDENTITY,
+            Vector3::new_uniform(1.0),
+        ));
+        camera.resize(screen_width, screen_height);
+        camera
+    }
+
+    pub fn resize(&mut self, screen_width: u32, screen_height: u32) {
+        let (viewport, half_width, half_height) = self.design.fit(screen_width, screen_height);
+        self.viewport = viewport;
+        self.half_width = half_width;
+        self.half_height = half_height;
+        self.set_projection(mat4_orthographic(
+            -half_width,
+            half_width,
+            -half_height,
+            half_height,
+            0.0,
+            1.0,
+        ));
+    }
+
+    pub fn set_projection(&mut self, projection: Matrix4x4) {
+        self.projection = projection;
+        self.inverse_projection = self.projection.inverse();
+    }
+
+    pub fn set_view(&mut self, view: Matrix4x4) {
+        self.view = view;
+        self.inverse_view = self.view.inverse();
+    }
+
+    /// How much of the world a single pixel covers vertically, including the view's zoom.
+    pub fn world_units_per_pixel(&self) -> f32 {
+        let pixel = 2.0 / self.viewport.height.max(1) as f32;
+        let to_world = |p| {
+            mat4_transform_point(
+                &self.inverse_view,
+                mat4_transform_point(&self.inverse_projection, p),
+            )
+        };
+        (to_world(Vector3::new(0.0, pixel, 0.0)) - to_world(Vector3::ZERO)).length()
+    }
+
+    /// The part of the world that's on screen.
+    pub fn visible_bounds(&self) -> Bounds {
+        let to_world = |x, y| {
+            let mut p = mat4_transform_point(
+                &self.inverse_view,
+                mat4_transform_point(&self.inverse_projection, Vector3::new(x, y, 0.0)),
+            );
+            p.z = 0.0;
+            p
+        };
+        Bounds::new(to_world(-1.0, -1.0), to_world(1.0, 1.0))
+    }
+}
+
+/// The part of the world a level takes up. The camera never shows past it
+/// unless the whole level fits on screen.
+#[derive(Debug, Clone, Copy)]
+pub struct Bounds {
+    pub min: Vector3,
+    pub max: Vector3,
+}
+
+impl Bounds {
+    /// Levels that don't declare bounds fill a single screen.
+    pub const SCREEN: Bounds = Bounds {
+        min: Vector3 {
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+        },
+        max: Vector3 {
+            x: 2.0,
+            y: 2.0,
+            z: 0.0,
+        },
+    };
+
+    pub fn new(min: Vector3, max: Vector3) -> Self {
+        Self { min, max }
+    }
+
+    pub fn center(&self) -> Vector3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn size(&self) -> Vector3 {
+        self.max - self.min
+    }
+
+    pub fn contains(&self, point: Vector3) -> bool {
+        point.x >= self.min.x
+            && point.x <= self.max.x
+            && point.y >= self.min.y
+            && point.y <= self.max.y
+    }
+
+    pub fn union(&self, other: &Bounds) -> Bounds {
+        Bounds::new(
+            Vector3::new(
+                self.min.x.min(other.min.x),
+                self.min.y.min(other.min.y),
+                0.0,
+            ),
+            Vector3::new(
+                self.max.x.max(other.max.x),
+                self.max.y.max(other.max.y),
+                0.0,
+            ),
+        )
+    }
+}
+
+const MIN_ZOOM: f32 = 0.25;
+
+/// Moves the game camera around levels bigger than the screen.
+/// Follows the ball while it's moving and otherwise stays where the player panned it.
+pub struct CameraController {
+    /// The world position at the center of the screen.
+    pub center: Vector3,
+    /// How much of the world is visible, 1.0 shows the design resolution.
+    pub zoom: f32,
+    /// How much of the remaining distance to the ball is covered each tick.
+    pub follow_speed: f32,
+    bounds: Bounds,
+}
+
+impl CameraController {
+    pub fn new(bounds: Bounds) -> Self {
+        Self {
+            center: bounds.center(),
+            zoom: 1.0,
+            follow_speed: 0.1,
+            bounds,
+        }
+    }
+
+    /// Call when a level is loaded.
+    pub fn reset(&mut self, bounds: Bounds) {
+        self.bounds = bounds;
+        self.zoom = 1.0;
+        self.center = bounds.center();
+    }
+
+    /// Eases towards `target` if there is one.
+    pub fn update(&mut self, target: Option<Vector3>) {
+        if let Some(target) = target {
+            self.center += (target - self.center) * self.follow_speed;
+        }
+    }
+
+    pub fn pan(&mut self, offset: Vector3) {
+        self.center += offset;
+    }
+
+    /// Zooms by `amount` around the center, larger numbers zoom out.
+    pub fn zoom_by(&mut self, amount: f32) {
+        self.zoom *= amount;
+    }
+
+    /// Clamps to the level's bounds and applies the view to `camera`.
+    /// Anything converting screen positions to the world should happen after this.
+    pub fn apply(&mut self, camera: &mut Camera) {
+        let size = self.bounds.size();
+        let max_zoom = (size.x * 0.5 / camera.half_width)
+            .max(size.y * 0.5 / camera.half_height)
+            .max(1.0);
+        self.zoom = self.zoom.max(MIN_ZOOM).min(max_zoom);
+
+        let half_width = camera.half_width * self.zoom;
+        let half_height = camera.half_height * self.zoom;
+        self.center.x = clamp_to_range(
+            self.center.x,
+            self.bounds.min.x,
+            self.bounds.max.x,
+            half_width,
+        );
+        self.center.y = clamp_to_range(
+            self.center.y,
+            self.bounds.min.y,
+            self.bounds.max.y,
+            half_height,
+        );
+        self.center.z = 0.0;
+
+        let scale = 1.0 / self.zoom;
+        camera.set_view(mat4_from_trs(
+            -self.center * scale,
+            Quaternion::IDENTITY,
+            Vector3::new_uniform(scale),
+        ));
+    }
+}
+
+/// Keeps a view of `half_extent` around `value` inside `min..max`,
+/// or centers it if the view is bigger than the range.
+fn clamp_to_range(value: f32, min: f32, max: f32, half_extent: f32) -> f32 {
+    if max - min <= half_extent * 2.0 {
+        (min + max) * 0.5
+    } else {
+        value.max(min + half_extent).min(max - half_extent)
+    }
+}
+
+/// `x` and `y` are in window pixels from the top left.
+/// Positions in the bars around a letterboxed viewport map to just outside the design area.
+pub fn screen_to_world(x: f32, y: f32, camera: &Camera, window_height: u32) -> Vector3 {
+    let viewport = camera.viewport;
+    let v = Vector3::new(
+        (x - viewport.x as f32) / viewport.width as f32,
+        (window_height as f32 - y - viewport.y as f32) / viewport.height as f32,
+        0.0,
+    ) * 2.0
+        - Vector3::new(1.0, 1.0, 0.0);
+
+    let mut p = mat4_transform_point(
+        &camera.inverse_view,
+        mat4_transform_point(&camera.inverse_projection, v),
+    );
+    p.z = 0.0;
+    p
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 0.0001
+    }
+
+    /// Where the middle of the screen ends up in the world.
+    fn screen_center(camera: &Camera) -> Vector3 {
+        screen_to_world(50.0, 50.0, camera, 100)
+    }
+
+    #[test]
+    fn apply_centers_the_view() {
+        let mut camera = Camera::game(100, 100);
+        let mut controller =
+            CameraController::new(Bounds::new(Vector3::ZERO, Vector3::new(8.0, 8.0, 0.0)));
+        controller.center = Vector3::new(3.0, 5.0, 0.0);
+        controller.apply(&mut camera);
+
+        let center = screen_center(&camera);
+        assert!(close(center.x, 3.0) && close(center.y, 5.0));
+        // The top of the screen is `zoom` above the center.
+        let top = screen_to_world(50.0, 0.0, &camera, 100);
+        assert!(close(top.y, 6.0));
+    }
+
+    #[test]
+    fn zoom_is_limited() {
+        let mut camera = Camera::game(100, 100);
+        let mut controller =
+            CameraController::new(Bounds::new(Vector3::ZERO, Vector3::new(8.0, 4.0, 0.0)));
+
+        controller.zoom_by(0.01);
+        controller.apply(&mut camera);
+        assert!(close(controller.zoom, MIN_ZOOM));
+
+        // Zooming out stops once the whole level fits, here its width.
+        controller.zoom_by(1000.0);
+        controller.apply(&mut camera);
+        assert!(close(controller.zoom, 4.0));
+    }
+
+    #[test]
+    fn world_units_per_pixel_follows_zoom() {
+        let mut camera = Camera::game(100, 100);
+        let before = camera.world_units_per_pixel();
+        let mut controller =
+            CameraController::new(Bounds::new(Vector3::ZERO, Vector3::new(8.0, 8.0, 0.0)));
+        controller.zoom = 2.0;
+        controller.apply(&mut camera);
+        assert!(close(camera.world_units_per_pixel(), before * 2.0));
+    }
+
+    #[test]
+    fn view_stays_within_bounds() {
+        let mut camera = Camera::game(100, 100);
+        let mut controller =
+            CameraController::new(Bounds::new(Vector3::ZERO, Vector3::new(8.0, 8.0, 0.0)));
+        controller.pan(Vector3::new(-10.0, 20.0, 0.0));
+        controller.apply(&mut camera);
+        assert!(close(controller.center.x, 1.0) && close(controller.center.y, 7.0));
+
+        // Levels smaller than the view are centered instead.
+        let mut small = CameraController::new(Bounds::SCREEN);
+        small.pan(Vector3::new(0.5, 0.5, 0.0));
+        small.zoom_by(1.5);
+        small.apply(&mut camera);
+        assert!(close(small.center.x, 1.0) && close(small.center.y, 1.0));
+    }
+
+    #[test]
+    fn follows_the_target_smoothly() {
+        let mut controller =
+            CameraController::new(Bounds::new(Vector3::ZERO, Vector3::new(8.0, 8.0, 0.0)));
+        controller.center = Vector3::ZERO;
+        let target = Vector3::new(1.0, 0.0, 0.0);
+
+        controller.update(Some(target));
+        assert!(close(controller.center.x, controller.follow_speed));
+        for _ in 0..100 {
+            controller.update(Some(target));
+        }
+        assert!(close(controller.center.x, 1.0));
+
+        // Without a target it stays put.
+        controller.update(None);
+        assert!(close(controller.center.x, 1.0));
+    }
+}
@@ -0,0 +1,387 @@
+use crate::*;
+use std::convert::TryFrom;
+
+#[cfg(target_arch = "wasm32")]
+mod capture_web {
+    use wasm_bindgen::prelude::*;
+
+    #[wasm_bindgen(module = "/src/helpers.js")]
+    extern "C" {
+        fn downloadBytes(path: &str, bytes: &[u8], mime: &str);
+    }
+
+    pub fn save_file(path: &str, bytes: &[u8], mime: &str) {
+        downloadBytes(path, bytes, mime);
+    }
+}
+#[cfg(target_arch = "wasm32")]
+pub use capture_web::*;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn save_file(path: &str, bytes: &[u8], _mime: &str) {
+    if let Err(e) = std::fs::write(path, bytes) {
+        log!("Could not save {}: {:?}", path, e);
+    }
+}
+
+/// An 8 bit RGBA image with rows from top to bottom.
+pub struct CapturedFrame {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl CapturedFrame {
+    pub fn to_png(&self) -> Vec<u8> {
+        encode_png(self.width, self.height, &self.pixels)
+    }
+}
+
+/// Reads back what has been drawn to the framebuffer so far this frame.
+pub fn read_framebuffer(gl: &GL, width: u32, height: u32) -> CapturedFrame {
+    let mut pixels = vec![0; (width * height * 4) as usize];
+    unsafe {
+        gl.read_pixels(
+            0,
+            0,
+            width as i32,
+            height as i32,
+            RGBA,
+            UNSIGNED_BYTE,
+            &mut pixels,
+        );
+    }
+
+    // GL's rows start at the bottom.
+    let row = (width * 4) as usize;
+    let mut flipped = Vec::with_capacity(pixels.len());
+    for y in (0..height as usize).rev() {
+        flipped.extend_from_slice(&pixels[y * row..(y + 1) * row]);
+    }
+    // The canvas is shown opaque regardless of what ended up in the alpha channel.
+    for alpha in flipped.iter_mut().skip(3).step_by(4) {
+        *alpha = 255;
+    }
+
+    CapturedFrame {
+        width,
+        height,
+        pixels: flipped,
+    }
+}
+
+impl From<&SoftwareRenderer> for CapturedFrame {
+    fn from(renderer: &SoftwareRenderer) -> Self {
+        CapturedFrame {
+            width: renderer.width,
+            height: renderer.height,
+            pixels: renderer.to_rgba8(),
+        }
+    }
+}
+
+/// Encodes frames into an animated GIF one at a time, so they don't all have to be kept.
+pub struct GifWriter {
+    encoder: gif::Encoder<Vec<u8>>,
+    width: u16,
+    height: u16,
+    delay: u16,
+}
+
+impl GifWriter {
+    /// `frame_step` is how many 60hz ticks each frame lasts.
+    /// GIFs can't be more than 65535 pixels wide or tall.
+    pub fn new(width: u32, height: u32, frame_step: u32) -> Result<Self, String> {
+        let too_big = || format!("A {}x{} frame is too big for a GIF", width, height);
+        let width = u16::try_from(width).map_err(|_| too_big())?;
+        let height = u16::try_from(height).map_err(|_| too_big())?;
+        let mut encoder =
+            gif::Encoder::new(Vec::new(), width, height, &[]).map_err(|e| e.to_string())?;
+        encoder
+            .set_repeat(gif::Repeat::Infinite)
+            .map_err(|e| e.to_string())?;
+        Ok(Self {
+            encoder,
+            width,
+            height,
+            // GIF delays are in hundredths of a second.
+            delay: ((frame_step as f32 * 100.0 / 60.0).round() as u16).max(2),
+        })
+    }
+
+    /// Frames have to be the size the GIF was made with.
+    pub fn write(&mut self, frame: &CapturedFrame) -> Result<(), String> {
+        if frame.width != self.width as u32 || frame.height != self.height as u32 {
+            return Err(format!(
+                "Frame is {}x{} but the GIF is {}x{}",
+                frame.width, frame.height, self.width, self.height
+            ));
+        }
+        let mut pixels = frame.pixels.clone();
+        let mut gif_frame = gif::Frame::from_rgba_speed(self.width, self.height, &mut pixels, 10);
+        gif_frame.delay = self.delay;
+        self.encoder
+            .write_frame(&gif_frame)
+            .map_err(|e| e.to_string())
+    }
+
+    pub fn finish(self) -> Result<Vec<u8>, String> {
+        self.encoder.into_inner().map_err(|e| e.to_string())
+    }
+}
+
+/// Records an animated GIF while `recording` is set.
+/// Frames are encoded as they're captured, so recordings can be as long as they like.
+pub struct Capture {
+    pub recording: bool,
+    /// Only every `frame_step`th frame is kept to keep the animation small.
+    pub frame_step: u32,
+    gif: Option<GifWriter>,
+    ticks: u32,
+}
+
+impl Capture {
+    pub fn new(frame_step: u32) -> Self {
+        Self {
+            recording: false,
+            frame_step: frame_step.max(1),
+            gif: None,
+            ticks: 0,
+        }
+    }
+
+    pub fn start(&mut self) {
+        self.gif = None;
+        self.ticks = 0;
+        self.recording = true;
+    }
+
+    /// Stops recording and returns the encoded GIF, if anything was recorded.
+    pub fn stop(&mut self) -> Option<Vec<u8>> {
+        self.recording = false;
+        match self.gif.take()?.finish() {
+            Ok(gif) => Some(gif),
+            Err(error) => {
+                log!("Could not finish GIF: {}", error);
+                None
+            }
+        }
+    }
+
+    /// Whether there's a GIF for `stop` to return, even if recording stopped by itself.
+    pub fn has_frames(&self) -> bool {
+        self.gif.is_some()
+    }
+
+    /// Call once per tick, `read` is only called for frames that are kept.
+    /// Recording stops if a frame can't be encoded, for example because the window was resized,
+    /// keeping what was recorded until then for `stop`.
+    pub fn tick(&mut self, read: impl FnOnce() -> CapturedFrame) {
+        if !self.recording {
+            return;
+        }
+        if self.ticks % self.frame_step == 0 {
+            let frame = read();
+            if self.gif.is_none() {
+                match GifWriter::new(frame.width, frame.height, self.frame_step) {
+                    Ok(gif) => self.gif = Some(gif),
+                    Err(error) => {
+                        log!("Could not start GIF: {}", error);
+                        self.recording = false;
+                        return;
+                    }
+                }
+            }
+            if let Some(Err(error)) = self.gif.as_mut().map(|gif| gif.write(&frame)) {
+                log!("Stopped recording: {}", error);
+                self.recording = false;
+            }
+        }
+        self.ticks += 1;
+    }
+}
+
+/// Plays a level's intro, draws the solution's strokes, launches the ball and renders
+/// every `frame_step`th tick into a GIF until the level is complete or `max_ticks` have passed.
+/// `solution` uses the same stroke format as level files, without the start position.
+pub fn capture_playthrough(
+    level_data: &str,
+    solution: Option<&str>,
+    width: u32,
+    height: u32,
+    frame_step: u32,
+    max_ticks: u32,
+) -> Result<Vec<u8>, String> {
+    let mut level = Level::new(Vector3::ZERO);
+    let mut lines = Lines::new();
+    let mut user_lines = Lines::new();
+    let mut particles = Particles::new(MAX_PARTICLES);
+    let mut mouse_playback = MousePlayback::new();
+    editor::load(&mut mouse_playback, &mut level, level_data)?;
+    let mut solution = solution.map(editor::parse_states).transpose()?;
+    lines.style.join = level.line_join;
+    user_lines.style.join = level.line_join;
+    mouse_playback.playing = true;
+    let mut ball = Ball::new(level.start_position);
+
+    let mut camera = Camera::game(width, height);
+    let mut camera_controller = CameraController::new(level.bounds);
+    let mut renderer = SoftwareRenderer::new(width, height);
+    #[cfg(not(target_arch = "wasm32"))]
+    renderer.load_decals(level_data)?;
+    let mut batch = ShapeBatch::new(30);
+    let mut capture = Capture::new(frame_step);
+    capture.start();
+
+    let mut launched = false;
+    let mut ticks_after_complete = 0;
+    for _ in 0..max_ticks {
+        let play_area = level.bounds.union(&camera.visible_bounds());
+        update_world(
+            &mut ball,
+            &mut level,
+            &mut mouse_playback,
+            &mut lines,
+            &mut user_lines,
+            &mut particles,
+            play_area,
+        );
+
+        if level.setup && !launched {
+            if let Some(solution) = solution.take() {
+                let mut solution_playback = MousePlayback::new();
+                solution_playback.state = solution;
+                solution_playback.play_until_end(&mut user_lines, &mut level);
+                user_lines.end_segment();
+            }
+            reset(&mut ball, &mut level, &mut particles);
+            ball.moving = true;
+            launched = true;
+        }
+
+        camera_controller.update(if ball.moving {
+            Some(ball.position)
+        } else {
+            None
+        });
+        camera_controller.apply(&mut camera);
+        let settings = FrameSettings::new(&camera, 1.0, camera.world_units_per_pixel());
+
+        capture.tick(|| {
+            draw_scene(
+                &mut renderer,
+                &settings,
+                &mut batch,
+                &level,
+                &level.theme,
+                &mut lines,
+                &mut user_lines,
+                &ball,
+                &particles,
+            );
+            CapturedFrame::from(&renderer)
+        });
+
+        // Linger for a second on the finished level.
+        if level.complete {
+            ticks_after_complete += 1;
+            if ticks_after_complete > 60 {
+                break;
+            }
+        }
+    }
+
+    capture
+        .stop()
+        .ok_or_else(|| "Nothing was captured".to_owned())
+}
+
+/// `capture <level file> <output .png or .gif> [solution file]`
+/// A PNG is a screenshot of the level once its intro has played.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn run_cli(args: &[String]) {
+    if args.len() < 2 {
+        log!("Usage: capture <level file> <output .png or .gif> [solution file]");
+        std::process::exit(1);
+    }
+    if let Err(error) = capture_to_file(&args[0], &args[1], args.get(2)) {
+        log!("{}", error);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn capture_to_file(level: &str, output: &str, solution: Option<&String>) -> Result<(), String> {
+    let read = |path: &str| {
+        std::fs::read_to_string(path).map_err(|e| format!("Could not read {}: {}", path, e))
+    };
+    let level_data = read(level)?;
+    let solution = solution.map(|path| read(path)).transpose()?;
+
+    let (width, height) = (480, 480);
+    let bytes = if output.ends_with(".gif") {
+        capture_playthrough(&level_data, solution.as_deref(), width, height, 4, 60 * 60)?
+    } else {
+        render_level(&level_data, width, height)?.to_png()
+    };
+    std::fs::write(output, &bytes).map_err(|e| format!("Could not write {}: {}", output, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(width: u32, height: u32) -> CapturedFrame {
+        CapturedFrame {
+            width,
+            height,
+            pixels: vec![255; (width * height * 4) as usize],
+        }
+    }
+
+    fn count_frames(gif: &[u8]) -> usize {
+        let mut decoder = gif::DecodeOptions::new().read_info(gif).unwrap();
+        let mut frames = 0;
+        while decoder.read_next_frame().unwrap().is_some() {
+            frames += 1;
+        }
+        frames
+    }
+
+    #[test]
+    fn empty_captures_have_no_gif() {
+        let mut capture = Capture::new(1);
+        capture.start();
+        assert_eq!(capture.stop(), None);
+    }
+
+    #[test]
+    fn keeps_every_frame_step_th_frame() {
+        let mut capture = Capture::new(2);
+        capture.start();
+        for _ in 0..5 {
+            capture.tick(|| frame(4, 4));
+        }
+        let gif = capture.stop().unwrap();
+        assert_eq!(count_frames(&gif), 3);
+    }
+
+    #[test]
+    fn resizing_stops_recording_but_keeps_the_gif() {
+        let mut capture = Capture::new(1);
+        capture.start();
+        capture.tick(|| frame(4, 4));
+        capture.tick(|| frame(8, 8));
+
+        assert!(!capture.recording);
+        assert!(capture.has_frames());
+        assert_eq!(count_frames(&capture.stop().unwrap()), 1);
+    }
+
+    #[test]
+    fn frames_too_big_for_a_gif_are_an_error() {
+        assert!(GifWriter::new(70_000, 4, 1).is_err());
+        assert!(GifWriter::new(4, 70_000, 1).is_err());
+        assert!(GifWriter::new(65_535, 4, 1).is_ok());
+    }
+}
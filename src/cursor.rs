@@ -0,0 +1,166 @@
+use crate::*;
+
+/// The first connected gamepad's left stick and buttons, read once per frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GamepadState {
+    pub stick: Vector2,
+    /// Held to draw.
+    pub pen: bool,
+    pub erase: bool,
+    pub launch: bool,
+}
+
+#[cfg(target_arch = "wasm32")]
+mod gamepad_web {
+    use super::*;
+    use wasm_bindgen::prelude::*;
+
+    #[wasm_bindgen(module = "/src/helpers.js")]
+    extern "C" {
+        fn pollGamepad() -> Box<[f32]>;
+    }
+
+    pub fn poll_gamepad() -> Option<GamepadState> {
+        // Stick x, stick y, then the A, B and start buttons.
+        let state = pollGamepad();
+        if state.len() < 5 {
+            return None;
+        }
+        Some(GamepadState {
+            stick: Vector2::new(state[0], state[1]),
+            pen: state[2] != 0.0,
+            erase: state[3] != 0.0,
+            launch: state[4] != 0.0,
+        })
+    }
+}
+#[cfg(target_arch = "wasm32")]
+pub use gamepad_web::*;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn poll_gamepad() -> Option<GamepadState> {
+    None
+}
+
+/// Sticks rest slightly off center.
+const STICK_DEAD_ZONE: f32 = 0.2;
+
+/// What happened to the cursor this frame that the game needs to act on.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CursorUpdate {
+    pub stroke_ended: bool,
+    pub launch: bool,
+}
+
+/// Draws without a mouse. Moved with the arrow keys or a gamepad stick,
+/// the pen is toggled from the keyboard or held down on a gamepad.
+pub struct Cursor {
+    /// In world units.
+    pub position: Vector3,
+    /// Shown and used instead of the mouse until the mouse moves again.
+    pub active: bool,
+    pub pen_down: bool,
+    pub erasing: bool,
+    /// World units moved per frame at full speed.
+    pub speed: f32,
+    gamepad_pen: bool,
+    gamepad_launch: bool,
+}
+
+impl Cursor {
+    pub fn new() -> Self {
+        Self {
+            position: Vector3::new(1.0, 1.0, 0.0),
+            active: false,
+            pen_down: false,
+            erasing: false,
+            speed: 0.012,
+            gamepad_pen: false,
+            gamepad_launch: false,
+        }
+    }
+
+    /// Hands control back to the mouse.
+    pub fn deactivate(&mut self) -> CursorUpdate {
+        let stroke_ended = self.pen_down;
+        self.active = false;
+        self.pen_down = false;
+        self.erasing = false;
+        CursorUpdate {
+            stroke_ended,
+            launch: false,
+        }
+    }
+
+    /// Call once per frame before `input.end_frame`. `area` is what's on screen.
+    pub fn update(
+        &mut self,
+        input: &Input,
+        gamepad: Option<&GamepadState>,
+        area: &Bounds,
+    ) -> CursorUpdate {
+        let mut update = CursorUpdate::default();
+
+        let mut direction = Vector3::ZERO;
+        if input.is_down(Action::CursorLeft) {
+            direction.x -= 1.0;
+        }
+        if input.is_down(Action::CursorRight) {
+            direction.x += 1.0;
+        }
+        if input.is_down(Action::CursorUp) {
+            direction.y += 1.0;
+        }
+        if input.is_down(Action::CursorDown) {
+            direction.y -= 1.0;
+        }
+        if direction.length() > 1.0 {
+            direction = direction.normal();
+        }
+
+        let mut used = direction.length() > 0.0
+            || input.was_pressed(Action::Pen)
+            || (input.is_down(Action::Erase) && self.active);
+
+        if let Some(gamepad) = gamepad {
+            let stick = Vector3::new(gamepad.stick.x, -gamepad.stick.y, 0.0);
+            if stick.length() > STICK_DEAD_ZONE {
+                direction += stick;
+                used = true;
+            }
+
+            // The gamepad's pen is held rather than toggled.
+            if gamepad.pen != self.gamepad_pen {
+                self.gamepad_pen = gamepad.pen;
+                if self.pen_down && !gamepad.pen {
+                    update.stroke_ended = true;
+                }
+                self.pen_down = gamepad.pen;
+                used = true;
+            }
+            if gamepad.launch && !self.gamepad_launch {
+                update.launch = true;
+            }
+            self.gamepad_launch = gamepad.launch;
+            used |= gamepad.erase;
+        }
+
+        if !used && !self.active {
+            return update;
+        }
+        self.active = true;
+
+        if input.was_pressed(Action::Pen) {
+            if self.pen_down {
+                update.stroke_ended = true;
+            }
+            self.pen_down = !self.pen_down;
+        }
+        self.erasing = input.is_down(Action::Erase) || gamepad.map(|g| g.erase).unwrap_or(false);
+
+        self.position += direction * self.speed;
+        self.position.x = self.position.x.max(area.min.x).min(area.max.x);
+        self.position.y = self.position.y.max(area.min.y).min(area.max.y);
+        update
+    }
+}
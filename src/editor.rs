@@ -1,9 +1,7 @@
 use crate::*;
+use std::ops::Range;
 
 pub struct Editor {
-    left_mouse_down: bool,
-    right_mouse_down: bool,
-    mouse_position: (f32, f32),
     dragging_start: bool,
     pub active: bool,
 }
@@ -11,70 +9,35 @@ pub struct Editor {
 impl Editor {
     pub fn new() -> Self {
         Self {
-            left_mouse_down: false,
-            right_mouse_down: false,
-            mouse_position: (0., 0.),
             active: false,
             dragging_start: false,
         }
     }
 
+    /// `change` is what `input` made of `event`.
     pub fn update(
         &mut self,
         event: Event,
+        change: Option<InputChange>,
+        input: &Input,
         mouse_playback: &mut MousePlayback,
         level: &mut Level,
         level_lines: &mut Lines,
-        user_lines: &mut Lines,
         camera: &Camera,
-        screen_width: u32,
         screen_height: u32,
     ) {
         mouse_playback.recording = true;
-        //  user_lines.clear();
+        if change == Some(InputChange::Released(Action::Draw)) {
+            end_stroke(mouse_playback, level_lines);
+        }
+        let mouse_position = input.mouse_position;
         match event {
-            Event::MouseMoved { x, y, .. } => {
-                if self.left_mouse_down && !self.dragging_start {
+            Event::MouseMoved { .. } => {
+                if input.is_down(Action::Draw) && !self.dragging_start {
                     let mouse_position =
-                        screen_to_world(x, y, &camera, screen_width, screen_height);
-                    mouse_playback.record_mouse(Vector2::new(mouse_position.x, mouse_position.y));
-                    level_lines.add_segment(mouse_position);
+                        screen_to_world(mouse_position.x, mouse_position.y, &camera, screen_height);
+                    draw_to(mouse_position, mouse_playback, level_lines);
                 }
-                self.mouse_position = (x, y);
-            }
-            Event::MouseButtonDown {
-                button: MouseButton::Right,
-                x,
-                y,
-                ..
-            } => {
-                self.right_mouse_down = true;
-            }
-            Event::MouseButtonUp {
-                button: MouseButton::Right,
-                x,
-                y,
-                ..
-            } => {
-                self.right_mouse_down = false;
-            }
-            Event::MouseButtonDown {
-                button: MouseButton::Left,
-                x,
-                y,
-                ..
-            } => {
-                self.left_mouse_down = true;
-            }
-            Event::MouseButtonUp {
-                button: MouseButton::Left,
-                x,
-                y,
-                ..
-            } => {
-                self.left_mouse_down = false;
-                level_lines.end_segment();
-                mouse_playback.record_mouse_up();
             }
             Event::KeyDown { key: Key::R, .. } => {
                 level_lines.clear();
@@ -109,19 +72,14 @@ impl Editor {
             }
             Event::KeyDown { key: Key::C, .. } => {
                 // Add a collectible to the world.
-                let mouse_pos = screen_to_world(
-                    self.mouse_position.0,
-                    self.mouse_position.1,
-                    &camera,
-                    screen_width,
-                    screen_height,
-                );
+                let mouse_pos =
+                    screen_to_world(mouse_position.x, mouse_position.y, &camera, screen_height);
                 if mouse_playback.recording {
                     mouse_playback.record_collectible(Vector2::new(mouse_pos.x, mouse_pos.y));
 
                     level.collectibles.push(Collectible {
                         position: mouse_pos,
-                        color: Color::new(1.0, 1.0, 1.0, 1.0),
+                        color: level.theme.collectible_color,
                         radius: 0.015,
                         collected: false,
                         alpha: 1.0,
@@ -132,14 +90,9 @@ impl Editor {
                 save(&mouse_playback, &level);
             }
             Event::Draw { .. } => {
-                if self.left_mouse_down {
-                    let mouse_pos = screen_to_world(
-                        self.mouse_position.0,
-                        self.mouse_position.1,
-                        &camera,
-                        screen_width,
-                        screen_height,
-                    );
+                if input.is_down(Action::Draw) {
+                    let mouse_pos =
+                        screen_to_world(mouse_position.x, mouse_position.y, &camera, screen_height);
                     if (mouse_pos - level.start_position).length() < 0.05 {
                         self.dragging_start = true;
                         log!("DRAGGING START");
@@ -147,17 +100,12 @@ impl Editor {
                 }
 
                 if self.dragging_start {
-                    let mouse_pos = screen_to_world(
-                        self.mouse_position.0,
-                        self.mouse_position.1,
-                        &camera,
-                        screen_width,
-                        screen_height,
-                    );
+                    let mouse_pos =
+                        screen_to_world(mouse_position.x, mouse_position.y, &camera, screen_height);
                     level.start_position = mouse_pos;
                 }
 
-                if !self.left_mouse_down {
+                if !input.is_down(Action::Draw) {
                     self.dragging_start = false;
                 }
             }
@@ -166,12 +114,153 @@ impl Editor {
     }
 }
 
+impl Editor {
+    /// Lets touches and pens draw level lines like the mouse does.
+    /// Pressure isn't recorded, level lines are always the same width.
+    pub fn update_pointers(
+        &mut self,
+        pointer_input: &PointerInput,
+        stroke_ended: bool,
+        mouse_playback: &mut MousePlayback,
+        level_lines: &mut Lines,
+        camera: &Camera,
+        screen_height: u32,
+    ) {
+        if stroke_ended {
+            end_stroke(mouse_playback, level_lines);
+        }
+        if let Some((position, _)) = pointer_input.drawing() {
+            let position = screen_to_world(position.x, position.y, camera, screen_height);
+            draw_to(position, mouse_playback, level_lines);
+        }
+    }
+}
+
+fn draw_to(position: Vector3, mouse_playback: &mut MousePlayback, level_lines: &mut Lines) {
+    mouse_playback.record_mouse(Vector2::new(position.x, position.y));
+    level_lines.add_segment(position);
+}
+
+fn end_stroke(mouse_playback: &mut MousePlayback, level_lines: &mut Lines) {
+    level_lines.end_segment();
+    mouse_playback.record_mouse_up();
+}
+
 pub fn save(mouse_playback: &MousePlayback, level: &Level) {
     let mut string = String::new();
     string += &level.start_position.x.to_string();
     string += " ";
     string += &level.start_position.y.to_string();
     string += " ";
+    if level.line_join != LineJoin::Round {
+        string += "join ";
+        string += level.line_join.name();
+        string += " ";
+    }
+    // Levels that fit on one screen don't need to say so.
+    if level.bounds.min != Bounds::SCREEN.min || level.bounds.max != Bounds::SCREEN.max {
+        string += "bounds ";
+        for value in &[
+            level.bounds.min.x,
+            level.bounds.min.y,
+            level.bounds.max.x,
+            level.bounds.max.y,
+        ] {
+            string += &value.to_string();
+            string += " ";
+        }
+    }
+    let theme = &level.theme;
+    let default_theme = Theme::default();
+    let style = &theme.ball_style;
+    let default_style = &default_theme.ball_style;
+    if style.trail_length != default_style.trail_length
+        || style.trail_width != default_style.trail_width
+        || style.trail_alpha != default_style.trail_alpha
+    {
+        string += &format!(
+            "trail {} {} {} ",
+            style.trail_length, style.trail_width, style.trail_alpha
+        );
+    }
+    if style.squash != default_style.squash {
+        string += &format!("squash {} ", style.squash);
+    }
+    if theme.background_top != default_theme.background_top
+        || theme.background_bottom != default_theme.background_bottom
+    {
+        string += &format!(
+            "background {} {} ",
+            color_to_hex(theme.background_top),
+            color_to_hex(theme.background_bottom)
+        );
+    }
+    for (name, color, default) in &[
+        ("lines", theme.line_color, default_theme.line_color),
+        (
+            "user_lines",
+            theme.user_line_color,
+            default_theme.user_line_color,
+        ),
+        ("ball", theme.ball_color, default_theme.ball_color),
+        (
+            "collectibles",
+            theme.collectible_color,
+            default_theme.collectible_color,
+        ),
+    ] {
+        if color != default {
+            string += &format!("{} {} ", name, color_to_hex(*color));
+        }
+    }
+    for decal in &level.background.decals {
+        string += &format!(
+            "decal {} {} {} {} {} {} ",
+            decal.image, decal.center.x, decal.center.y, decal.size.x, decal.size.y, decal.parallax
+        );
+        if decal.parameters.mag_filter == Filter::Nearest {
+            string += "nearest ";
+        }
+        let wrap = match decal.parameters.wrap {
+            Wrap::Clamp => None,
+            Wrap::Repeat => Some("repeat"),
+            Wrap::Mirror => Some("mirror"),
+        };
+        if let Some(wrap) = wrap {
+            string += &format!("{} {} {} ", wrap, decal.repeat.x, decal.repeat.y);
+        }
+    }
+    if level.background.star_count > 0 {
+        string += &format!("stars {} ", level.background.star_count);
+    }
+    for silhouette in &level.background.silhouettes {
+        string += &format!(
+            "silhouette {} {} {} ",
+            silhouette.parallax,
+            color_to_hex(silhouette.color),
+            silhouette.points.len()
+        );
+        for point in &silhouette.points {
+            string += &format!("{} {} ", point.x, point.y);
+        }
+    }
+    let script = &level.script;
+    if script.ordered {
+        string += "ordered ";
+    }
+    for trigger in &script.triggers {
+        let bounds = &trigger.bounds;
+        string += &format!(
+            "trigger {} {} {} {} {} ",
+            trigger.name, bounds.min.x, bounds.min.y, bounds.max.x, bounds.max.y
+        );
+    }
+    for strokes in &script.hidden {
+        string += &format!("hide {} {} ", strokes.start, strokes.len());
+    }
+    for rule in &script.rules {
+        string += &format!("on {} {} ", rule.event, rule.action);
+    }
     for s in &mouse_playback.state {
         if s.mouse_up {
             string += "a"; // a is mouseup
@@ -195,24 +284,372 @@ pub fn save(mouse_playback: &MousePlayback, level: &Level) {
     download("level.txt", &string);
 }
 
-pub fn load(mouse_playback: &mut MousePlayback, level: &mut Level, s: &str) {
-    mouse_playback.state.clear();
+fn next<'a>(s: &mut impl Iterator<Item = &'a str>, what: &str) -> Result<&'a str, String> {
+    s.next()
+        .ok_or_else(|| format!("Level ended before its {}", what))
+}
+
+fn number<'a, T: std::str::FromStr>(
+    s: &mut impl Iterator<Item = &'a str>,
+    what: &str,
+) -> Result<T, String> {
+    let word = next(s, what)?;
+    word.parse()
+        .map_err(|_| format!("Invalid {} {:?}, expected a number", what, word))
+}
+
+/// Reads a decal after its `decal` keyword:
+/// `file x y width height parallax`, optionally followed by `nearest`
+/// and then `repeat columns rows` or `mirror columns rows`.
+fn parse_decal<'a>(
+    s: &mut std::iter::Peekable<impl Iterator<Item = &'a str>>,
+) -> Result<Decal, String> {
+    let image = next(s, "decal image")?.to_owned();
+    let center = Vector3::new(number(s, "decal")?, number(s, "decal")?, 0.);
+    let size = Vector2::new(number(s, "decal")?, number(s, "decal")?);
+    let parallax: f32 = number(s, "decal parallax")?;
+    if size.x <= 0.0 || size.y <= 0.0 {
+        return Err(format!("Decal {} must have a positive size", image));
+    }
+    if parallax < 0.0 || parallax > 1.0 {
+        return Err("Decal parallax must be from 0 to 1".to_owned());
+    }
+
+    // Sharp pixels for pixel art, and images that tile across the decal.
+    let mut parameters = TextureParameters::default();
+    if s.peek() == Some(&"nearest") {
+        s.next();
+        parameters.min_filter = Filter::Nearest;
+        parameters.mag_filter = Filter::Nearest;
+    }
+    let mut repeat = Vector2::new(1.0, 1.0);
+    let wrap = match s.peek() {
+        Some(&"repeat") => Some(Wrap::Repeat),
+        Some(&"mirror") => Some(Wrap::Mirror),
+        _ => None,
+    };
+    if let Some(wrap) = wrap {
+        s.next();
+        parameters.wrap = wrap;
+        repeat = Vector2::new(number(s, "decal repeat")?, number(s, "decal repeat")?);
+        if repeat.x <= 0.0 || repeat.y <= 0.0 {
+            return Err(format!("Decal {} must repeat a positive amount", image));
+        }
+    }
+
+    Ok(Decal {
+        image,
+        center,
+        size,
+        parallax,
+        parameters,
+        repeat,
+    })
+}
+
+/// Strokes `first` to `first + count`, as long as that doesn't overflow.
+fn strokes(first: usize, count: usize) -> Result<Range<usize>, String> {
+    match first.checked_add(count) {
+        Some(end) => Ok(first..end),
+        None => Err(format!(
+            "{} strokes from stroke {} is too many",
+            count, first
+        )),
+    }
+}
+
+/// An action of a script rule, like `sound file rate gain`.
+fn action<'a>(s: &mut impl Iterator<Item = &'a str>) -> Result<ScriptAction, String> {
+    match next(s, "action")? {
+        "sound" => {
+            let file = next(s, "sound file")?.to_owned();
+            let rate: f64 = number(s, "sound rate")?;
+            let gain: f64 = number(s, "sound gain")?;
+            if rate <= 0.0 || gain < 0.0 {
+                return Err(format!("Sound {} must have a positive rate and gain", file));
+            }
+            Ok(ScriptAction::Sound { file, rate, gain })
+        }
+        "reveal" => {
+            let first = number(s, "first revealed stroke")?;
+            let count = number(s, "revealed stroke count")?;
+            strokes(first, count)?;
+            Ok(ScriptAction::Reveal { first, count })
+        }
+        "gravity" => Ok(ScriptAction::Gravity(Vector2::new(
+            number(s, "gravity")?,
+            number(s, "gravity")?,
+        ))),
+        "complete" => Ok(ScriptAction::Complete),
+        action => Err(format!("Unknown action {:?}", action)),
+    }
+}
+
+/// Reports a problem with the level instead of panicking.
+/// `level` and `mouse_playback` are only changed if the whole level could be read.
+pub fn load(mouse_playback: &mut MousePlayback, level: &mut Level, s: &str) -> Result<(), String> {
+    let star_seed = rng::hash(s.as_bytes());
     let mut s = s.split(" ").peekable();
 
-    level.start_position = Vector3::new(
-        s.next().unwrap().parse().unwrap(),
-        s.next().unwrap().parse().unwrap(),
+    let start_position = Vector3::new(
+        number(&mut s, "start position")?,
+        number(&mut s, "start position")?,
         0.,
     );
-    while let Some(_) = s.peek() {
-        let first = s.next().unwrap();
 
+    // Optionally followed by how the level's lines are joined, round if it isn't given.
+    let line_join = if s.peek() == Some(&"join") {
+        s.next();
+        let name = next(&mut s, "join")?;
+        LineJoin::from_name(name)
+            .ok_or_else(|| format!("Unknown join {:?}, expected round or miter", name))?
+    } else {
+        LineJoin::Round
+    };
+
+    // Optionally followed by the level's bounds, for levels bigger than a screen,
+    // how the ball's trail and squash look, the level's colours, its background and its script.
+    let mut bounds = Bounds::SCREEN;
+    let mut theme = Theme::default();
+    let mut background = Background::new();
+    let mut script = Script::new();
+    loop {
+        match s.peek() {
+            Some(&"bounds") => {
+                s.next();
+                let min = Vector3::new(number(&mut s, "bounds")?, number(&mut s, "bounds")?, 0.);
+                let max = Vector3::new(number(&mut s, "bounds")?, number(&mut s, "bounds")?, 0.);
+                if min.x >= max.x || min.y >= max.y {
+                    return Err(
+                        "Level bounds must have their minimum before their maximum".to_owned()
+                    );
+                }
+                bounds = Bounds::new(min, max);
+            }
+            Some(&"trail") => {
+                s.next();
+                theme.ball_style.trail_length = number(&mut s, "trail length")?;
+                theme.ball_style.trail_width = number(&mut s, "trail width")?;
+                theme.ball_style.trail_alpha = number(&mut s, "trail alpha")?;
+                if theme.ball_style.trail_width < 0.0
+                    || theme.ball_style.trail_alpha < 0.0
+                    || theme.ball_style.trail_alpha > 1.0
+                {
+                    return Err(
+                        "Trail width can't be negative and alpha must be from 0 to 1".to_owned(),
+                    );
+                }
+            }
+            Some(&"squash") => {
+                s.next();
+                theme.ball_style.squash = number(&mut s, "squash")?;
+                if theme.ball_style.squash < 0.0 {
+                    return Err("Squash can't be negative".to_owned());
+                }
+            }
+            Some(&"background") => {
+                s.next();
+                theme.background_top = parse_color(next(&mut s, "background")?)?;
+                theme.background_bottom = parse_color(next(&mut s, "background")?)?;
+            }
+            Some(&"lines") => {
+                s.next();
+                theme.line_color = parse_color(next(&mut s, "line colour")?)?;
+            }
+            Some(&"user_lines") => {
+                s.next();
+                theme.user_line_color = parse_color(next(&mut s, "user line colour")?)?;
+            }
+            Some(&"ball") => {
+                s.next();
+                theme.ball_color = parse_color(next(&mut s, "ball colour")?)?;
+            }
+            Some(&"collectibles") => {
+                s.next();
+                theme.collectible_color = parse_color(next(&mut s, "collectible colour")?)?;
+            }
+            Some(&"decal") => {
+                s.next();
+                background.decals.push(parse_decal(&mut s)?);
+            }
+            Some(&"stars") => {
+                s.next();
+                background.star_count = number(&mut s, "star count")?;
+                if background.star_count > MAX_STARS {
+                    return Err(format!("A level can have at most {} stars", MAX_STARS));
+                }
+            }
+            Some(&"silhouette") => {
+                s.next();
+                let parallax: f32 = number(&mut s, "silhouette parallax")?;
+                if parallax < 0.0 || parallax > 1.0 {
+                    return Err("Silhouette parallax must be from 0 to 1".to_owned());
+                }
+                let color = parse_color(next(&mut s, "silhouette colour")?)?;
+                let count: usize = number(&mut s, "silhouette point count")?;
+                if count > MAX_SILHOUETTE_POINTS {
+                    return Err(format!(
+                        "A silhouette can have at most {} points",
+                        MAX_SILHOUETTE_POINTS
+                    ));
+                }
+                let mut points = Vec::new();
+                for _ in 0..count {
+                    let x = number(&mut s, "silhouette point")?;
+                    let y = number(&mut s, "silhouette point")?;
+                    points.push(Vector3::new(x, y, 0.));
+                }
+                background.silhouettes.push(Silhouette {
+                    points,
+                    color,
+                    parallax,
+                });
+            }
+            Some(&"ordered") => {
+                s.next();
+                script.ordered = true;
+            }
+            Some(&"trigger") => {
+                s.next();
+                let name = next(&mut s, "trigger name")?;
+                let min = Vector3::new(number(&mut s, "trigger")?, number(&mut s, "trigger")?, 0.);
+                let max = Vector3::new(number(&mut s, "trigger")?, number(&mut s, "trigger")?, 0.);
+                if min.x >= max.x || min.y >= max.y {
+                    return Err(format!(
+                        "Trigger {} must have its minimum before its maximum",
+                        name
+                    ));
+                }
+                script
+                    .triggers
+                    .push(Trigger::new(name, Bounds::new(min, max)));
+            }
+            Some(&"hide") => {
+                s.next();
+                let first: usize = number(&mut s, "first hidden stroke")?;
+                let count: usize = number(&mut s, "hidden stroke count")?;
+                script.hidden.push(strokes(first, count)?);
+            }
+            Some(&"on") => {
+                s.next();
+                let event = match next(&mut s, "event")? {
+                    "collect" => ScriptEvent::Collect(number(&mut s, "collectible")?),
+                    "enter" => ScriptEvent::Enter(next(&mut s, "trigger name")?.to_owned()),
+                    event => return Err(format!("Unknown event {:?}", event)),
+                };
+                let action = action(&mut s)?;
+                script.rules.push(Rule { event, action });
+            }
+            _ => break,
+        }
+    }
+    background.place_stars(&bounds, star_seed);
+    let states = parse_states_from(s)?;
+
+    // Collectibles are placed during playback, so rules can only be checked against them now.
+    let collectibles = states
+        .iter()
+        .filter(|state| state.collectible_place)
+        .count();
+    // Like playback, strokes are counted by mouse ups, with the last one possibly never released.
+    let drawn_strokes = states.iter().filter(|state| state.mouse_up).count()
+        + match states.iter().rev().find(|state| !state.collectible_place) {
+            Some(state) if !state.mouse_up => 1,
+            _ => 0,
+        };
+    let check_strokes = |strokes: &Range<usize>| {
+        if strokes.end > drawn_strokes {
+            Err(format!(
+                "Strokes {} to {} don't exist, the level has {}",
+                strokes.start,
+                strokes.end - 1,
+                drawn_strokes
+            ))
+        } else {
+            Ok(())
+        }
+    };
+    for strokes in &script.hidden {
+        check_strokes(strokes)?;
+    }
+    for rule in &script.rules {
+        if let ScriptAction::Reveal { first, count } = rule.action {
+            check_strokes(&(first..first + count))?;
+        }
+        match &rule.event {
+            ScriptEvent::Collect(index) if *index >= collectibles => {
+                return Err(format!(
+                    "Collectible {} doesn't exist, the level has {}",
+                    index, collectibles
+                ));
+            }
+            ScriptEvent::Enter(name) if !script.triggers.iter().any(|t| t.name == *name) => {
+                return Err(format!("There's no trigger named {}", name));
+            }
+            _ => {}
+        }
+    }
+
+    level.start_position = start_position;
+    level.line_join = line_join;
+    level.bounds = bounds;
+    level.theme = theme;
+    level.background = background;
+    level.script = script;
+    mouse_playback.state = states;
+    Ok(())
+}
+
+/// The images a level's decals use and how they're sampled, so they can be loaded before the level is.
+/// Doesn't check the rest of the level, `load` does that.
+pub fn decal_images(s: &str) -> Vec<(String, TextureParameters)> {
+    let mut words = s.split(" ").peekable();
+    let mut images = Vec::new();
+    while let Some(word) = words.next() {
+        if word == "decal" {
+            if let Ok(decal) = parse_decal(&mut words) {
+                images.push((decal.image, decal.parameters));
+            }
+        }
+    }
+    images
+}
+
+/// The sounds a level's script plays, so they can be loaded before the level is.
+pub fn script_sounds(s: &str) -> Vec<String> {
+    let mut words = s.split(" ");
+    let mut sounds = Vec::new();
+    while let Some(word) = words.next() {
+        if word == "sound" {
+            if let Some(file) = words.next() {
+                sounds.push(file.to_owned());
+            }
+        }
+    }
+    sounds
+}
+
+/// Parses recorded mouse states without a leading start position.
+pub fn parse_states(s: &str) -> Result<Vec<MouseState>, String> {
+    parse_states_from(s.split(" "))
+}
+
+fn parse_states_from<'a>(s: impl Iterator<Item = &'a str>) -> Result<Vec<MouseState>, String> {
+    fn value<T: std::str::FromStr>(word: Option<&str>) -> Result<T, String> {
+        let word = word.ok_or("Drawing ended partway through a point")?;
+        word.parse()
+            .map_err(|_| format!("Invalid drawing value {:?}, expected a number", word))
+    }
+
+    let mut s = s.peekable();
+    let mut state = Vec::new();
+    while let Some(first) = s.next() {
         match first {
             "a" => {
-                let frame = s.next().unwrap().parse().unwrap();
+                let frame = value(s.next())?;
 
                 // Mouse up
-                mouse_playback.state.push(MouseState {
+                state.push(MouseState {
                     position: Vector2::new(0.0, 0.0),
                     frame,
                     mouse_up: true,
@@ -221,11 +658,11 @@ pub fn load(mouse_playback: &mut MousePlayback, level: &mut Level, s: &str) {
             }
             "b" => {
                 // Collectible
-                let x = s.next().unwrap().parse().unwrap();
-                let y = s.next().unwrap().parse().unwrap();
-                let frame = s.next().unwrap().parse().unwrap();
+                let x = value(s.next())?;
+                let y = value(s.next())?;
+                let frame = value(s.next())?;
 
-                mouse_playback.state.push(MouseState {
+                state.push(MouseState {
                     position: Vector2::new(x, y),
                     frame,
                     mouse_up: false,
@@ -234,10 +671,10 @@ pub fn load(mouse_playback: &mut MousePlayback, level: &mut Level, s: &str) {
             }
             "" => {}
             _ => {
-                let x = first.parse().unwrap();
-                let y = s.next().unwrap().parse().unwrap();
-                let frame = s.next().unwrap().parse().unwrap();
-                mouse_playback.state.push(MouseState {
+                let x = value(Some(first))?;
+                let y = value(s.next())?;
+                let frame = value(s.next())?;
+                state.push(MouseState {
                     position: Vector2::new(x, y),
                     frame,
                     mouse_up: false,
@@ -246,4 +683,130 @@ pub fn load(mouse_playback: &mut MousePlayback, level: &mut Level, s: &str) {
             }
         }
     }
+    Ok(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bundled_levels_load() {
+        for (i, data) in LEVELS.iter().enumerate() {
+            let mut level = Level::new(Vector3::ZERO);
+            let mut mouse_playback = MousePlayback::new();
+            if let Err(error) = load(&mut mouse_playback, &mut level, data) {
+                panic!("Level {}: {}", i, error);
+            }
+        }
+    }
+
+    #[test]
+    fn failed_loads_change_nothing() {
+        let mut level = Level::new(Vector3::ZERO);
+        let mut mouse_playback = MousePlayback::new();
+        load(
+            &mut mouse_playback,
+            &mut level,
+            "0.5 0.5 join miter 0.1 0.2 3 a 4 ",
+        )
+        .unwrap();
+
+        let bad = [
+            "0.7 0.7 join bevel ",
+            "0.7 0.7 bounds 0 0 4 4 lines #12 ",
+            "0.7 0.7 bounds 0 0 4 4 0.1 0.2 ",
+            "0.7 0.7 bounds 0 0 4 4 0.1 x 3 ",
+        ];
+        for data in &bad {
+            assert!(load(&mut mouse_playback, &mut level, data).is_err());
+            assert_eq!(level.start_position.x, 0.5);
+            assert_eq!(level.line_join, LineJoin::Miter);
+            assert_eq!(level.bounds.max.x, Bounds::SCREEN.max.x);
+            assert_eq!(mouse_playback.state.len(), 2);
+        }
+    }
+
+    #[test]
+    fn decals_choose_how_they_are_sampled() {
+        let mut level = Level::new(Vector3::ZERO);
+        let data = "0.5 0.5 decal a.png 1 1 2 2 1 decal b.png 1 1 2 2 1 nearest mirror 4 2 ";
+        load(&mut MousePlayback::new(), &mut level, data).unwrap();
+
+        let decals = &level.background.decals;
+        assert_eq!(decals[0].parameters, TextureParameters::default());
+        assert_eq!(decals[0].repeat, Vector2::new(1.0, 1.0));
+        assert_eq!(decals[1].parameters.mag_filter, Filter::Nearest);
+        assert_eq!(decals[1].parameters.wrap, Wrap::Mirror);
+        assert_eq!(decals[1].repeat, Vector2::new(4.0, 2.0));
+        assert_eq!(
+            decal_images(data),
+            vec![
+                ("a.png".to_owned(), decals[0].parameters),
+                ("b.png".to_owned(), decals[1].parameters),
+            ]
+        );
+
+        let bad = "0.5 0.5 decal a.png 1 1 2 2 1 repeat 0 1 ";
+        assert!(load(&mut MousePlayback::new(), &mut level, bad).is_err());
+    }
+
+    #[test]
+    fn huge_counts_are_errors() {
+        let mut level = Level::new(Vector3::ZERO);
+        let mut mouse_playback = MousePlayback::new();
+        let data = [
+            "0.5 0.5 stars 4000000000 ",
+            "0.5 0.5 silhouette 1 #000000 99999999999 ",
+        ];
+        for data in &data {
+            assert!(load(&mut mouse_playback, &mut level, data).is_err());
+        }
+    }
+
+    #[test]
+    fn strokes_must_exist() {
+        // Two strokes, the second never released.
+        let load_script = |script: &str| {
+            let data = format!(
+                "0.5 0.5 {}0.1 0.1 1 0.2 0.2 2 a 3 b 0.5 0.5 4 0.3 0.3 5 ",
+                script
+            );
+            load(
+                &mut MousePlayback::new(),
+                &mut Level::new(Vector3::ZERO),
+                &data,
+            )
+        };
+
+        load_script("hide 0 2 ").unwrap();
+        load_script("hide 1 1 on collect 0 reveal 0 2 ").unwrap();
+        let bad = [
+            "hide 1 2 ",
+            "hide 18446744073709551615 2 ",
+            "on collect 0 reveal 2 1 ",
+            "on collect 0 reveal 1 18446744073709551615 ",
+        ];
+        for script in &bad {
+            assert!(load_script(script).is_err(), "{}", script);
+        }
+    }
+
+    #[test]
+    fn stars_depend_only_on_the_level() {
+        let data = "0.5 0.5 stars 5 ";
+        let stars = || {
+            let mut level = Level::new(Vector3::ZERO);
+            load(&mut MousePlayback::new(), &mut level, data).unwrap();
+            level
+                .background
+                .stars
+                .iter()
+                .map(|star| (star.position.x, star.position.y))
+                .collect::<Vec<_>>()
+        };
+        let first = stars();
+        rng::with_stream(RngStream::Procedural, |rng| rng.next_u32());
+        assert_eq!(stars(), first);
+    }
 }
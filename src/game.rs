@@ -0,0 +1,319 @@
+use crate::*;
+
+/// What a tick did that's shown or heard but doesn't change the world.
+#[derive(Debug, Default, Clone)]
+pub struct TickOutcome {
+    /// The height of a collectible collected this tick.
+    pub collected: Option<f32>,
+    /// The level was completed and starts fading out.
+    pub completed: bool,
+    /// A level was loaded, by its index in the game's levels.
+    pub loaded_level: Option<u32>,
+    /// What the level's script did that the world doesn't handle itself, like playing sounds.
+    pub effects: Vec<Effect>,
+}
+
+/// The world and how the player's input changes it, without any drawing or sound.
+/// The game and replays of recorded sessions both go through it, so a replay plays out exactly the same.
+pub struct Game<'a> {
+    levels: &'a [&'a str],
+    pub current_level: u32,
+    pub input: Input,
+    pub cursor: Cursor,
+    pub pointer_input: PointerInput,
+    /// Edits made with the editor aren't recorded, only whether it's open.
+    pub editor: Editor,
+    pub mouse_playback: MousePlayback,
+    pub level: Level,
+    pub lines: Lines,
+    pub user_lines: Lines,
+    pub ball: Ball,
+    pub particles: Particles,
+    pub camera: Camera,
+    pub camera_controller: CameraController,
+    pub screen_height: u32,
+    /// Fades the whole scene out and in between levels.
+    pub level_alpha: f64,
+    fade_out: bool,
+    fade_in: bool,
+    /// The theme of the level being faded out of, blended into the new level's while fading in.
+    previous_theme: Theme,
+    /// Levels don't move on when they're completed while the editor has been used, to avoid losing work.
+    pub prevent_transition: bool,
+}
+
+impl<'a> Game<'a> {
+    /// Starts on the first of `levels`.
+    pub fn new(levels: &'a [&'a str], input: Input, scaling: ScalingPolicy) -> Self {
+        let level = Level::new(Vector3::ZERO);
+        let mut camera = Camera::game(1, 1);
+        camera.design.policy = scaling;
+        let mut game = Self {
+            levels,
+            current_level: 0,
+            input,
+            cursor: Cursor::new(),
+            pointer_input: PointerInput::new(),
+            editor: Editor::new(),
+            mouse_playback: MousePlayback::new(),
+            ball: Ball::new(level.start_position),
+            particles: Particles::new(MAX_PARTICLES),
+            camera_controller: CameraController::new(level.bounds),
+            level,
+            lines: Lines::new(),
+            user_lines: Lines::new(),
+            camera,
+            screen_height: 1,
+            level_alpha: 1.0,
+            fade_out: false,
+            fade_in: false,
+            previous_theme: Theme::default(),
+            prevent_transition: false,
+        };
+        game.load_level(0);
+        game
+    }
+
+    fn load_level(&mut self, index: u32) {
+        log!("CURRENT LEVEL {:?}", index);
+        // Bundled levels should always load, if one doesn't it's left empty rather than half loaded.
+        if let Err(error) = load_level_data(
+            &mut self.ball,
+            self.levels[index as usize],
+            &mut self.level,
+            &mut self.mouse_playback,
+            &mut self.lines,
+            &mut self.user_lines,
+            &mut self.particles,
+        ) {
+            log!("Could not load level {}: {}", index, error);
+        }
+        self.camera_controller.reset(self.level.bounds);
+    }
+
+    pub fn resize(&mut self, screen_width: u32, screen_height: u32) {
+        self.screen_height = screen_height;
+        self.camera.resize(screen_width, screen_height);
+        self.camera_controller.apply(&mut self.camera);
+    }
+
+    fn to_world(&self, position: Vector2) -> Vector3 {
+        screen_to_world(position.x, position.y, &self.camera, self.screen_height)
+    }
+
+    fn launch(&mut self) {
+        reset(&mut self.ball, &mut self.level, &mut self.particles);
+        self.ball.moving = true;
+    }
+
+    /// The level's theme, or a blend from the previous level's while fading in.
+    pub fn theme(&self) -> Theme {
+        if self.fade_in {
+            self.previous_theme
+                .lerp(&self.level.theme, self.level_alpha as f32)
+        } else {
+            self.level.theme
+        }
+    }
+
+    /// Call after `input` reports a change.
+    pub fn action_changed(&mut self, change: InputChange) {
+        match change {
+            InputChange::Pressed(Action::ToggleEditor) => {
+                self.editor.active = !self.editor.active;
+                if self.editor.active {
+                    self.prevent_transition = true;
+                }
+                log!("EDITOR ACTIVE: {:?}", self.editor.active);
+            }
+            InputChange::Released(Action::Draw) => self.user_lines.end_segment(),
+            InputChange::Pressed(Action::Undo) => {
+                if !self.editor.active {
+                    self.user_lines.remove_last_stroke();
+                }
+            }
+            InputChange::Pressed(Action::LaunchBall) => self.launch(),
+            InputChange::Pressed(Action::ZoomIn) => self.camera_controller.zoom_by(0.8),
+            InputChange::Pressed(Action::ZoomOut) => self.camera_controller.zoom_by(1.25),
+            _ => {}
+        }
+    }
+
+    /// Call after `input.mouse_position` moved from `previous`.
+    pub fn mouse_moved(&mut self, previous: Vector2) {
+        if self.cursor.active && self.cursor.deactivate().stroke_ended {
+            self.user_lines.end_segment();
+        }
+
+        // Dragging pans the camera.
+        if self.input.is_down(Action::Pan) {
+            let from = self.to_world(previous);
+            let to = self.to_world(self.input.mouse_position);
+            self.camera_controller.pan(from - to);
+            self.camera_controller.apply(&mut self.camera);
+        }
+
+        if self.input.is_down(Action::Draw) && !self.editor.active {
+            let position = self.to_world(self.input.mouse_position);
+            self.user_lines
+                .add_segment_with_radius(position, LINE_RADIUS);
+        }
+    }
+
+    /// Advances everything by one frame, with the touches and pens since the last tick
+    /// and the gamepad as it is now.
+    pub fn tick(
+        &mut self,
+        pointer_events: &[PointerEvent],
+        gamepad: Option<&GamepadState>,
+    ) -> TickOutcome {
+        let mut outcome = TickOutcome::default();
+
+        // Move the camera first so everything below converts the mouse with this frame's view.
+        let (previous_center, previous_zoom) =
+            (self.camera_controller.center, self.camera_controller.zoom);
+        self.camera_controller.update(if self.ball.moving {
+            Some(self.ball.position)
+        } else {
+            None
+        });
+        self.camera_controller.apply(&mut self.camera);
+
+        // The world moved under a held mouse, so keep the line under the cursor.
+        if self.input.is_down(Action::Draw)
+            && !self.editor.active
+            && ((self.camera_controller.center - previous_center).length() > 0.0001
+                || self.camera_controller.zoom != previous_zoom)
+        {
+            let position = self.to_world(self.input.mouse_position);
+            self.user_lines
+                .add_segment_with_radius(position, LINE_RADIUS);
+        }
+
+        // Touches and pens
+        for event in pointer_events {
+            self.pointer_input.handle_event(event);
+        }
+        self.pointer_input.tick();
+        let stroke_ended = self.pointer_input.take_stroke_ended();
+        if self.editor.active {
+            self.editor.update_pointers(
+                &self.pointer_input,
+                stroke_ended,
+                &mut self.mouse_playback,
+                &mut self.lines,
+                &self.camera,
+                self.screen_height,
+            );
+        } else {
+            if stroke_ended {
+                self.user_lines.end_segment();
+            }
+            if let Some((position, pressure)) = self.pointer_input.drawing() {
+                let position = self.to_world(position);
+                self.user_lines
+                    .add_segment_with_radius(position, pressure_radius(pressure));
+            }
+            let erasing: Vec<Vector2> = self.pointer_input.erasing().collect();
+            for position in erasing {
+                let position = self.to_world(position);
+                self.user_lines.erase(position, 0.06);
+            }
+        }
+
+        // The keyboard and gamepad cursor
+        let cursor_update = self
+            .cursor
+            .update(&self.input, gamepad, &self.camera.visible_bounds());
+        if self.cursor.active && !self.editor.active {
+            if cursor_update.stroke_ended {
+                self.user_lines.end_segment();
+            }
+            if self.cursor.pen_down {
+                self.user_lines
+                    .add_segment_with_radius(self.cursor.position, LINE_RADIUS);
+            }
+        }
+        if cursor_update.launch {
+            self.launch();
+        }
+
+        // Check if the ball placeholder is clicked, touched or drawn over with the cursor
+        let press_position = if self.cursor.active {
+            Some(self.cursor.position).filter(|_| self.cursor.pen_down)
+        } else if self.input.is_down(Action::Draw) {
+            Some(self.to_world(self.input.mouse_position))
+        } else {
+            self.pointer_input
+                .drawing()
+                .map(|(position, _)| self.to_world(position))
+        };
+        if let Some(press_position) =
+            press_position.filter(|_| self.level.setup && !self.editor.active)
+        {
+            if (press_position - self.level.start_position).length() < self.ball.radius {
+                self.launch();
+            }
+        }
+
+        // Eraser
+        if self.input.is_down(Action::Erase) || self.cursor.erasing {
+            let position = if self.cursor.active {
+                self.cursor.position
+            } else {
+                self.to_world(self.input.mouse_position)
+            };
+            self.user_lines.erase(position, 0.06);
+        }
+
+        let play_area = self.level.bounds.union(&self.camera.visible_bounds());
+        let (collected, effects) = update_world(
+            &mut self.ball,
+            &mut self.level,
+            &mut self.mouse_playback,
+            &mut self.lines,
+            &mut self.user_lines,
+            &mut self.particles,
+            play_area,
+        );
+        outcome.collected = collected;
+        outcome.effects = effects;
+
+        // Manage fade out
+        if self.fade_out && self.level_alpha < 0.0 {
+            self.previous_theme = self.level.theme;
+            reset_ball(&mut self.ball, &mut self.level);
+            self.fade_in = true;
+            self.fade_out = false;
+            self.level_alpha = 0.0;
+            // This is where the actual level transition happens, the last level repeats.
+            if self.current_level + 1 < self.levels.len() as u32 {
+                self.current_level += 1;
+            }
+            self.load_level(self.current_level);
+            outcome.loaded_level = Some(self.current_level);
+        }
+        if self.fade_out {
+            self.level_alpha -= 0.02;
+        }
+
+        if self.fade_in {
+            self.level_alpha += 0.02;
+            if self.level_alpha > 1.0 {
+                self.fade_in = false;
+                self.level_alpha = 1.0;
+            }
+        }
+
+        // Kick off level transition
+        if self.level.complete && !self.prevent_transition {
+            self.level.complete = false;
+            self.fade_out = true;
+            self.fade_in = false;
+            outcome.completed = true;
+        }
+
+        self.input.end_frame();
+        outcome
+    }
+}
@@ -1,22 +1,176 @@
+use crate::log;
 use glow::*;
 use kettlewin::*;
+use std::cell::Cell;
 
 pub type GL = Context;
 
-pub fn setup(window: &Window) -> (GLContext, Context) {
-    // Create a GLContext
-    #[cfg(target_arch = "wasm32")]
-    let mut gl_context = GLContext::new().webgl1().build().unwrap();
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AntiAliasing {
+    /// Shaders fade the last pixel of each shape using its distance to the edge.
+    /// Works everywhere, including WebGL1 without extensions.
+    Analytic,
+    /// Ask the context for multisampling, falling back to `Analytic` if it isn't available.
+    Msaa(u8),
+}
 
-    #[cfg(not(target_arch = "wasm32"))]
-    let mut gl_context = GLContext::new().build().unwrap();
+impl AntiAliasing {
+    /// Parses `analytic`, or `msaa` followed by the number of samples like `msaa4`.
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        let name = name.trim();
+        if name == "analytic" {
+            return Ok(AntiAliasing::Analytic);
+        }
+        if let Some(Ok(samples)) = name.strip_prefix("msaa").map(str::parse) {
+            if samples > 0 {
+                return Ok(AntiAliasing::Msaa(samples));
+            }
+        }
+        Err(format!(
+            "Unknown anti-aliasing {}, expected analytic or msaa followed by the samples",
+            name
+        ))
+    }
+}
 
-    // Assign the GLContext's window.
-    gl_context.set_window(Some(window)).unwrap();
+/// The anti-aliasing asked for with `?anti_aliasing=msaa4` after the page's address,
+/// or natively with the `ANTI_ALIASING` environment variable. `Analytic` if neither is set.
+pub fn anti_aliasing_setting() -> AntiAliasing {
+    let setting = crate::setting("anti_aliasing");
+    match setting.as_deref().map(AntiAliasing::from_name) {
+        Some(Ok(anti_aliasing)) => anti_aliasing,
+        Some(Err(error)) => {
+            log!("{}", error);
+            AntiAliasing::Analytic
+        }
+        None => AntiAliasing::Analytic,
+    }
+}
 
-    #[cfg(target_arch = "wasm32")]
+/// Which GL the context turned out to be, which decides the shading language too.
+// Native builds are always `Desktop`.
+#[cfg_attr(not(target_arch = "wasm32"), allow(dead_code))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GlVersion {
+    /// GLSL ES 1.00, the language shaders are written in.
+    WebGl1,
+    /// GLSL ES 3.00.
+    WebGl2,
+    /// GL 4.1 with GLSL 4.10.
+    Desktop,
+}
+
+/// What the context can do beyond WebGL1.
+/// WebGL1 gets some of these from extensions, WebGL2 and desktop GL always have them.
+#[derive(Debug, Clone, Copy)]
+pub struct Capabilities {
+    pub version: GlVersion,
+    /// 32 bit indices, see `index_type`.
+    pub uint_indices: bool,
+    /// Render targets that multisample and resolve into a texture.
+    pub msaa_renderbuffers: bool,
+    /// Render targets with 16 bit float channels, so bright colours aren't clipped before bloom.
+    pub float_render_targets: bool,
+}
+
+impl Capabilities {
+    fn detect(gl: &Context, version: GlVersion) -> Self {
+        let extensions = gl.supported_extensions();
+        let has = |extension: &str| extensions.contains(extension);
+        match version {
+            GlVersion::WebGl1 => Self {
+                version,
+                uint_indices: has("OES_element_index_uint"),
+                msaa_renderbuffers: false,
+                // Half floats need their own texture type in WebGL1, so aren't worth it.
+                float_render_targets: false,
+            },
+            GlVersion::WebGl2 => Self {
+                version,
+                uint_indices: true,
+                msaa_renderbuffers: true,
+                float_render_targets: has("EXT_color_buffer_float"),
+            },
+            GlVersion::Desktop => Self::desktop(),
+        }
+    }
+
+    fn desktop() -> Self {
+        Self {
+            version: GlVersion::Desktop,
+            uint_indices: true,
+            msaa_renderbuffers: true,
+            float_render_targets: true,
+        }
+    }
+
+    /// What can be assumed before a context is made.
+    fn baseline() -> Self {
+        if cfg!(target_arch = "wasm32") {
+            Self {
+                version: GlVersion::WebGl1,
+                uint_indices: false,
+                msaa_renderbuffers: false,
+                float_render_targets: false,
+            }
+        } else {
+            Self::desktop()
+        }
+    }
+}
+
+thread_local! {
+    static CAPABILITIES: Cell<Capabilities> = Cell::new(Capabilities::baseline());
+}
+
+/// Of the context made by `setup`.
+pub fn capabilities() -> Capabilities {
+    CAPABILITIES.with(|capabilities| capabilities.get())
+}
+
+/// WebGL2 where the browser has it, otherwise WebGL1.
+#[cfg(target_arch = "wasm32")]
+fn create_context(window: &Window, samples: u8) -> (GLContext, Context, GlVersion) {
+    if let Ok(mut gl_context) = GLContext::new().webgl2().samples(samples).build() {
+        if gl_context.set_window(Some(window)).is_ok() {
+            if let Some(context) = gl_context.webgl2_context() {
+                let gl = glow::Context::from_webgl2_context(context);
+                return (gl_context, gl, GlVersion::WebGl2);
+            }
+        }
+    }
+
+    let mut gl_context = GLContext::new().webgl1().samples(samples).build().unwrap();
+    gl_context.set_window(Some(window)).unwrap();
     let gl = glow::Context::from_webgl1_context(gl_context.webgl1_context().unwrap());
-    #[cfg(not(target_arch = "wasm32"))]
+    (gl_context, gl, GlVersion::WebGl1)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn create_context(window: &Window, samples: u8) -> (GLContext, Context, GlVersion) {
+    let mut gl_context = GLContext::new().samples(samples).build().unwrap();
+    gl_context.set_window(Some(window)).unwrap();
     let gl = glow::Context::from_loader_function(|s| gl_context.get_proc_address(s));
-    (gl_context, gl)
+    (gl_context, gl, GlVersion::Desktop)
+}
+
+/// Returns the anti-aliasing that actually ended up being used.
+/// What else the context supports is available from `capabilities` afterwards.
+pub fn setup(window: &Window, anti_aliasing: AntiAliasing) -> (GLContext, Context, AntiAliasing) {
+    let samples = match anti_aliasing {
+        AntiAliasing::Msaa(samples) => samples,
+        AntiAliasing::Analytic => 0,
+    };
+
+    let (gl_context, gl, version) = create_context(window, samples);
+    let detected = Capabilities::detect(&gl, version);
+    CAPABILITIES.with(|capabilities| capabilities.set(detected));
+
+    // The context may quietly ignore the request for samples.
+    let anti_aliasing = if samples > 0 && unsafe { gl.get_parameter_i32(SAMPLES) } > 0 {
+        anti_aliasing
+    } else {
+        AntiAliasing::Analytic
+    };
+    (gl_context, gl, anti_aliasing)
 }
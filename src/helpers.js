@@ -89,4 +89,100 @@ export function playBallAudio(buffer, rate, gain) {
 export function ballAudio(gain, rate) {
     ball_audio_gain.gain.value = gain;
     ball_audio_source.playbackRate.value = rate;
-}
\ No newline at end of file
+}
+
+export function downloadBytes(filename, bytes, mime) {
+    var blob = new Blob([bytes], { type: mime });
+    var url = URL.createObjectURL(blob);
+    var element = document.createElement('a');
+    element.setAttribute('href', url);
+    element.setAttribute('download', filename);
+
+    element.style.display = 'none';
+    document.body.appendChild(element);
+
+    element.click();
+
+    document.body.removeChild(element);
+    URL.revokeObjectURL(url);
+}
+
+var pointer_events = [];
+
+// Touches and pens are queued here and taken once per frame.
+// Mice are left to the window's own mouse events.
+export function setupPointerEvents() {
+    var canvas = document.getElementsByTagName("canvas")[0] || document.body;
+    canvas.style.touchAction = "none";
+
+    function queue(phase) {
+        return function (event) {
+            if (event.pointerType == "mouse") {
+                return;
+            }
+            event.preventDefault();
+            if (phase == 0) {
+                // Audio can only start from inside an input event.
+                setup();
+            }
+            var kind = event.pointerType == "touch" ? 1 : 2;
+            // Button 5 is the eraser end of a pen.
+            var eraser = (event.buttons & 32) != 0 || event.button == 5;
+            // Positions are in the canvas' pixels, the same as the window size the game sees.
+            var rect = canvas.getBoundingClientRect();
+            var scale = rect.width > 0 ? canvas.width / rect.width : 1;
+            var x = (event.clientX - rect.left) * scale;
+            var y = (event.clientY - rect.top) * scale;
+            pointer_events.push(phase, event.pointerId, kind, x, y, event.pressure, eraser ? 1 : 0);
+        };
+    }
+    canvas.addEventListener("pointerdown", queue(0));
+    canvas.addEventListener("pointermove", queue(1));
+    canvas.addEventListener("pointerup", queue(2));
+    canvas.addEventListener("pointercancel", queue(3));
+}
+
+export function takePointerEvents() {
+    var events = new Float32Array(pointer_events);
+    pointer_events = [];
+    return events;
+}
+
+// The first connected gamepad as its left stick and the A, B and start buttons.
+export function pollGamepad() {
+    var pads = navigator.getGamepads ? navigator.getGamepads() : [];
+    for (var i = 0; i < pads.length; i++) {
+        var pad = pads[i];
+        if (pad && pad.connected) {
+            var pressed = function (button) {
+                return pad.buttons[button] && pad.buttons[button].pressed ? 1 : 0;
+            };
+            return new Float32Array([pad.axes[0] || 0, pad.axes[1] || 0, pressed(0), pressed(1), pressed(9)]);
+        }
+    }
+    return new Float32Array(0);
+}
+
+var context_events = [];
+
+// 0 is the WebGL context being lost and 1 is it coming back, taken once per frame.
+// Without preventing the default the browser never restores it.
+export function setupContextEvents() {
+    var canvas = document.getElementsByTagName("canvas")[0];
+    if (!canvas) {
+        return;
+    }
+    canvas.addEventListener("webglcontextlost", function (event) {
+        event.preventDefault();
+        context_events.push(0);
+    });
+    canvas.addEventListener("webglcontextrestored", function () {
+        context_events.push(1);
+    });
+}
+
+export function takeContextEvents() {
+    var events = new Uint8Array(context_events);
+    context_events = [];
+    return events;
+}
@@ -1,6 +1,200 @@
 use crate::*;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Filter {
+    Nearest,
+    Linear,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Wrap {
+    Clamp,
+    Repeat,
+    Mirror,
+}
+
+/// How a texture is sampled.
+/// WebGL1 can only repeat and mipmap textures whose sides are powers of two,
+/// so other textures are clamped without mipmaps there.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextureParameters {
+    pub min_filter: Filter,
+    pub mag_filter: Filter,
+    pub wrap: Wrap,
+    pub mipmaps: bool,
+}
+
+impl Default for TextureParameters {
+    fn default() -> Self {
+        Self {
+            min_filter: Filter::Linear,
+            mag_filter: Filter::Linear,
+            wrap: Wrap::Clamp,
+            mipmaps: false,
+        }
+    }
+}
+
+/// What a texture was made from, kept to make it again if the context is lost.
+enum ImageSource {
+    /// 8 bit RGBA rows starting at the top.
+    Pixels(Vec<u8>),
+    #[cfg(target_arch = "wasm32")]
+    Element(web_sys::HtmlImageElement),
+}
+
 pub struct Image {
     pub texture: Texture,
+    pub width: u32,
+    pub height: u32,
+    source: ImageSource,
+    parameters: TextureParameters,
+    // See `resources::generation`.
+    generation: u32,
+}
+
+impl Image {
+    /// `pixels` are 8 bit RGBA rows starting at the top.
+    pub fn from_rgba(
+        gl: &GL,
+        width: u32,
+        height: u32,
+        pixels: Vec<u8>,
+        parameters: &TextureParameters,
+    ) -> Result<Image, String> {
+        if pixels.len() != (width * height * 4) as usize {
+            return Err(format!(
+                "Expected {} bytes for a {}x{} image but got {}",
+                width * height * 4,
+                width,
+                height,
+                pixels.len()
+            ));
+        }
+        let source = ImageSource::Pixels(pixels);
+        let mut image = Image {
+            texture: upload(gl, width, height, &source)?,
+            width,
+            height,
+            source,
+            parameters: *parameters,
+            generation: generation(),
+        };
+        image.set_parameters(gl, parameters);
+        Ok(image)
+    }
+
+    /// Makes the texture again after the context was lost.
+    pub fn restore(&mut self, gl: &GL) -> Result<(), String> {
+        self.texture = upload(gl, self.width, self.height, &self.source)?;
+        self.generation = generation();
+        let parameters = self.parameters;
+        self.set_parameters(gl, &parameters);
+        Ok(())
+    }
+
+    pub fn set_parameters(&mut self, gl: &GL, parameters: &TextureParameters) {
+        self.parameters = *parameters;
+        let supported = cfg!(not(target_arch = "wasm32"))
+            || (self.width.is_power_of_two() && self.height.is_power_of_two());
+        let (wrap, mipmaps) = if supported {
+            (parameters.wrap, parameters.mipmaps)
+        } else {
+            (Wrap::Clamp, false)
+        };
+
+        let filter = |filter| match filter {
+            Filter::Nearest => NEAREST,
+            Filter::Linear => LINEAR,
+        };
+        let min_filter = match (parameters.min_filter, mipmaps) {
+            (Filter::Nearest, true) => NEAREST_MIPMAP_LINEAR,
+            (Filter::Linear, true) => LINEAR_MIPMAP_LINEAR,
+            (min_filter, false) => filter(min_filter),
+        };
+        let wrap = match wrap {
+            Wrap::Clamp => CLAMP_TO_EDGE,
+            Wrap::Repeat => REPEAT,
+            Wrap::Mirror => MIRRORED_REPEAT,
+        };
+
+        unsafe {
+            gl.bind_texture(TEXTURE_2D, Some(self.texture));
+            gl.tex_parameter_i32(TEXTURE_2D, TEXTURE_MIN_FILTER, min_filter as i32);
+            gl.tex_parameter_i32(
+                TEXTURE_2D,
+                TEXTURE_MAG_FILTER,
+                filter(parameters.mag_filter) as i32,
+            );
+            gl.tex_parameter_i32(TEXTURE_2D, TEXTURE_WRAP_S, wrap as i32);
+            gl.tex_parameter_i32(TEXTURE_2D, TEXTURE_WRAP_T, wrap as i32);
+            if mipmaps {
+                gl.generate_mipmap(TEXTURE_2D);
+            }
+            gl.bind_texture(TEXTURE_2D, None);
+        }
+    }
+}
+
+impl Drop for Image {
+    fn drop(&mut self) {
+        free(self.generation, Resource::Texture(self.texture));
+    }
+}
+
+fn upload(gl: &GL, width: u32, height: u32, source: &ImageSource) -> Result<Texture, String> {
+    unsafe {
+        let texture = gl.create_texture()?;
+        gl.bind_texture(TEXTURE_2D, Some(texture));
+        match source {
+            ImageSource::Pixels(pixels) => gl.tex_image_2d(
+                TEXTURE_2D,
+                0, /* mip level */
+                RGBA as i32,
+                width as i32,
+                height as i32,
+                0,
+                RGBA,
+                UNSIGNED_BYTE,
+                Some(&pixels[..]),
+            ),
+            #[cfg(target_arch = "wasm32")]
+            ImageSource::Element(element) => gl.tex_image_2d_with_html_image(
+                TEXTURE_2D,
+                0, /* mip level */
+                RGBA as i32,
+                RGBA,
+                UNSIGNED_BYTE,
+                element,
+            ),
+        }
+        Ok(texture)
+    }
+}
+
+/// Decodes a PNG of any colour type to 8 bit RGBA. Returns the width, height and pixels.
+pub fn decode_png(bytes: &[u8]) -> Result<(u32, u32, Vec<u8>), String> {
+    let mut decoder = png::Decoder::new(bytes);
+    // Palettes and small or large bit depths all become 8 bits per channel.
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let (info, mut reader) = decoder.read_info().map_err(|e| e.to_string())?;
+    let mut data = vec![0; info.buffer_size()];
+    reader.next_frame(&mut data).map_err(|e| e.to_string())?;
+
+    let rgba = match info.color_type {
+        png::ColorType::RGBA => data,
+        png::ColorType::RGB => data
+            .chunks(3)
+            .flat_map(|c| vec![c[0], c[1], c[2], 255])
+            .collect(),
+        png::ColorType::GrayscaleAlpha => data
+            .chunks(2)
+            .flat_map(|c| vec![c[0], c[0], c[0], c[1]])
+            .collect(),
+        png::ColorType::Grayscale => data.iter().flat_map(|&c| vec![c, c, c, 255]).collect(),
+        color_type => return Err(format!("Unsupported PNG colour type {:?}", color_type)),
+    };
+    Ok((info.width, info.height, rgba))
 }
 
 #[cfg(target_arch = "wasm32")]
@@ -15,31 +209,52 @@ mod image_web {
     extern "C" {
         fn loadImage(path: &str) -> js_sys::Promise;
     }
-    pub async fn load_image(gl: &GL, path: &str) -> Result<Image, ()> {
+    pub async fn load_image(
+        gl: &GL,
+        path: &str,
+        parameters: &TextureParameters,
+    ) -> Result<Image, String> {
         let path = path.to_owned();
-        let image = JsFuture::from(loadImage(&path)).await.unwrap();
+        let image = JsFuture::from(loadImage(&path))
+            .await
+            .map_err(|_| format!("Could not load {}", path))?;
         let image: HtmlImageElement = image.dyn_into().unwrap();
-
-        unsafe {
-            let texture = gl.create_texture().unwrap();
-            gl.bind_texture(TEXTURE_2D, Some(texture));
-
-            gl.tex_image_2d_with_html_image(
-                TEXTURE_2D,
-                0, /* mip level */
-                RGBA as i32,
-                RGBA,
-                UNSIGNED_BYTE,
-                &image,
-            );
-            Ok(Image { texture })
-        }
+        let (width, height) = (image.width(), image.height());
+        let source = ImageSource::Element(image);
+        let mut image = Image {
+            texture: upload(gl, width, height, &source)?,
+            width,
+            height,
+            source,
+            parameters: *parameters,
+            generation: generation(),
+        };
+        image.set_parameters(gl, parameters);
+        Ok(image)
     }
 }
 #[cfg(target_arch = "wasm32")]
 pub use image_web::*;
 
+/// Where images are read from natively, the same folder the web build serves them from.
+#[cfg(not(target_arch = "wasm32"))]
+const ASSET_DIRECTORY: &str = "web_build";
+
+/// Only PNGs can be loaded natively.
+#[cfg(not(target_arch = "wasm32"))]
+pub async fn load_image(
+    gl: &GL,
+    path: &str,
+    parameters: &TextureParameters,
+) -> Result<Image, String> {
+    let (width, height, pixels) = read_png(path)?;
+    Image::from_rgba(gl, width, height, pixels, parameters)
+}
+
+/// Reads a PNG from where images are kept natively, as 8 bit RGBA.
 #[cfg(not(target_arch = "wasm32"))]
-pub async fn load_image(gl: &GL, path: &str) -> Result<Image, ()> {
-    unimplemented!()
+pub fn read_png(path: &str) -> Result<(u32, u32, Vec<u8>), String> {
+    let bytes = std::fs::read(std::path::Path::new(ASSET_DIRECTORY).join(path))
+        .map_err(|e| format!("Could not load {}: {}", path, e))?;
+    decode_png(&bytes).map_err(|e| format!("{}: {}", path, e))
 }
@@ -0,0 +1,340 @@
+use crate::*;
+
+/// Something the player can do, independent of which key or button does it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    Draw,
+    Erase,
+    Pan,
+    LaunchBall,
+    Undo,
+    ZoomIn,
+    ZoomOut,
+    Screenshot,
+    RecordGif,
+    ToggleEditor,
+    CursorLeft,
+    CursorRight,
+    CursorUp,
+    CursorDown,
+    /// Lifts or lowers the keyboard cursor's pen.
+    Pen,
+    SaveSession,
+}
+
+impl Action {
+    pub const ALL: [Action; 16] = [
+        Action::Draw,
+        Action::Erase,
+        Action::Pan,
+        Action::LaunchBall,
+        Action::Undo,
+        Action::ZoomIn,
+        Action::ZoomOut,
+        Action::Screenshot,
+        Action::RecordGif,
+        Action::ToggleEditor,
+        Action::CursorLeft,
+        Action::CursorRight,
+        Action::CursorUp,
+        Action::CursorDown,
+        Action::Pen,
+        Action::SaveSession,
+    ];
+
+    pub fn from_name(name: &str) -> Option<Action> {
+        Action::ALL
+            .iter()
+            .copied()
+            .find(|action| format!("{:?}", action) == name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MouseBinding {
+    Left,
+    Right,
+    Middle,
+}
+
+impl MouseBinding {
+    fn from_button(button: MouseButton) -> Option<MouseBinding> {
+        match button {
+            MouseButton::Left => Some(MouseBinding::Left),
+            MouseButton::Right => Some(MouseBinding::Right),
+            MouseButton::Middle => Some(MouseBinding::Middle),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Binding {
+    /// A key by its name, for example `Space` or `Z`.
+    Key(String),
+    Mouse(MouseBinding),
+}
+
+/// Whether a key with this name exists, using kettlewin's names so bindings compare to events.
+fn is_key_name(name: &str) -> bool {
+    const NAMED: &[&str] = &[
+        "Space",
+        "Enter",
+        "Backspace",
+        "Tab",
+        "Escape",
+        "Left",
+        "Right",
+        "Up",
+        "Down",
+        "Equal",
+        "Minus",
+        "Shift",
+        "Control",
+        "Alt",
+        "Meta",
+    ];
+    let numbered = |prefix: &str, range: std::ops::RangeInclusive<u8>| {
+        range
+            .into_iter()
+            .any(|n| name == format!("{}{}", prefix, n))
+    };
+    let letter = name.len() == 1 && name.chars().all(|c| c.is_ascii_uppercase());
+    letter || numbered("Digit", 0..=9) || numbered("F", 1..=12) || NAMED.contains(&name)
+}
+
+impl Binding {
+    fn from_name(name: &str) -> Option<Binding> {
+        match name {
+            "MouseLeft" => Some(Binding::Mouse(MouseBinding::Left)),
+            "MouseRight" => Some(Binding::Mouse(MouseBinding::Right)),
+            "MouseMiddle" => Some(Binding::Mouse(MouseBinding::Middle)),
+            key if is_key_name(key) => Some(Binding::Key(key.to_owned())),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputChange {
+    Pressed(Action),
+    Released(Action),
+}
+
+/// Tracks which actions are held based on the window's events.
+/// Only ever looks at the events it's given, so it can be driven by a made up event stream.
+pub struct Input {
+    bindings: Vec<(Binding, Action)>,
+    held: Vec<Action>,
+    pressed: Vec<Action>,
+    /// In window pixels from the top left.
+    pub mouse_position: Vector2,
+}
+
+impl Input {
+    pub fn new(bindings: Vec<(Binding, Action)>) -> Self {
+        Self {
+            bindings,
+            held: Vec::new(),
+            pressed: Vec::new(),
+            mouse_position: Vector2::new(0., 0.),
+        }
+    }
+
+    /// Reads bindings from lines of an action followed by a key or mouse button, like `Undo Z`.
+    /// Lines starting with `#` are ignored and an action can have more than one binding.
+    pub fn from_config(config: &str) -> Result<Self, String> {
+        let mut bindings = Vec::new();
+        for (number, line) in config.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut words = line.split_whitespace();
+            let (action, binding) = match (words.next(), words.next(), words.next()) {
+                (Some(action), Some(binding), None) => (action, binding),
+                _ => {
+                    return Err(format!(
+                        "Line {}: expected an action and a binding",
+                        number + 1
+                    ))
+                }
+            };
+            let action = Action::from_name(action)
+                .ok_or_else(|| format!("Line {}: unknown action {}", number + 1, action))?;
+            let binding = Binding::from_name(binding)
+                .ok_or_else(|| format!("Line {}: unknown key {}", number + 1, binding))?;
+            bindings.push((binding, action));
+        }
+        Ok(Self::new(bindings))
+    }
+
+    /// The default controls, natively replaced by a `controls.txt` in the working directory.
+    pub fn load() -> Self {
+        let default = || Self::from_config(include_str!("controls.txt")).unwrap();
+
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            if let Ok(config) = std::fs::read_to_string("controls.txt") {
+                match Self::from_config(&config) {
+                    Ok(input) => return input,
+                    Err(e) => log!("Could not load controls.txt: {}", e),
+                }
+            }
+        }
+        default()
+    }
+
+    /// Updates held actions from an event and returns what changed.
+    /// A single event only ever changes a single action, the first one bound.
+    pub fn handle_event(&mut self, event: &Event) -> Option<InputChange> {
+        let (binding, down) = match event {
+            Event::MouseMoved { x, y, .. } => {
+                self.mouse_position = Vector2::new(*x, *y);
+                return None;
+            }
+            Event::MouseButtonDown { button, x, y, .. } => {
+                self.mouse_position = Vector2::new(*x, *y);
+                (Binding::Mouse(MouseBinding::from_button(*button)?), true)
+            }
+            Event::MouseButtonUp { button, x, y, .. } => {
+                self.mouse_position = Vector2::new(*x, *y);
+                (Binding::Mouse(MouseBinding::from_button(*button)?), false)
+            }
+            Event::KeyDown { key, .. } => (Binding::Key(format!("{:?}", key)), true),
+            Event::KeyUp { key, .. } => (Binding::Key(format!("{:?}", key)), false),
+            _ => return None,
+        };
+        self.handle_binding(&binding, down)
+    }
+
+    /// Presses or releases the action `binding` is bound to, which is what events come down to.
+    fn handle_binding(&mut self, binding: &Binding, down: bool) -> Option<InputChange> {
+        let action = self
+            .bindings
+            .iter()
+            .find(|(b, _)| b == binding)
+            .map(|(_, action)| *action)?;
+        let change = if down {
+            InputChange::Pressed(action)
+        } else {
+            InputChange::Released(action)
+        };
+        Some(change).filter(|change| self.apply(*change))
+    }
+
+    /// Presses or releases an action directly, like replays do.
+    /// Returns false if it changes nothing, like pressing what's already held.
+    pub fn apply(&mut self, change: InputChange) -> bool {
+        match change {
+            InputChange::Pressed(action) if !self.held.contains(&action) => {
+                self.held.push(action);
+                self.pressed.push(action);
+                true
+            }
+            InputChange::Released(action) if self.held.contains(&action) => {
+                self.held.retain(|a| *a != action);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn is_down(&self, action: Action) -> bool {
+        self.held.contains(&action)
+    }
+
+    /// Pressed at some point since the last `end_frame`.
+    pub fn was_pressed(&self, action: Action) -> bool {
+        self.pressed.contains(&action)
+    }
+
+    /// Call at the end of every frame.
+    pub fn end_frame(&mut self) {
+        self.pressed.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(name: &str) -> Binding {
+        Binding::Key(name.to_owned())
+    }
+
+    #[test]
+    fn default_controls_load() {
+        let input = Input::from_config(include_str!("controls.txt")).unwrap();
+        assert!(input
+            .bindings
+            .contains(&(Binding::Mouse(MouseBinding::Left), Action::Draw)));
+        // The editor is for making levels, not something players should stumble into.
+        assert!(!input
+            .bindings
+            .iter()
+            .any(|(_, action)| *action == Action::ToggleEditor));
+    }
+
+    #[test]
+    fn unknown_names_are_errors() {
+        assert!(Input::from_config("Undo Zed").is_err());
+        assert!(Input::from_config("Undoo Z").is_err());
+        assert!(Input::from_config("Undo F13").is_err());
+        assert!(Input::from_config("Undo").is_err());
+        assert!(Input::from_config("# Undo Zed\n\nUndo Z").is_ok());
+    }
+
+    #[test]
+    fn bindings_press_and_release_actions() {
+        let mut input = Input::from_config("Undo Z\nLaunchBall Space").unwrap();
+
+        assert_eq!(
+            input.handle_binding(&key("Z"), true),
+            Some(InputChange::Pressed(Action::Undo))
+        );
+        // Held keys don't press again.
+        assert_eq!(input.handle_binding(&key("Z"), true), None);
+        assert!(input.is_down(Action::Undo));
+        assert!(input.was_pressed(Action::Undo));
+        assert!(!input.was_pressed(Action::LaunchBall));
+        // Unbound keys do nothing.
+        assert_eq!(input.handle_binding(&key("Q"), true), None);
+
+        assert_eq!(
+            input.handle_binding(&key("Z"), false),
+            Some(InputChange::Released(Action::Undo))
+        );
+        assert!(!input.is_down(Action::Undo));
+        assert!(input.was_pressed(Action::Undo));
+        // Releasing what isn't held does nothing.
+        assert_eq!(input.handle_binding(&key("Z"), false), None);
+    }
+
+    #[test]
+    fn end_frame_forgets_presses_but_not_held() {
+        let mut input = Input::from_config("LaunchBall Space").unwrap();
+        input.handle_binding(&key("Space"), true);
+        input.end_frame();
+
+        assert!(!input.was_pressed(Action::LaunchBall));
+        assert!(input.is_down(Action::LaunchBall));
+    }
+
+    #[test]
+    fn mouse_buttons_are_bindings() {
+        let mut input = Input::from_config("Draw MouseLeft\nErase MouseRight").unwrap();
+        let right = Binding::Mouse(MouseBinding::Right);
+
+        assert_eq!(
+            input.handle_binding(&right, true),
+            Some(InputChange::Pressed(Action::Erase))
+        );
+        assert!(input.is_down(Action::Erase));
+        assert!(!input.is_down(Action::Draw));
+        assert_eq!(
+            MouseBinding::from_button(MouseButton::Middle),
+            Some(MouseBinding::Middle)
+        );
+    }
+}
@@ -1,18 +1,46 @@
 use crate::*;
+
+/// A run of connected segments that gets tessellated as a single polyline.
+#[derive(Clone, Copy)]
+struct Stroke {
+    first_segment: usize,
+    first_vertex: usize,
+    first_index: usize,
+}
+
 pub struct Lines {
     pub last_position: Option<Vector3>,
     pub line_points: Vec<Vector3>,
+    /// The radius at each of `line_points`.
+    pub line_radii: Vec<f32>,
     pub needs_update: bool,
-    pub mesh: Mesh,
+    // Created on first upload so lines can be built without a GL context.
+    pub mesh: Option<Mesh>,
+    pub style: LineStyle,
+    // CPU side copy of the mesh. Each stroke owns a contiguous block of it
+    // so only strokes that changed need to be tessellated and uploaded again.
+    vertices: Vec<MeshVertex>,
+    indices: Vec<Tri>,
+    strokes: Vec<Stroke>,
+    // Segments from here onwards have changed since the last upload.
+    first_dirty_segment: usize,
+    last_radius: f32,
 }
 
 impl Lines {
-    pub fn new(gl: &GL) -> Self {
+    pub fn new() -> Self {
         Self {
             needs_update: false,
             last_position: None,
             line_points: Vec::new(),
-            mesh: Mesh::new(&gl),
+            line_radii: Vec::new(),
+            mesh: None,
+            style: LineStyle::default(),
+            vertices: Vec::new(),
+            indices: Vec::new(),
+            strokes: Vec::new(),
+            first_dirty_segment: 0,
+            last_radius: LINE_RADIUS,
         }
     }
     pub fn end_segment(&mut self) {
@@ -20,6 +48,11 @@ impl Lines {
     }
 
     pub fn add_segment(&mut self, position: Vector3) {
+        self.add_segment_with_radius(position, LINE_RADIUS);
+    }
+
+    /// Like `add_segment` but the line's width changes towards `radius`.
+    pub fn add_segment_with_radius(&mut self, position: Vector3, radius: f32) {
         self.needs_update = true;
         if let Some(last_position_inner) = self.last_position {
             if (last_position_inner - position).length() > 0.01 {
@@ -30,62 +63,169 @@ impl Lines {
                 ));
                 self.line_points
                     .push(Vector3::new(position.x, position.y, 0.0));
+                self.line_radii.push(self.last_radius);
+                self.line_radii.push(radius);
 
                 self.last_position = Some(position);
+                self.last_radius = radius;
             }
         } else {
             // Add a point
             self.last_position = Some(position);
+            self.last_radius = radius;
         }
     }
 
     pub fn erase(&mut self, position: Vector3, radius: f32) {
-        let len = self.line_points.len();
-        let mut to_remove = Vec::new();
-
-        for i in (1..len).step_by(2) {
-            let intersection =
-                point_with_line_segment(position, self.line_points[i - 1], self.line_points[i]);
-            if intersection.0 < radius + LINE_RADIUS {
-                to_remove.push(i);
+        let segment_count = self.line_points.len() / 2;
+        let mut kept = 0;
+        let mut first_removed = None;
+
+        // Compact the remaining segments towards the front, keeping their order.
+        for segment in 0..segment_count {
+            let a = self.line_points[segment * 2];
+            let b = self.line_points[segment * 2 + 1];
+            let intersection = point_with_line_segment(position, a, b);
+            if intersection.0 < radius + self.segment_radius(segment) {
+                first_removed.get_or_insert(segment);
+                continue;
             }
+
+            self.line_points[kept * 2] = a;
+            self.line_points[kept * 2 + 1] = b;
+            self.line_radii[kept * 2] = self.line_radii[segment * 2];
+            self.line_radii[kept * 2 + 1] = self.line_radii[segment * 2 + 1];
+            kept += 1;
         }
 
-        if to_remove.len() > 0 {
+        if let Some(first_removed) = first_removed {
             self.needs_update = true;
+            self.line_points.truncate(kept * 2);
+            self.line_radii.truncate(kept * 2);
+
+            // Everything before the first removed segment stayed in place.
+            self.first_dirty_segment = self.first_dirty_segment.min(first_removed);
         }
-        // Swap points to remove to the end and then pop.
-        let mut len = self.line_points.len();
-
-        for i in &to_remove {
-            let i = *i;
-            self.line_points.swap(i, len - 1);
-            self.line_points.swap(i - 1, len - 2);
-            len -= 2;
+    }
+
+    /// Removes the most recently drawn stroke, or what's left of it after erasing.
+    pub fn remove_last_stroke(&mut self) {
+        self.end_segment();
+        let mut segment = self.line_points.len() / 2;
+        if segment == 0 {
+            return;
         }
 
-        for i in to_remove {
-            self.line_points.pop();
-            self.line_points.pop();
+        // Walk back while segments start where the previous one ended.
+        segment -= 1;
+        while segment > 0 && self.line_points[segment * 2] == self.line_points[segment * 2 - 1] {
+            segment -= 1;
         }
+
+        self.line_points.truncate(segment * 2);
+        self.line_radii.truncate(segment * 2);
+        self.first_dirty_segment = self.first_dirty_segment.min(segment);
+        self.needs_update = true;
+    }
+
+    /// The widest the segment gets, used for collisions.
+    pub fn segment_radius(&self, segment: usize) -> f32 {
+        self.line_radii[segment * 2].max(self.line_radii[segment * 2 + 1])
     }
 
     pub fn clear(&mut self) {
         self.needs_update = true;
         self.last_position = None;
         self.line_points.clear();
+        self.line_radii.clear();
+        self.vertices.clear();
+        self.indices.clear();
+        self.strokes.clear();
+        self.first_dirty_segment = 0;
     }
 
     pub fn update_mesh(&mut self, gl: &GL) {
-        if self.needs_update {
+        // A mesh from a lost context is remade with everything, not just what changed.
+        let lost = self.mesh.as_ref().map_or(false, |mesh| mesh.is_lost());
+        if self.needs_update || lost {
             self.needs_update = false;
-            lines::update_mesh_with_line(
-                &gl,
-                &mut self.mesh,
-                &self.line_points,
-                LINE_RADIUS,
-                Vector3::FORWARD,
+            let (first_vertex, first_index) = self.build_geometry();
+            self.mesh
+                .get_or_insert_with(|| Mesh::new_dynamic(gl))
+                .update_from(gl, &self.vertices, &self.indices, first_vertex, first_index);
+            self.first_dirty_segment = self.line_points.len() / 2;
+        }
+    }
+
+    /// The CPU side triangles for every line, for renderers that don't use GL.
+    pub fn geometry(&mut self) -> (&[MeshVertex], &[Tri]) {
+        self.build_geometry();
+        (&self.vertices, &self.indices)
+    }
+
+    /// Tessellates every stroke from the one containing the first dirty segment onwards.
+    /// Returns where the rebuilt vertices and indices start.
+    fn build_geometry(&mut self) -> (usize, usize) {
+        // Strokes that start after the dirty segment may have been split or merged by an erase.
+        let keep = self
+            .strokes
+            .iter()
+            .rposition(|stroke| stroke.first_segment <= self.first_dirty_segment);
+        let (mut segment, first_vertex, first_index) = match keep {
+            Some(i) => {
+                let stroke = self.strokes[i];
+                self.strokes.truncate(i);
+                (
+                    stroke.first_segment,
+                    stroke.first_vertex,
+                    stroke.first_index,
+                )
+            }
+            None => {
+                self.strokes.clear();
+                (0, 0, 0)
+            }
+        };
+        self.vertices.truncate(first_vertex);
+        self.indices.truncate(first_index);
+
+        let segment_count = self.line_points.len() / 2;
+        let mut points = Vec::new();
+        let mut radii = Vec::new();
+        while segment < segment_count {
+            let stroke = Stroke {
+                first_segment: segment,
+                first_vertex: self.vertices.len(),
+                first_index: self.indices.len(),
+            };
+
+            points.clear();
+            radii.clear();
+            points.push(self.line_points[segment * 2]);
+            points.push(self.line_points[segment * 2 + 1]);
+            radii.push(self.line_radii[segment * 2]);
+            radii.push(self.line_radii[segment * 2 + 1]);
+            segment += 1;
+
+            // Segments continue a stroke when they start where the last one ended.
+            while segment < segment_count
+                && self.line_points[segment * 2] == *points.last().unwrap()
+            {
+                points.push(self.line_points[segment * 2 + 1]);
+                radii.push(self.line_radii[segment * 2 + 1]);
+                segment += 1;
+            }
+
+            lines::tessellate_polyline(
+                &points,
+                |i| radii[i],
+                &self.style,
+                &mut self.vertices,
+                &mut self.indices,
             );
+            self.strokes.push(stroke);
         }
+
+        (first_vertex, first_index)
     }
 }
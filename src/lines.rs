@@ -1,116 +1,415 @@
 use crate::*;
 
-fn produce_end_cap(
-    center: Vector3,
-    right: Vector3,
-    forward: Vector3,
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LineJoin {
+    Round,
+    /// Falls back to a bevel when the miter would be longer than `miter_limit` radii.
+    Miter,
+}
+
+impl LineJoin {
+    /// As levels write it after `join`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "round" => Some(LineJoin::Round),
+            "miter" => Some(LineJoin::Miter),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            LineJoin::Round => "round",
+            LineJoin::Miter => "miter",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct LineStyle {
+    pub join: LineJoin,
+    pub miter_limit: f32,
+    /// Number of triangles used for a half circle of a round join or cap.
+    pub round_resolution: u32,
+}
+
+impl Default for LineStyle {
+    fn default() -> Self {
+        Self {
+            join: LineJoin::Round,
+            miter_limit: 4.0,
+            round_resolution: 6,
+        }
+    }
+}
+
+fn left_normal(direction: Vector3) -> Vector3 {
+    Vector3::new(-direction.y, direction.x, 0.0)
+}
+
+fn rotate(v: Vector3, angle: f32) -> Vector3 {
+    let (sin, cos) = angle.sin_cos();
+    Vector3::new(v.x * cos - v.y * sin, v.x * sin + v.y * cos, 0.0)
+}
+
+fn push_vertex(vertices: &mut Vec<MeshVertex>, position: Vector3, edge: f32) -> u32 {
+    vertices.push(MeshVertex { position, edge });
+    (vertices.len() - 1) as u32
+}
+
+/// Fans around `center` from `start` by `angle` radians, ending on the existing vertex `end`.
+/// The arc's vertices are on the edge of the shape.
+fn produce_arc(
+    center: (Vector3, u32),
+    start: (Vector3, u32),
+    end: u32,
+    angle: f32,
     resolution: u32,
-    vertices: &mut Vec<Vector3>,
+    vertices: &mut Vec<MeshVertex>,
     indices: &mut Vec<[u32; 3]>,
 ) {
-    let start = vertices.len() as u32;
-    vertices.push(center - right);
-    vertices.push(center + right);
+    let steps = ((angle.abs() / crate::zmath::PI) * resolution as f32)
+        .ceil()
+        .max(1.0) as u32;
+    let offset = start.0 - center.0;
+    let mut previous = start.1;
+    for step in 1..steps {
+        let new_vertex = push_vertex(
+            vertices,
+            center.0 + rotate(offset, angle * step as f32 / steps as f32),
+            0.0,
+        );
+        indices.push([center.1, previous, new_vertex]);
+        previous = new_vertex;
+    }
+    indices.push([center.1, previous, end]);
+}
 
-    let increment = crate::zmath::PI / (resolution + 1) as f32;
-    let mut current_angle = 0.;
+/// Tessellates a connected stroke into triangles that never overlap each other,
+/// so translucent strokes don't show seams where segments meet.
+/// `radius` is queried per point which allows strokes to vary in width.
+/// Every point gets a vertex on the center line so `edge` can be interpolated for anti-aliasing.
+/// Returns the index in `points` that each vertex added to `vertices` was made for.
+pub fn tessellate_polyline(
+    points: &[Vector3],
+    radius: impl Fn(usize) -> f32,
+    style: &LineStyle,
+    vertices: &mut Vec<MeshVertex>,
+    indices: &mut Vec<[u32; 3]>,
+) -> Vec<usize> {
+    // Repeated points have no direction so skip them.
+    let mut filtered: Vec<(Vector3, f32, usize)> = Vec::with_capacity(points.len());
+    for (i, point) in points.iter().enumerate() {
+        let point = Vector3::new(point.x, point.y, 0.0);
+        match filtered.last() {
+            Some((last, _, _)) if (*last - point).length() < 0.0001 => {}
+            _ => filtered.push((point, radius(i), i)),
+        }
+    }
 
-    for _ in 0..resolution {
-        current_angle += increment;
+    let mut sources = Vec::new();
+    let count = filtered.len();
+    if count < 2 {
+        return sources;
+    }
+
+    // The (left, center, right) vertices that the previous segment ends on.
+    let mut previous_out: Option<(u32, u32, u32)> = None;
+
+    for i in 0..count {
+        let (p, r, source) = filtered[i];
+        let first_vertex = vertices.len();
+        let previous_direction = if i > 0 {
+            Some((p - filtered[i - 1].0).normal())
+        } else {
+            None
+        };
+        let next_direction = if i + 1 < count {
+            Some((filtered[i + 1].0 - p).normal())
+        } else {
+            None
+        };
+
+        let (pair_in, pair_out) = match (previous_direction, next_direction) {
+            (None, Some(d)) | (Some(d), None) => {
+                // Round cap facing away from the stroke.
+                let is_start = previous_direction.is_none();
+                let n = left_normal(d) * r;
+                let center = push_vertex(vertices, p, r);
+                let left = push_vertex(vertices, p + n, 0.0);
+                let right = push_vertex(vertices, p - n, 0.0);
+                if is_start {
+                    produce_arc(
+                        (p, center),
+                        (p + n, left),
+                        right,
+                        crate::zmath::PI,
+                        style.round_resolution,
+                        vertices,
+                        indices,
+                    );
+                } else {
+                    produce_arc(
+                        (p, center),
+                        (p - n, right),
+                        left,
+                        crate::zmath::PI,
+                        style.round_resolution,
+                        vertices,
+                        indices,
+                    );
+                }
+                ((left, center, right), (left, center, right))
+            }
+            (Some(d0), Some(d1)) => {
+                let n0 = left_normal(d0);
+                let n1 = left_normal(d1);
+                let turn = d0.x * d1.y - d0.y * d1.x;
+
+                if turn.abs() < 0.0001 && Vector3::dot(d0, d1) > 0.0 {
+                    // Straight through, segments can share vertices.
+                    let n = (n0 + n1).normal() * r;
+                    let center = push_vertex(vertices, p, r);
+                    let left = push_vertex(vertices, p + n, 0.0);
+                    let right = push_vertex(vertices, p - n, 0.0);
+                    ((left, center, right), (left, center, right))
+                } else {
+                    // Positive turns go left, which puts the inside of the corner on the left.
+                    let side = if turn >= 0.0 { 1.0 } else { -1.0 };
+                    // A full reversal has no miter direction, so point the corner backwards.
+                    let miter = if (n0 + n1).length() > 0.0001 {
+                        (n0 + n1).normal()
+                    } else {
+                        -d0
+                    };
+                    let cos_half = Vector3::dot(miter, n0).max(0.0001);
+
+                    // Keep the inner corner within the nearer half of both neighbouring segments,
+                    // otherwise it overlaps the joins at their other ends when they're short.
+                    let shortest = (p - filtered[i - 1].0)
+                        .length()
+                        .min((filtered[i + 1].0 - p).length());
+                    let sin_half = (1.0 - cos_half * cos_half).max(0.0).sqrt();
+                    let inner_length = if sin_half > 0.0001 {
+                        (r / cos_half).min(shortest * 0.5 / sin_half)
+                    } else {
+                        r / cos_half
+                    };
+
+                    // Both segments run up to the line between the center and the inner corner,
+                    // leaving only the wedge on the outside of the corner to fill.
+                    let center = push_vertex(vertices, p, r);
+                    let inner = push_vertex(vertices, p + miter * inner_length * side, 0.0);
+                    let outer_start_position = p - n0 * r * side;
+                    let outer_end_position = p - n1 * r * side;
+                    let outer_start = push_vertex(vertices, outer_start_position, 0.0);
+                    let outer_end = push_vertex(vertices, outer_end_position, 0.0);
 
-        let new_vertex = vertices.len() as u32;
-        vertices.push(center + right * current_angle.cos() + forward * current_angle.sin());
+                    match style.join {
+                        LineJoin::Round => {
+                            let angle = Vector3::dot(d0, d1).max(-1.0).min(1.0).acos() * side;
+                            produce_arc(
+                                (p, center),
+                                (outer_start_position, outer_start),
+                                outer_end,
+                                angle,
+                                style.round_resolution,
+                                vertices,
+                                indices,
+                            );
+                        }
+                        LineJoin::Miter => {
+                            if 1.0 / cos_half <= style.miter_limit {
+                                let tip =
+                                    push_vertex(vertices, p - miter * (r / cos_half) * side, 0.0);
+                                indices.push([center, outer_start, tip]);
+                                indices.push([center, tip, outer_end]);
+                            } else {
+                                indices.push([center, outer_start, outer_end]);
+                            }
+                        }
+                    }
 
-        indices.push([start, new_vertex - 1, new_vertex]);
+                    if side > 0.0 {
+                        ((inner, center, outer_start), (inner, center, outer_end))
+                    } else {
+                        ((outer_start, center, inner), (outer_end, center, inner))
+                    }
+                }
+            }
+            (None, None) => unreachable!(),
+        };
+
+        if let Some((left, center, right)) = previous_out {
+            let (next_left, next_center, next_right) = pair_in;
+            indices.push([left, center, next_center]);
+            indices.push([left, next_center, next_left]);
+            indices.push([center, right, next_right]);
+            indices.push([center, next_right, next_center]);
+        }
+        previous_out = Some(pair_out);
+        sources.resize(sources.len() + vertices.len() - first_vertex, source);
     }
+    sources
 }
 
-pub fn update_mesh_with_circle(
-    gl: &GL,
-    mesh: &mut Mesh,
-    center: Vector3,
-    radius: f32,
-    resolution: u32, // Number of sides
-) {
-    let mut vertices = Vec::new();
-    let mut indices = Vec::new();
-
-    let start = vertices.len() as u32;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    vertices.push(center);
+    const RADIUS: f32 = 0.1;
 
-    let increment = (crate::zmath::PI * 2.0) / resolution as f32;
-    let mut current_angle = increment;
+    fn tessellate(points: &[(f32, f32)], join: LineJoin) -> (Vec<Vector3>, Vec<[u32; 3]>) {
+        let points: Vec<_> = points
+            .iter()
+            .map(|&(x, y)| Vector3::new(x, y, 0.0))
+            .collect();
+        let style = LineStyle {
+            join,
+            ..LineStyle::default()
+        };
+        let (mut vertices, mut indices) = (Vec::new(), Vec::new());
+        tessellate_polyline(&points, |_| RADIUS, &style, &mut vertices, &mut indices);
+        let positions = vertices.iter().map(|v| v.position).collect();
+        (positions, indices)
+    }
 
-    let right = Vector3::RIGHT * radius;
-    let forward = Vector3::UP * radius;
+    fn corners(vertices: &[Vector3], triangle: [u32; 3]) -> [Vector3; 3] {
+        [
+            vertices[triangle[0] as usize],
+            vertices[triangle[1] as usize],
+            vertices[triangle[2] as usize],
+        ]
+    }
 
-    for i in 0..resolution {
-        current_angle += increment;
+    fn cross(a: Vector3, b: Vector3, p: Vector3) -> f32 {
+        (b.x - a.x) * (p.y - a.y) - (b.y - a.y) * (p.x - a.x)
+    }
 
-        let new_vertex = vertices.len() as u32;
-        let dir = Vector3::new(current_angle.cos(), current_angle.sin(), 0.0).normal();
-        vertices.push(center + dir * radius);
+    fn area(vertices: &[Vector3], indices: &[[u32; 3]]) -> f32 {
+        indices
+            .iter()
+            .map(|&triangle| {
+                let [a, b, c] = corners(vertices, triangle);
+                cross(a, b, c).abs() / 2.0
+            })
+            .sum()
+    }
 
-        if i > 0 {
-            indices.push([start, new_vertex, new_vertex - 1]);
+    /// The most triangles any point of a fine grid is strictly inside of.
+    fn most_overlapping(vertices: &[Vector3], indices: &[[u32; 3]]) -> usize {
+        let (mut min, mut max) = (vertices[0], vertices[0]);
+        for v in vertices {
+            min = Vector3::new(min.x.min(v.x), min.y.min(v.y), 0.0);
+            max = Vector3::new(max.x.max(v.x), max.y.max(v.y), 0.0);
         }
+        let step = 0.003;
+        let mut most = 0;
+        let mut y = min.y;
+        while y < max.y {
+            let mut x = min.x;
+            while x < max.x {
+                let p = Vector3::new(x, y, 0.0);
+                let inside = indices
+                    .iter()
+                    .filter(|&&triangle| {
+                        let [a, b, c] = corners(vertices, triangle);
+                        let sides = [cross(a, b, p), cross(b, c, p), cross(c, a, p)];
+                        let edge = 0.00001;
+                        sides.iter().all(|&s| s > edge) || sides.iter().all(|&s| s < -edge)
+                    })
+                    .count();
+                most = most.max(inside);
+                x += step;
+            }
+            y += step;
+        }
+        most
     }
 
-    indices.push([start, 1, (vertices.len() - 1) as u32]);
+    #[test]
+    fn straight_runs_share_vertices() {
+        let (vertices, indices) =
+            tessellate(&[(0.0, 0.0), (1.0, 0.0), (2.0, 0.0)], LineJoin::Round);
+        // Two caps of a center, two sides and five vertices between them,
+        // and the middle point only needs its center and two sides.
+        assert_eq!(vertices.len(), 8 + 3 + 8);
+        // Six triangles per cap and four per segment, two either side of its center line.
+        assert_eq!(indices.len(), 6 + 6 + 2 * 4);
+        assert_eq!(most_overlapping(&vertices, &indices), 1);
 
-    mesh.update(gl, &vertices, &indices);
-}
+        // The body plus two caps that are a little smaller than half circles.
+        let area = area(&vertices, &indices);
+        let body = 2.0 * 2.0 * RADIUS;
+        assert!(area > body + 0.9 * zmath::PI * RADIUS * RADIUS);
+        assert!(area < body + zmath::PI * RADIUS * RADIUS);
+    }
 
-/// Pass in an array where every two lines is a line segment
-pub fn update_mesh_with_line(
-    gl: &GL,
-    mesh: &mut Mesh,
-    lines: &[Vector3],
-    radius: f32,
-    plane_normal: Vector3,
-) {
-    let resolution = 4;
-    let mut vertices = Vec::new();
-    let mut indices = Vec::new();
-
-    let line_len = lines.len();
-
-    for i in (1..line_len).step_by(2) {
-        let mut forward = lines[i] - lines[i - 1];
-        forward.normalize();
-
-        let mut right = Vector3::cross(forward, plane_normal);
-        right.normalize();
-
-        produce_end_cap(
-            lines[i - 1],
-            -right * radius,
-            -forward * radius,
-            resolution,
-            &mut vertices,
-            &mut indices,
-        );
+    #[test]
+    fn sharp_turns_do_not_overlap() {
+        let points = [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0)];
+        let (round_vertices, round_indices) = tessellate(&points, LineJoin::Round);
+        let (miter_vertices, miter_indices) = tessellate(&points, LineJoin::Miter);
 
-        let start = vertices.len() as u32;
+        // A quarter turn is three steps of a round join, two new vertices between its sides.
+        assert_eq!(round_vertices.len(), 8 + 4 + 2 + 8);
+        assert_eq!(round_indices.len(), 12 + 3 + 2 * 4);
+        // A miter adds its tip instead.
+        assert_eq!(miter_vertices.len(), 8 + 4 + 1 + 8);
+        assert_eq!(miter_indices.len(), 12 + 2 + 2 * 4);
 
-        vertices.push(-right * radius + lines[i - 1]);
-        vertices.push(right * radius + lines[i - 1]);
-        vertices.push(right * radius + lines[i]);
-        vertices.push(-right * radius + lines[i]);
+        // How far the outside of the corner, below and to the right of it, reaches.
+        let corner = Vector3::new(1.0, 0.0, 0.0);
+        let farthest = |vertices: &[Vector3]| {
+            vertices
+                .iter()
+                .filter(|v| v.x >= corner.x || v.y <= corner.y)
+                .map(|&v| (v - corner).length())
+                .fold(0.0, f32::max)
+        };
+        assert!(farthest(&round_vertices[8..14]) <= RADIUS * 1.001);
+        assert!((farthest(&miter_vertices[8..13]) - RADIUS * 2.0f32.sqrt()).abs() < 0.001);
 
-        indices.push([start + 0, start + 1, start + 2]);
-        indices.push([start + 0, start + 2, start + 3]);
+        assert_eq!(most_overlapping(&round_vertices, &round_indices), 1);
+        assert_eq!(most_overlapping(&miter_vertices, &miter_indices), 1);
 
-        produce_end_cap(
-            lines[i],
-            right * radius,
-            forward * radius,
-            resolution,
-            &mut vertices,
-            &mut indices,
-        );
+        // Too sharp for the miter limit, so it's beveled.
+        let points = [(0.0, 0.0), (1.0, 0.0), (0.2, 0.2)];
+        for &join in &[LineJoin::Round, LineJoin::Miter] {
+            let (vertices, indices) = tessellate(&points, join);
+            assert_eq!(most_overlapping(&vertices, &indices), 1);
+            if join == LineJoin::Miter {
+                assert_eq!(vertices.len(), 8 + 4 + 8);
+            }
+        }
+    }
+
+    #[test]
+    fn reversals_end_in_a_round_corner() {
+        let (vertices, indices) =
+            tessellate(&[(0.0, 0.0), (1.0, 0.0), (0.5, 0.0)], LineJoin::Round);
+        // A half turn takes the whole resolution of the join.
+        assert_eq!(vertices.len(), 8 + 4 + 5 + 8);
+        assert_eq!(indices.len(), 12 + 6 + 2 * 4);
+        assert!(vertices.iter().all(|v| v.x.is_finite() && v.y.is_finite()));
+        let right = vertices.iter().map(|v| v.x).fold(0.0, f32::max);
+        assert!((right - (1.0 + RADIUS)).abs() < 0.001);
     }
 
-    mesh.update(gl, &vertices, &indices);
+    #[test]
+    fn short_segments_keep_corners_apart() {
+        // Two turns the same way around a segment much shorter than the line is wide.
+        let (sin, cos) = (zmath::PI / 6.0).sin_cos();
+        let middle = (1.0 + 0.02 * cos, 0.02 * sin);
+        let points = [
+            (0.0, 0.0),
+            (1.0, 0.0),
+            middle,
+            (middle.0 + 0.5, middle.1 + 0.866),
+        ];
+        for &join in &[LineJoin::Round, LineJoin::Miter] {
+            let (vertices, indices) = tessellate(&points, join);
+            assert_eq!(most_overlapping(&vertices, &indices), 1);
+        }
+    }
 }
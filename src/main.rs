@@ -1,48 +1,125 @@
 use glow::*;
 use kettlewin::*;
-
+use std::collections::HashMap;
+
+mod background;
+mod ball_effects;
+mod batch;
+mod camera;
+mod capture;
+mod cursor;
 mod editor;
+mod game;
 mod gl;
 mod image;
+mod input;
 mod line_manager;
 mod lines;
 mod log;
 mod mesh;
 mod mouse_playback;
+mod particles;
+mod pointer;
+mod post_process;
+mod render;
+mod resources;
+mod rng;
+mod script;
+mod session;
 mod shader;
-
+mod software_renderer;
+mod text;
+mod theme;
+
+use background::*;
+use ball_effects::*;
+use batch::*;
+use camera::*;
+use capture::*;
+use cursor::*;
 use editor::*;
+use game::*;
 use gl::*;
 use image::*;
+use input::*;
 use line_manager::*;
-use log::*;
 use mesh::*;
 use mouse_playback::*;
+use particles::*;
+use pointer::*;
+use post_process::*;
+use render::*;
+use resources::*;
+use rng::*;
+use script::*;
+use session::*;
 use shader::*;
+use software_renderer::*;
+use text::*;
+use theme::*;
 #[allow(dead_code)]
 mod zmath;
 
 mod audio;
-use audio::*;
 
 use lines::*;
 use zmath::*;
 
+#[cfg(target_arch = "wasm32")]
 use wasm_bindgen::{prelude::*, JsCast};
+#[cfg(target_arch = "wasm32")]
 use wasm_bindgen_futures::JsFuture;
+#[cfg(target_arch = "wasm32")]
 #[wasm_bindgen(module = "/src/helpers.js")]
 extern "C" {
     fn download(path: &str, text: &str);
 }
+
+#[cfg(not(target_arch = "wasm32"))]
+fn download(path: &str, text: &str) {
+    capture::save_file(path, text.as_bytes(), "text/plain");
+}
+
+/// A setting from `?name=value` after the page's address,
+/// or natively from the environment variable with the name in capitals.
+#[cfg(target_arch = "wasm32")]
+pub fn setting(name: &str) -> Option<String> {
+    let search = web_sys::window()?.location().search().ok()?;
+    search
+        .trim_start_matches('?')
+        .split('&')
+        .find_map(|pair| pair.strip_prefix(name)?.strip_prefix('='))
+        .map(str::to_owned)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn setting(name: &str) -> Option<String> {
+    std::env::var(name.to_uppercase()).ok()
+}
+
 fn main() {
     #[cfg(target_arch = "wasm32")]
     console_error_panic_hook::set_once();
 
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let args: Vec<String> = std::env::args().collect();
+        if args.get(1).map(|a| a.as_str()) == Some("capture") {
+            capture::run_cli(&args[2..]);
+            return;
+        }
+        if args.get(1).map(|a| a.as_str()) == Some("replay") {
+            session::run_replay_cli(&args[2..]);
+            return;
+        }
+    }
+
     let (app, mut event_loop) = initialize();
     event_loop.run_async(app, run);
 }
 
-#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Color {
     r: f32,
     g: f32,
@@ -51,10 +128,13 @@ pub struct Color {
 }
 
 impl Color {
-    pub fn new(r: f32, g: f32, b: f32, a: f32) -> Self {
+    pub const fn new(r: f32, g: f32, b: f32, a: f32) -> Self {
         Self { r, g, b, a }
     }
 }
+
+pub const LEVEL_LINE_COLOR: Color = Color::new(138.0 / 255.0, 132. / 255.0, 170. / 255.0, 1.0);
+pub const USER_LINE_COLOR: Color = Color::new(88.0 / 255.0, 65. / 255.0, 226. / 255.0, 1.0);
 #[derive(Debug)]
 struct Collectible {
     position: Vector3,
@@ -64,33 +144,33 @@ struct Collectible {
     collected: bool,
 }
 
-struct Camera {
-    projection: Matrix4x4,
-    view: Matrix4x4,
-    inverse_projection: Matrix4x4,
-    inverse_view: Matrix4x4,
-}
-
 pub struct Level {
     start_position: Vector3,
-    line_color: Color,
-    user_line_color: Color,
+    /// How corners of the level's lines and the player's are drawn.
+    line_join: LineJoin,
+    theme: Theme,
     collected: u32,
     collectibles: Vec<Collectible>,
     complete: bool,
     setup: bool,
+    bounds: Bounds,
+    background: Background,
+    script: Script,
 }
 
 impl Level {
-    pub fn new(start_position: Vector3, line_color: Color, user_line_color: Color) -> Self {
+    pub fn new(start_position: Vector3) -> Self {
         Self {
             start_position,
-            line_color,
+            line_join: LineJoin::Round,
+            theme: Theme::default(),
             collected: 0,
             collectibles: Vec::new(),
-            user_line_color,
             complete: false,
             setup: false,
+            bounds: Bounds::SCREEN,
+            background: Background::new(),
+            script: Script::new(),
         }
     }
 
@@ -100,6 +180,7 @@ impl Level {
             collectible.collected = false;
         }
         self.complete = false;
+        self.script.reset();
     }
 
     pub fn collect(&mut self, amount: u32) {
@@ -112,37 +193,106 @@ impl Level {
         log!("LEN: {:?}", self.collectibles.len());
     }
 
+    /// Runs the level's script for where the ball is and the collectibles it just collected.
+    /// Completes the level if the script says to and returns what's left for the game to do.
+    pub fn run_script(&mut self, ball_position: Vector3, collected: &[usize]) -> Vec<Effect> {
+        let mut effects = self.script.update(ball_position);
+        for &index in collected {
+            effects.extend(self.script.collect(index));
+        }
+        let mut complete = false;
+        effects.retain(|effect| {
+            let is_complete = *effect == Effect::Complete;
+            complete |= is_complete;
+            !is_complete
+        });
+        if complete && !self.complete {
+            self.complete = true;
+            log!("Finished level!");
+        }
+        effects
+    }
+
     pub fn clear(&mut self) {
         self.complete = false;
         self.collectibles.clear();
+        self.bounds = Bounds::SCREEN;
+        self.theme = Theme::default();
+        self.background.clear();
+        self.script.clear();
         self.reset();
     }
 }
 
-impl Camera {
-    pub fn new(projection: Matrix4x4, view: Matrix4x4) -> Self {
-        Self {
-            projection,
-            inverse_projection: projection.inverse(),
-            view,
-            inverse_view: view.inverse(),
-        }
-    }
-
-    pub fn set_projection(&mut self, projection: Matrix4x4) {
-        self.projection = projection;
-        self.inverse_projection = self.projection.inverse();
-    }
-
-    pub fn set_view(&mut self, view: Matrix4x4) {
-        self.view = view;
-        self.inverse_view = self.view.inverse();
-    }
-}
+const LEVELS: [&str; 21] = [
+    include_str!("levels/level0.txt"),
+    include_str!("levels/level0a.txt"),
+    include_str!("levels/level0b.txt"),
+    include_str!("levels/level1.txt"),
+    include_str!("levels/level2.txt"),
+    include_str!("levels/level2b.txt"),
+    include_str!("levels/level3.txt"),
+    include_str!("levels/breeze.txt"),
+    include_str!("levels/icecream.txt"),
+    include_str!("levels/dew.txt"),
+    include_str!("levels/leaves.txt"),
+    include_str!("levels/cool_s.txt"),
+    include_str!("levels/distant_mountains.txt"),
+    include_str!("levels/love.txt"),
+    include_str!("levels/mountain_forest.txt"),
+    include_str!("levels/music.txt"),
+    include_str!("levels/snowflakes.txt"),
+    include_str!("levels/squiggles.txt"),
+    include_str!("levels/hear.txt"),
+    include_str!("levels/remember.txt"),
+    include_str!("levels/fin.txt"),
+];
+
+/// Shown on a title card as each level in `LEVELS` fades in, empty for no card.
+const LEVEL_NAMES: [&str; 21] = [
+    "", // Titlescreen
+    "", // Tutorial 1
+    "", // Tutorial 2
+    "Remember starry nights",
+    "City",
+    "The cool air",
+    "Big dipper",
+    "Summer breeze",
+    "Icecream",
+    "Morning dew",
+    "Leaves rustling",
+    "Cool s",
+    "Distant mountains",
+    "Love",
+    "Mountain forest",
+    "Music",
+    "Snowflakes",
+    "Squiggles",
+    "If you hear this",
+    "I hope you remember",
+    "Fin",
+];
 
 const LINE_RADIUS: f32 = 0.01;
+/// How far past the sides of the play area the ball can go before it's reset.
+const OFF_SCREEN_MARGIN: f32 = 1.0;
+/// How fast the ball has to hit a line, towards it, for the hit to show.
+const HARD_IMPACT_SPEED: f32 = 0.004;
+/// How faint collectibles are until they can be collected, in levels where they're collected in order.
+const LOCKED_COLLECTIBLE_ALPHA: f32 = 0.3;
+/// Samples per pixel for the post processed scene, on contexts that can multisample render targets.
+const POST_PROCESS_SAMPLES: u8 = 4;
+
+/// The ball hitting a line.
+#[derive(Debug, Clone, Copy)]
+pub struct Impact {
+    pub position: Vector3,
+    /// Points away from the line, towards the ball.
+    pub normal: Vector3,
+    pub speed: f32,
+}
 
-struct Ball {
+pub struct Ball {
     position: Vector3,
     velocity: Vector3,
     radius: f32,
@@ -150,22 +300,52 @@ struct Ball {
     alpha: f32,
     moving: bool,
     grounded: i32,
+    /// The hardest hit this tick, if any were hard enough.
+    impact: Option<Impact>,
+    trail: Trail,
+    squash: Squash,
 }
 
 impl Ball {
-    fn check_lines(&mut self, points: &[Vector3]) {
+    pub fn new(position: Vector3) -> Self {
+        Self {
+            position,
+            velocity: Vector3::ZERO,
+            radius: 0.06,
+            color: Color::new(1.0, 1.0, 1.0, 1.0),
+            alpha: 1.0,
+            moving: false,
+            grounded: 0,
+            impact: None,
+            trail: Trail::new(),
+            squash: Squash::new(),
+        }
+    }
+
+    fn check_lines(&mut self, lines: &Lines) {
+        let points = &lines.line_points;
         let len = points.len();
 
         for i in (1..len).step_by(2) {
             let (distance, p) = point_with_line_segment(self.position, points[i - 1], points[i]);
 
             if distance
-                < (self.radius + LINE_RADIUS - 0.001/* Allow ball to sink slightly into surface*/)
+                < (self.radius + lines.segment_radius(i / 2) - 0.001/* Allow ball to sink slightly into surface*/)
             {
                 let normal_of_collision = (self.position - p).normal();
                 let velocity_along_collision = Vector3::dot(normal_of_collision, self.velocity);
                 if velocity_along_collision < 0.0 {
                     self.velocity -= normal_of_collision * velocity_along_collision * 1.4;
+
+                    let speed = -velocity_along_collision;
+                    let hardest = self.impact.map(|i| i.speed).unwrap_or(HARD_IMPACT_SPEED);
+                    if speed > hardest {
+                        self.impact = Some(Impact {
+                            position: p,
+                            normal: normal_of_collision,
+                            speed,
+                        });
+                    }
                 }
 
                 self.grounded = 10;
@@ -174,37 +354,37 @@ impl Ball {
         }
     }
     // Every two Vector3s in points is a line segment
-    fn ball_physics(&mut self, points: &[Vector3], user_lines: &[Vector3]) {
+    fn ball_physics(&mut self, lines: &Lines, user_lines: &Lines, gravity: Vector3) {
         self.grounded -= 1;
-        self.velocity += Vector3::DOWN * 0.0001;
+        self.impact = None;
+        self.velocity += gravity;
 
-        self.check_lines(points);
+        self.check_lines(lines);
         self.check_lines(user_lines);
         self.position += self.velocity;
     }
 
-    fn check_for_collectibles(&mut self, level: &mut Level) -> (bool, f32) {
-        let mut collected_count = 0;
-        let mut height = 0.0;
-        for collectible in &mut level.collectibles {
+    /// Returns the indices of any collectibles that were collected.
+    fn check_for_collectibles(&mut self, level: &mut Level) -> Vec<usize> {
+        let mut collected = Vec::new();
+        for (index, collectible) in level.collectibles.iter_mut().enumerate() {
+            let next = level.collected as usize + collected.len();
             if !collectible.collected
+                && level.script.can_collect(index, next)
                 && (self.position - collectible.position).length()
                     < self.radius + collectible.radius
             {
                 log!("COLLECT!");
                 collectible.alpha = 0.1;
                 collectible.collected = true;
-                collected_count += 1;
-                height = collectible.position.y;
+                collected.push(index);
             }
         }
 
-        if collected_count > 0 {
-            level.collect(collected_count);
-            (true, height)
-        } else {
-            (false, height)
+        if !collected.is_empty() {
+            level.collect(collected.len() as u32);
         }
+        collected
     }
 }
 
@@ -221,68 +401,58 @@ fn point_with_line_segment(p: Vector3, a: Vector3, b: Vector3) -> (f32, Vector3)
 
 async fn run(app: Application, mut events: Events) {
     let window = app.new_window().build().unwrap();
-    let (mut gl_context, gl) = gl::setup(&window);
-    //  let beach_image = load_image(&gl, "beach.jpg").await.unwrap();
+    let (mut gl_context, gl, anti_aliasing) = gl::setup(&window, anti_aliasing_setting());
 
+    log!("Drawing with {:?}", capabilities().version);
     audio::setup();
-    let vert = include_str!("shaders/vert.vs");
-    let frag = include_str!("shaders/frag.fs");
-
-    let shader_program = ShaderProgram::new(&gl, vert, frag);
+    // There's nothing to draw with if the shaders don't build.
+    let mut backend = match GlBackend::new(&gl) {
+        Ok(backend) => backend,
+        Err(error) => {
+            log!("Could not build shaders: {}", error);
+            return;
+        }
+    };
+    // Levels load without waiting, so every level's decals are loaded up front.
+    // Each image is loaded once, sampled the way the first decal using it asks for.
+    let mut decal_images: Vec<(String, TextureParameters)> = Vec::new();
+    for (image, parameters) in LEVELS.iter().flat_map(|level| editor::decal_images(level)) {
+        if !decal_images.iter().any(|(loaded, _)| *loaded == image) {
+            decal_images.push((image, parameters));
+        }
+    }
+    for (image, parameters) in &decal_images {
+        match load_image(&gl, image, parameters).await {
+            Ok(texture) => backend.add_texture(image, texture),
+            Err(error) => log!("Could not load decal: {}", error),
+        }
+    }
+    let font = Font::parse(include_str!("fonts/dejavu_sans.fnt")).unwrap();
+    match load_image(&gl, &font.image, &TextureParameters::default()).await {
+        Ok(texture) => backend.add_texture(&font.image, texture),
+        Err(error) => log!("Could not load font: {}", error),
+    }
+    let mut title_card = TitleCard::new();
+    // Bloom and colour grading, skipped if render targets aren't supported.
+    let mut post_process = match PostProcess::new(&gl, POST_PROCESS_SAMPLES) {
+        Ok(post_process) => Some(post_process),
+        Err(error) => {
+            log!("Post processing is disabled: {}", error);
+            None
+        }
+    };
+    // Shaders are reloaded when they're edited in native debug builds.
+    let mut shader_watcher = ShaderWatcher::new();
+    let mut batch = ShapeBatch::new(30);
 
     let mut screen_width = 0;
     let mut screen_height = 0;
-    let mut mouse_down = false;
-    let mut right_mouse_down = false;
-    let mut mouse_position = Vector2::new(0., 0.);
-
-    let mut mouse_playback = MousePlayback::new();
-    mouse_playback.playing = true;
-
-    let mut level = Level::new(
-        Vector3::ZERO,
-        Color::new(138.0 / 255.0, 132. / 255.0, 170. / 255.0, 1.0),
-        Color::new(88.0 / 255.0, 65. / 255.0, 226. / 255.0, 1.0),
-    );
-
-    let mut lines = Lines::new(&gl);
-    let mut user_lines = Lines::new(&gl);
-
-    let level_string = [
-        include_str!("levels/level0.txt"),            // Titlescreen
-        include_str!("levels/level0a.txt"),           // Tutorial 1
-        include_str!("levels/level0b.txt"),           // Tutorial 2
-        include_str!("levels/level1.txt"),            // Remember starry nights
-        include_str!("levels/level2.txt"),            // City
-        include_str!("levels/level2b.txt"),           // The cool air
-        include_str!("levels/level3.txt"),            // Big dipper
-        include_str!("levels/breeze.txt"),            // Summer breeze
-        include_str!("levels/icecream.txt"),          // icecream
-        include_str!("levels/dew.txt"),               // Morning dew
-        include_str!("levels/leaves.txt"),            // Leaves rustling
-        include_str!("levels/cool_s.txt"),            // Cool s
-        include_str!("levels/distant_mountains.txt"), // distant mountains
-        include_str!("levels/love.txt"),              // Love
-        include_str!("levels/mountain_forest.txt"),
-        include_str!("levels/music.txt"),      // Music
-        include_str!("levels/snowflakes.txt"), // Snowflakes
-        include_str!("levels/squiggles.txt"),  // Squiggles (sort of like phone wire)
-        include_str!("levels/hear.txt"),       // "if you hear this"
-        include_str!("levels/remember.txt"),   // "I hope you remember"
-        include_str!("levels/fin.txt"),        // fin
-    ];
-
-    let mut current_level = 0;
-
-    let mut ball = Ball {
-        position: level.start_position,
-        velocity: Vector3::ZERO,
-        radius: 0.06,
-        color: Color::new(1.0, 1.0, 1.0, 1.0),
-        alpha: 1.0,
-        moving: false,
-        grounded: 0,
-    };
+    let mut game = Game::new(&LEVELS, Input::load(), scaling_setting());
+    rng::set_seed(rng::seed_from_time());
+    // Everything the player does is recorded so it can be saved and replayed, along with the seed.
+    let mut session = Session::new(rng::seed(), game.camera.design.policy);
+    pointer::setup_pointer_events();
+    resources::setup_context_events();
 
     let wind_sound = audio::load_audio("wind.wav").await.unwrap();
     let ball_sound = audio::load_audio("ball_roll.wav").await.unwrap();
@@ -290,369 +460,204 @@ async fn run(app: Application, mut events: Events) {
     // Plays forever
     ball_sound.play_ball_audio();
 
-    load_level(
-        &mut ball,
-        &level_string,
-        current_level,
-        &mut level,
-        &mut mouse_playback,
-        &mut lines,
-        &mut user_lines,
-        &wind_sound,
-    );
-    let mut circle = Mesh::new(&gl);
-    lines::update_mesh_with_circle(&gl, &mut circle, Vector3::ZERO, 1.0, 30);
-
-    let mut camera = Camera::new(
-        mat4_orthographic(-1.0, 1.0, -1.0, 1.0, 0.0, 1.0),
-        Matrix4x4::IDENTITY,
-    );
-
-    // Camera shifts everything down and to the left to make 0,0 bottom left.
-    // 2.0, 2.0 is upper right
-    camera.set_view(mat4_from_trs(
-        Vector3::new(-1.0, -1.0, 0.0),
-        Quaternion::IDENTITY,
-        Vector3::new_uniform(1.0),
-    ));
-
-    let white = Color::new(1.0, 1.0, 1.0, 1.0);
-
-    unsafe {
-        gl.enable(BLEND);
-        gl.blend_func(SRC_ALPHA, ONE_MINUS_SRC_ALPHA);
-    }
-
-    let mut fade_out = false;
-    let mut fade_in = false;
-    let mut level_alpha = 1.0;
+    // Screenshots and GIFs are taken from what's drawn, see controls.txt for their keys.
+    let mut take_screenshot = false;
+    let mut capture = Capture::new(3);
 
     let bell_sound = audio::load_audio("bell1.wav").await.unwrap();
+    // Like decals, every sound a level's script can play is loaded up front.
+    let mut script_sounds = HashMap::new();
+    for file in LEVELS.iter().flat_map(|level| editor::script_sounds(level)) {
+        if !script_sounds.contains_key(&file) {
+            match audio::load_audio(&file).await {
+                Ok(sound) => {
+                    script_sounds.insert(file, sound);
+                }
+                Err(()) => log!("Could not load sound {}", file),
+            }
+        }
+    }
 
-    // hardcoded to not do regular transitions.
-    let mut prevent_transition = false;
-    // Actually enables edit capabilties
-    let mut editor = Editor::new();
     loop {
         let event = events.next_event().await;
-        /*
-        match event {
-            Event::KeyDown { key: Key::E, .. } => {
-                editor.active = !editor.active;
-                if editor.active {
-                    // To avoid accidentally losing work
-                    prevent_transition = true;
-                }
-                log!("EDITOR ACTIVE: {:?}", editor.active);
+        let previous_mouse_position = game.input.mouse_position;
+        let change = game.input.handle_event(&event);
+        if let Some(change) = change {
+            session.record(SessionEvent::Action(change, game.input.mouse_position));
+            game.action_changed(change);
+        }
+        match change {
+            Some(InputChange::Pressed(Action::Erase)) => {
+                audio::setup(); // Setup if not already setup
             }
-            Event::KeyDown {
-                key: Key::Digit1, ..
-            } => {
-                prevent_transition = !prevent_transition;
-                log!("PREVENT TRANSITION: {:?}", prevent_transition);
+            Some(InputChange::Pressed(Action::SaveSession)) => {
+                save_file("session.txt", session.to_text().as_bytes(), "text/plain");
+            }
+            Some(InputChange::Pressed(Action::Screenshot)) => {
+                take_screenshot = true;
+            }
+            Some(InputChange::Pressed(Action::RecordGif)) => {
+                if capture.recording || capture.has_frames() {
+                    if let Some(gif) = capture.stop() {
+                        save_file("wonder.gif", &gif, "image/gif");
+                    }
+                } else {
+                    capture.start();
+                }
             }
             _ => {}
-        }*/
-        if editor.active {
-            editor.update(
+        }
+        if game.editor.active {
+            game.editor.update(
                 event.clone(),
-                &mut mouse_playback,
-                &mut level,
-                &mut lines,
-                &mut user_lines,
-                &camera,
-                screen_width,
-                screen_height,
+                change,
+                &game.input,
+                &mut game.mouse_playback,
+                &mut game.level,
+                &mut game.lines,
+                &game.camera,
+                game.screen_height,
             );
         }
         match event {
             Event::WindowCloseRequested { .. } => app.quit(),
-            Event::MouseMoved { x, y, .. } => {
-                // When the mouse check if the mouse drawing should be updated.
-                mouse_position = Vector2::new(x, y);
-
-                if mouse_down {
-                    let mouse_position = screen_to_world(
-                        mouse_position.x,
-                        mouse_position.y,
-                        &camera,
-                        screen_width,
-                        screen_height,
-                    );
-                    if !editor.active {
-                        user_lines.add_segment(mouse_position)
-                    }
-                }
-            }
-            Event::MouseButtonDown {
-                button: MouseButton::Right,
-                x,
-                y,
-                ..
-            } => {
-                audio::setup(); // Setup if not already setup
-                let mouse_pos = screen_to_world(x, y, &camera, screen_width, screen_height);
-                right_mouse_down = true;
-            }
-            Event::MouseButtonUp {
-                button: MouseButton::Right,
-                x,
-                y,
-                ..
-            } => {
-                right_mouse_down = false;
-            }
-            Event::MouseButtonDown {
-                button: MouseButton::Left,
-                x,
-                y,
-                ..
-            } => {
-                mouse_down = true;
+            Event::MouseMoved { .. } => {
+                session.record(SessionEvent::MouseMove(game.input.mouse_position));
+                game.mouse_moved(previous_mouse_position);
             }
-            Event::MouseButtonUp {
-                button: MouseButton::Left,
-                x,
-                y,
-                ..
-            } => {
-                mouse_down = false;
-                user_lines.end_segment();
-            }
-            /*
-            Event::KeyDown { key: Key::N, .. } => {
-                level.complete = true;
-            }*/
-            Event::KeyDown {
-                key: Key::Space, ..
-            } => {
-                reset(&mut ball, &mut level);
-                ball.moving = true;
-            }
-            Event::WindowResized { width, height, .. } => unsafe {
+            Event::WindowResized { width, height, .. } => {
                 screen_width = width;
                 screen_height = height;
-                gl.viewport(0, 0, width as i32, height as i32);
-                let aspect_ratio = width as f32 / height as f32;
-                camera.set_projection(mat4_orthographic(
-                    -aspect_ratio,
-                    aspect_ratio,
-                    -1.0,
-                    1.0,
-                    0.0,
-                    1.0,
-                ));
-            },
+                session.record(SessionEvent::Resize(width, height));
+                game.resize(width, height);
+                // Targets can't be made without a context, restoring it resizes them anyway.
+                let resized = post_process
+                    .as_mut()
+                    .filter(|_| !resources::context_lost())
+                    .map(|post_process| post_process.resize(&gl, width, height));
+                if let Some(Err(error)) = resized {
+                    log!("Post processing is disabled: {}", error);
+                    post_process = None;
+                }
+            }
             Event::Draw { .. } => {
-                // Check if the ball placeholder is clicked
-                if level.setup && mouse_down && !editor.active {
-                    let mouse_pos = screen_to_world(
-                        mouse_position.x,
-                        mouse_position.y,
-                        &camera,
-                        screen_width,
-                        screen_height,
-                    );
-                    if (mouse_pos - level.start_position).length() < ball.radius {
-                        reset(&mut ball, &mut level);
-                        ball.moving = true;
+                // Everything on the GPU is gone after the context is lost, so it's made again once it's back.
+                for event in resources::poll_context_events() {
+                    resources::handle_context_event(event);
+                    if event == ContextEvent::Restored {
+                        backend.restore();
+                        let restored = post_process
+                            .as_mut()
+                            .map(|post_process| post_process.restore(&gl));
+                        if let Some(Err(error)) = restored {
+                            log!("Post processing is disabled: {}", error);
+                            post_process = None;
+                        }
                     }
                 }
-
-                // Check if the ball is out of the screen bounds
-                if ball.position.x < -1.0 || ball.position.x > 3.0 || ball.position.y < 0.0 {
-                    reset(&mut ball, &mut level);
+                if shader_watcher.changed() {
+                    backend.reload_shaders();
+                    if let Some(post_process) = &mut post_process {
+                        post_process.reload_shaders(&gl);
+                    }
                 }
 
-                // Check if the ball should be spawned
-                if mouse_playback.complete && !level.setup {
-                    ball.position = level.start_position;
-                    ball.velocity = Vector3::ZERO;
-                    level.setup = true;
+                let pointer_events = pointer::poll_pointer_events();
+                for pointer_event in &pointer_events {
+                    session.record(SessionEvent::Pointer(*pointer_event));
                 }
-
-                // Eraser
-                if right_mouse_down {
-                    let mouse_position = screen_to_world(
-                        mouse_position.x,
-                        mouse_position.y,
-                        &camera,
-                        screen_width,
-                        screen_height,
+                let gamepad = cursor::poll_gamepad();
+                session.record_gamepad(gamepad);
+                let outcome = game.tick(&pointer_events, gamepad.as_ref());
+                session.end_tick();
+
+                if let Some(height) = outcome.collected {
+                    bell_sound.play(
+                        1.2 + (height as f64 / 2.0) * 2.0
+                            + rng::with_stream(RngStream::Audio, |rng| rng.next_f64()) * 0.2,
+                        2.0,
                     );
-
-                    user_lines.erase(mouse_position, 0.06);
                 }
-                mouse_playback.increment_frame();
-                if mouse_playback.playing {
-                    mouse_playback.playback(8, &mut lines, &mut level);
+                if let Some(5) | Some(7) | Some(12) = outcome.loaded_level {
+                    wind_sound.play(1.0, 5.0);
                 }
-                // First update physics
-                if level.setup && ball.moving {
-                    ball.ball_physics(&lines.line_points, &user_lines.line_points);
-                    let (hit_collectible, height) = ball.check_for_collectibles(&mut level);
-                    if hit_collectible {
-                        bell_sound.play(
-                            1.2 + (height as f64 / 2.0) * 2.0 + audio::random() * 0.2,
-                            2.0,
-                        );
+                if let Some(level) = outcome.loaded_level {
+                    title_card.show(LEVEL_NAMES[level as usize]);
+                }
+                for effect in outcome.effects {
+                    if let Effect::Sound { file, rate, gain } = effect {
+                        match script_sounds.get(&file) {
+                            Some(sound) => sound.play(rate, gain),
+                            None => log!("Sound {} wasn't loaded", file),
+                        }
                     }
                 }
 
                 // Update ball roll audio
-                let ball_roll_audio = ball.velocity.length() as f64 / 0.02;
-
-                audio::ball_audio(ball_roll_audio * 3.5 * level_alpha, 0.2 + ball_roll_audio);
-
-                // Clear the screen.
-                unsafe {
-                    gl.clear_color(19.0 / 255.0, 12.0 / 255.0, 61.0 / 255.0, 1.0);
-                    gl.clear(COLOR_BUFFER_BIT | DEPTH_BUFFER_BIT);
-                }
-
-                shader_program.use_program(&gl);
-
-                // Set the total level fade
-                shader_program.set_float(&gl, "u_fade", level_alpha as f32);
+                let ball_roll_audio = game.ball.velocity.length() as f64 / 0.02;
 
-                // Bind the camera's view and projection
-                shader_program.set_matrix(&gl, "u_view", &camera.view);
-                shader_program.set_matrix(&gl, "u_projection", &camera.projection);
-
-                // First render the level lines
-                shader_program.set_matrix(&gl, "u_model", &Matrix4x4::IDENTITY);
-                shader_program.set_color(&gl, "u_color", &level.line_color);
-
-                // Only updates if necessary
-                lines.update_mesh(&gl);
-                lines.mesh.draw(&gl);
-
-                shader_program.set_color(&gl, "u_color", &level.user_line_color);
-
-                // Only updates if necessary
-                user_lines.update_mesh(&gl);
-                user_lines.mesh.draw(&gl);
-
-                // Render the circle placeholder
-                shader_program.set_matrix(
-                    &gl,
-                    "u_model",
-                    &mat4_from_trs(
-                        level.start_position,
-                        Quaternion::IDENTITY,
-                        Vector3::new_uniform(ball.radius),
-                    ),
+                audio::ball_audio(
+                    ball_roll_audio * 3.5 * game.level_alpha,
+                    0.2 + ball_roll_audio,
                 );
-                shader_program.set_color(
-                    &gl,
-                    "u_color",
-                    &Color::new(ball.color.r, ball.color.g, ball.color.b, 0.1),
-                );
-                circle.draw(&gl);
-
-                // Then render the circle
-                shader_program.set_matrix(
-                    &gl,
-                    "u_model",
-                    &mat4_from_trs(
-                        ball.position,
-                        Quaternion::IDENTITY,
-                        Vector3::new_uniform(ball.radius),
-                    ),
-                );
-                ball.color = Color::new(1.0, 1.0, 1.0, ball.alpha);
-                if ball.alpha < 1.0 {
-                    ball.alpha += 0.015;
-                } else {
-                    ball.alpha = 1.0;
-                }
-
-                shader_program.set_color(&gl, "u_color", &ball.color);
 
-                circle.draw(&gl);
-                // Draw collectibles
-                for collectible in &mut level.collectibles {
-                    let color = Color::new(
-                        collectible.color.r,
-                        collectible.color.g,
-                        collectible.color.b,
-                        collectible.alpha,
-                    );
-                    shader_program.set_matrix(
-                        &gl,
-                        "u_model",
-                        &mat4_from_trs(
-                            collectible.position,
-                            Quaternion::IDENTITY,
-                            Vector3::new_uniform(collectible.radius),
-                        ),
+                // Analytic anti-aliasing isn't needed if whatever is drawn into multisamples.
+                let camera = &game.camera;
+                let multisampled = match &post_process {
+                    Some(post_process) => post_process.multisampled(),
+                    None => anti_aliasing != AntiAliasing::Analytic,
+                };
+                let pixel_size = if multisampled {
+                    0.0
+                } else {
+                    camera.world_units_per_pixel()
+                };
+                title_card.update();
+                // Nothing can be drawn until a lost context is restored.
+                if !resources::context_lost() {
+                    if let Some(post_process) = &post_process {
+                        post_process.begin(&gl);
+                    }
+                    let settings = FrameSettings::new(camera, game.level_alpha as f32, pixel_size);
+                    draw_scene(
+                        &mut backend,
+                        &settings,
+                        &mut batch,
+                        &game.level,
+                        &game.theme(),
+                        &mut game.lines,
+                        &mut game.user_lines,
+                        &game.ball,
+                        &game.particles,
                     );
-                    if !collectible.collected {
-                        if collectible.alpha < 1.0 {
-                            collectible.alpha += 0.04;
-                        } else {
-                            collectible.alpha = 1.0;
-                        }
-                        shader_program.set_color(&gl, "u_color", &color);
-                    } else {
-                        shader_program.set_color(
-                            &gl,
-                            "u_color",
-                            &Color::new(color.r, color.g, color.b, 0.1),
+                    title_card.draw(&mut backend, &font, &settings.visible_bounds);
+                    if game.editor.active {
+                        draw_editor_labels(
+                            &mut backend,
+                            &font,
+                            &settings.visible_bounds,
+                            game.current_level,
+                            LEVEL_NAMES[game.current_level as usize],
                         );
                     }
-                    circle.draw(&gl);
-                }
 
-                // Manage fade out
-                if fade_out && level_alpha < 0.0 {
-                    reset_ball(&mut ball, &mut level);
-                    fade_in = true;
-                    fade_out = false;
-                    level_alpha = 0.0;
-                    // This is where the actual level transition happen
-                    if current_level + 1 < level_string.len() as u32 {
-                        current_level += 1;
-                        load_level(
-                            &mut ball,
-                            &level_string,
-                            current_level,
-                            &mut level,
-                            &mut mouse_playback,
-                            &mut lines,
-                            &mut user_lines,
-                            &wind_sound,
-                        );
-                    } else {
-                        load_level(
-                            &mut ball,
-                            &level_string,
-                            current_level,
-                            &mut level,
-                            &mut mouse_playback,
-                            &mut lines,
-                            &mut user_lines,
-                            &wind_sound,
-                        );
+                    let cursor = &game.cursor;
+                    if cursor.active {
+                        let alpha = if cursor.pen_down { 1.0 } else { 0.5 };
+                        batch.clear();
+                        batch.push_circle(cursor.position, 0.015, Color::new(1.0, 1.0, 1.0, alpha));
+                        backend.draw_batch(&mut batch);
                     }
-                }
-                if fade_out {
-                    level_alpha -= 0.02;
-                }
-
-                if fade_in {
-                    level_alpha += 0.02;
-                    if level_alpha > 1.0 {
-                        fade_in = false;
-                        level_alpha = 1.0;
+                    if let Some(post_process) = &post_process {
+                        post_process.end(&gl);
                     }
-                }
 
-                // Kick off level transition
-                if level.complete && !prevent_transition {
-                    level.complete = false;
-                    fade_out = true;
-                    fade_in = false;
+                    if take_screenshot {
+                        take_screenshot = false;
+                        let frame = read_framebuffer(&gl, screen_width, screen_height);
+                        save_file("wonder.png", &frame.to_png(), "image/png");
+                    }
+                    capture.tick(|| read_framebuffer(&gl, screen_width, screen_height));
                 }
 
                 // Finally display what we've drawn.
@@ -673,57 +678,151 @@ fn reset_ball(ball: &mut Ball, level: &mut Level) {
     ball.moving = false;
     ball.position = level.start_position;
     ball.velocity = Vector3::ZERO;
+    ball.trail.clear();
+    ball.squash = Squash::new();
 }
 
-fn reset(ball: &mut Ball, level: &mut Level) {
+fn reset(ball: &mut Ball, level: &mut Level, particles: &mut Particles) {
     reset_ball(ball, level);
     // user_lines.clear();
     level.reset();
+    particles.clear();
+}
+
+/// Advances the level's playback, the ball, particles and the fades by one tick.
+/// `play_area` is the level's bounds along with whatever else is on screen.
+/// Returns the height of a collectible if one was collected,
+/// along with effects of the level's script the simulation doesn't handle itself, like sounds.
+fn update_world(
+    ball: &mut Ball,
+    level: &mut Level,
+    mouse_playback: &mut MousePlayback,
+    lines: &mut Lines,
+    user_lines: &mut Lines,
+    particles: &mut Particles,
+    play_area: Bounds,
+) -> (Option<f32>, Vec<Effect>) {
+    // Check if the ball has left the play area, with a little room to the sides to come back
+    if ball.position.x < play_area.min.x - OFF_SCREEN_MARGIN
+        || ball.position.x > play_area.max.x + OFF_SCREEN_MARGIN
+        || ball.position.y < play_area.min.y
+    {
+        reset(ball, level, particles);
+    }
+
+    // Check if the ball should be spawned
+    if mouse_playback.complete && !level.setup {
+        ball.position = level.start_position;
+        ball.velocity = Vector3::ZERO;
+        level.setup = true;
+    }
+
+    mouse_playback.increment_frame();
+    if mouse_playback.playing {
+        mouse_playback.playback(8, lines, level);
+    }
+
+    // First update physics
+    let mut collected = None;
+    let mut effects = Vec::new();
+    particles.update();
+    level.background.update();
+    ball.squash.update();
+    if level.setup && ball.moving {
+        ball.ball_physics(lines, user_lines, level.script.gravity());
+        ball.trail
+            .push(ball.position, level.theme.ball_style.trail_length);
+        if let Some(impact) = ball.impact {
+            ball.squash.hit(&impact, level.theme.ball_style.squash);
+            let color = Color::new(ball.color.r, ball.color.g, ball.color.b, 0.8);
+            particles.emit(&Emitter::impact(color), impact.position, impact.normal);
+        }
+
+        let was_complete = level.complete;
+        let indices = ball.check_for_collectibles(level);
+        for &index in &indices {
+            particles.emit(
+                &Emitter::pickup(level.theme.collectible_color),
+                level.collectibles[index].position,
+                Vector3::UP,
+            );
+        }
+        if let Some(&index) = indices.last() {
+            collected = Some(level.collectibles[index].position.y);
+        }
+
+        for effect in level.run_script(ball.position, &indices) {
+            match effect {
+                Effect::Reveal(strokes) => mouse_playback.reveal(&strokes, lines),
+                effect => effects.push(effect),
+            }
+        }
+
+        if level.complete && !was_complete {
+            for collectible in &level.collectibles {
+                let color = collectible.color;
+                particles.emit(
+                    &Emitter::level_complete(color),
+                    collectible.position,
+                    Vector3::UP,
+                );
+            }
+        }
+    }
+
+    // Fade in the ball and collectibles
+    let ball_color = level.theme.ball_color;
+    ball.color = Color::new(
+        ball_color.r,
+        ball_color.g,
+        ball_color.b,
+        ball_color.a * ball.alpha,
+    );
+    if ball.alpha < 1.0 {
+        ball.alpha += 0.015;
+    } else {
+        ball.alpha = 1.0;
+    }
+    // Collectibles that have to wait their turn stay faint.
+    let next = level.collected as usize;
+    for (index, collectible) in level.collectibles.iter_mut().enumerate() {
+        if !collectible.collected {
+            let alpha = if level.script.can_collect(index, next) {
+                1.0
+            } else {
+                LOCKED_COLLECTIBLE_ALPHA
+            };
+            if collectible.alpha < alpha {
+                collectible.alpha = (collectible.alpha + 0.04).min(alpha);
+            } else {
+                collectible.alpha = alpha;
+            }
+        }
+    }
+
+    (collected, effects)
 }
 
-fn load_level(
+/// The part of loading a level that affects the simulation, shared with replays.
+fn load_level_data(
     ball: &mut Ball,
-    data: &[&str],
-    current_level: u32,
+    data: &str,
     level: &mut Level,
     mouse_playback: &mut MousePlayback,
     lines: &mut Lines,
     user_lines: &mut Lines,
-    wind: &Audio,
-) {
-    log!("CURRENT LEVEL {:?}", current_level);
-    let data = data[current_level as usize];
+    particles: &mut Particles,
+) -> Result<(), String> {
     lines.clear();
     user_lines.clear();
     level.clear();
     mouse_playback.clear();
-    editor::load(mouse_playback, level, data);
+    particles.clear();
+    editor::load(mouse_playback, level, data)?;
+    lines.style.join = level.line_join;
+    user_lines.style.join = level.line_join;
     mouse_playback.playing = true;
     ball.position = level.start_position;
-
-    if current_level == 5 || current_level == 7 || current_level == 12 {
-        wind.play(1.0, 5.0);
-    }
-}
-
-fn screen_to_world(
-    x: f32,
-    y: f32,
-    camera: &Camera,
-    window_width: u32,
-    window_height: u32,
-) -> Vector3 {
-    let v = Vector3::new(
-        x / (window_width as f32),
-        1.0 - (y / (window_height as f32)),
-        0.0,
-    ) * 2.0
-        - Vector3::new(1.0, 1.0, 0.0);
-
-    let mut p = mat4_transform_point(
-        &camera.inverse_view,
-        mat4_transform_point(&camera.inverse_projection, v),
-    );
-    p.z = 0.0;
-    p
+    ball.trail.clear();
+    Ok(())
 }
@@ -1,24 +1,271 @@
 use crate::*;
-pub struct Mesh {
+use std::borrow::Cow;
+use std::marker::PhantomData;
+
+/// A vertex attribute, at the location `ShaderOptions::default` binds its name to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Attribute {
+    Position,
+    Color,
+    Edge,
+    Uv,
+}
+
+impl Attribute {
+    pub fn location(self) -> u32 {
+        match self {
+            Attribute::Position => 0,
+            Attribute::Color => 1,
+            Attribute::Edge => 2,
+            Attribute::Uv => 3,
+        }
+    }
+
+    pub fn floats(self) -> i32 {
+        match self {
+            Attribute::Position => 3,
+            Attribute::Color => 4,
+            Attribute::Edge => 1,
+            Attribute::Uv => 2,
+        }
+    }
+}
+
+/// A vertex's attributes in the order they're laid out, as tightly packed floats.
+#[derive(Debug, Clone, Copy)]
+pub struct VertexLayout(pub &'static [Attribute]);
+
+impl VertexLayout {
+    pub fn stride(&self) -> i32 {
+        self.0.iter().map(|attribute| attribute.floats() * 4).sum()
+    }
+
+    /// Points the attributes at the bound vertex buffer and enables them.
+    pub fn enable(&self, gl: &GL) {
+        let stride = self.stride();
+        let mut offset = 0;
+        for attribute in self.0 {
+            unsafe {
+                gl.vertex_attrib_pointer_f32(
+                    attribute.location(),
+                    attribute.floats(),
+                    FLOAT,
+                    false,
+                    stride,
+                    offset,
+                );
+                gl.enable_vertex_attrib_array(attribute.location());
+            }
+            offset += attribute.floats() * 4;
+        }
+    }
+
+    /// Disables everything but the position, which every layout has,
+    /// so the next draw doesn't read attributes its buffer doesn't have.
+    pub fn disable(&self, gl: &GL) {
+        for attribute in self.0 {
+            if *attribute != Attribute::Position {
+                unsafe { gl.disable_vertex_attrib_array(attribute.location()) };
+            }
+        }
+    }
+}
+
+/// A `#[repr(C)]` struct of floats, uploaded to meshes as is.
+pub trait Vertex: Copy {
+    const LAYOUT: VertexLayout;
+}
+
+pub type Tri = [u32; 3];
+
+/// `edge` is the distance from the vertex to the nearest edge of the shape in world units.
+/// Shaders use it to fade out the last pixel of a shape for anti-aliasing.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct MeshVertex {
+    pub position: Vector3,
+    pub edge: f32,
+}
+
+impl Vertex for MeshVertex {
+    const LAYOUT: VertexLayout = VertexLayout(&[Attribute::Position, Attribute::Edge]);
+}
+
+/// A vertex for textured meshes. `uv` is where on the texture it samples, with 0.0, 0.0 at the top left.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct TexturedVertex {
+    pub position: Vector3,
+    pub uv: Vector2,
+}
+
+impl Vertex for TexturedVertex {
+    const LAYOUT: VertexLayout = VertexLayout(&[Attribute::Position, Attribute::Uv]);
+}
+
+/// WebGL1 only has 32 bit indices with `OES_element_index_uint`, see `Capabilities::uint_indices`,
+/// so 16 bit indices are used whenever they can reach every vertex.
+/// Errors if more vertices than that are needed and the context can't index them.
+pub fn index_type(vertex_count: usize) -> Result<u32, String> {
+    index_type_for(vertex_count, capabilities().uint_indices)
+}
+
+fn index_type_for(vertex_count: usize, uint_indices: bool) -> Result<u32, String> {
+    if vertex_count <= u16::MAX as usize + 1 {
+        Ok(UNSIGNED_SHORT)
+    } else if uint_indices {
+        Ok(UNSIGNED_INT)
+    } else {
+        Err(format!(
+            "Not drawing {} vertices, more than can be indexed without 32 bit indices",
+            vertex_count
+        ))
+    }
+}
+
+fn index_size(index_type: u32) -> usize {
+    if index_type == UNSIGNED_SHORT {
+        2
+    } else {
+        4
+    }
+}
+
+/// `indices` as they're uploaded for `index_type`.
+pub fn index_bytes(indices: &[u32], index_type: u32) -> Cow<'_, [u8]> {
+    if index_type == UNSIGNED_SHORT {
+        let mut bytes = Vec::with_capacity(indices.len() * 2);
+        for &index in indices {
+            bytes.extend_from_slice(&(index as u16).to_ne_bytes());
+        }
+        Cow::Owned(bytes)
+    } else {
+        Cow::Borrowed(unsafe { slice_to_bytes(indices) })
+    }
+}
+
+fn flatten(tris: &[Tri]) -> &[u32] {
+    unsafe { std::slice::from_raw_parts(tris.as_ptr() as *const u32, tris.len() * 3) }
+}
+
+pub struct Mesh<V: Vertex = MeshVertex> {
     vertex_buffer: Buffer,
     index_buffer: Buffer,
     count: u32,
+    usage: u32,
+    // Allocated buffer sizes in bytes, only grown for dynamic meshes.
+    vertex_capacity: usize,
+    index_capacity: usize,
+    // `UNSIGNED_SHORT` or `UNSIGNED_INT`, see `index_type`.
+    index_type: u32,
+    // See `resources::generation`.
+    generation: u32,
+    vertex: PhantomData<V>,
 }
 
-pub type Tri = [u32; 3];
-impl Mesh {
-    pub fn update(&mut self, gl: &GL, vertices: &[Vector3], indices: &[Tri]) {
+impl<V: Vertex> Mesh<V> {
+    /// Leaves the mesh empty, and logs why, if the vertices can't be indexed.
+    pub fn update(&mut self, gl: &GL, vertices: &[V], indices: &[Tri]) {
+        self.recreate_if_lost(gl);
+        self.index_type = match index_type(vertices.len()) {
+            Ok(index_type) => index_type,
+            Err(error) => {
+                log!("{}", error);
+                self.count = 0;
+                return;
+            }
+        };
         unsafe {
+            let vertex_bytes = slice_to_bytes(&vertices);
+            let index_bytes = index_bytes(flatten(indices), self.index_type);
+
             gl.bind_buffer(ARRAY_BUFFER, Some(self.vertex_buffer));
-            gl.buffer_data_u8_slice(ARRAY_BUFFER, slice_to_bytes(&vertices), STATIC_DRAW);
+            gl.buffer_data_u8_slice(ARRAY_BUFFER, vertex_bytes, self.usage);
 
             gl.bind_buffer(ELEMENT_ARRAY_BUFFER, Some(self.index_buffer));
-            gl.buffer_data_u8_slice(ELEMENT_ARRAY_BUFFER, slice_to_bytes(&indices), STATIC_DRAW);
+            gl.buffer_data_u8_slice(ELEMENT_ARRAY_BUFFER, &index_bytes, self.usage);
             self.count = (indices.len() * 3) as u32;
+            self.vertex_capacity = vertex_bytes.len();
+            self.index_capacity = index_bytes.len();
         }
     }
 
-    pub fn new(gl: &GL) -> Mesh {
+    /// Uploads only the vertices and indices from the given offsets onwards.
+    /// `vertices` and `indices` are the full contents of the mesh, everything before
+    /// the offsets is expected to already be on the GPU.
+    /// Buffers grow by doubling, so appending a segment at a time stays cheap.
+    /// Like `update` the mesh is left empty if the vertices can't be indexed,
+    /// and everything is uploaded again once they can.
+    pub fn update_from(
+        &mut self,
+        gl: &GL,
+        vertices: &[V],
+        indices: &[Tri],
+        first_vertex: usize,
+        first_index: usize,
+    ) {
+        self.recreate_if_lost(gl);
+        let index_type = match index_type(vertices.len()) {
+            Ok(index_type) => index_type,
+            Err(error) => {
+                log!("{}", error);
+                self.count = 0;
+                // Nothing of the mesh can be kept when it's next uploaded.
+                self.vertex_capacity = 0;
+                self.index_capacity = 0;
+                return;
+            }
+        };
+        let indices = flatten(indices);
+        unsafe {
+            let vertex_bytes = slice_to_bytes(&vertices);
+            let index_length = indices.len() * index_size(index_type);
+
+            if vertex_bytes.len() > self.vertex_capacity
+                || index_length > self.index_capacity
+                || index_type != self.index_type
+            {
+                // Out of room or the indices got wider, so reallocate and upload everything.
+                self.index_type = index_type;
+                let index_bytes = index_bytes(indices, index_type);
+                self.vertex_capacity = (vertex_bytes.len() * 2).max(self.vertex_capacity);
+                self.index_capacity = (index_bytes.len() * 2).max(self.index_capacity);
+
+                gl.bind_buffer(ARRAY_BUFFER, Some(self.vertex_buffer));
+                gl.buffer_data_size(ARRAY_BUFFER, self.vertex_capacity as i32, self.usage);
+                gl.buffer_sub_data_u8_slice(ARRAY_BUFFER, 0, vertex_bytes);
+
+                gl.bind_buffer(ELEMENT_ARRAY_BUFFER, Some(self.index_buffer));
+                gl.buffer_data_size(ELEMENT_ARRAY_BUFFER, self.index_capacity as i32, self.usage);
+                gl.buffer_sub_data_u8_slice(ELEMENT_ARRAY_BUFFER, 0, &index_bytes);
+            } else {
+                let vertex_offset = first_vertex.min(vertices.len()) * std::mem::size_of::<V>();
+                let first_index = first_index.min(indices.len() / 3) * 3;
+
+                gl.bind_buffer(ARRAY_BUFFER, Some(self.vertex_buffer));
+                gl.buffer_sub_data_u8_slice(
+                    ARRAY_BUFFER,
+                    vertex_offset as i32,
+                    &vertex_bytes[vertex_offset..],
+                );
+
+                gl.bind_buffer(ELEMENT_ARRAY_BUFFER, Some(self.index_buffer));
+                gl.buffer_sub_data_u8_slice(
+                    ELEMENT_ARRAY_BUFFER,
+                    (first_index * index_size(index_type)) as i32,
+                    &index_bytes(&indices[first_index..], index_type),
+                );
+            }
+            self.count = indices.len() as u32;
+        }
+    }
+
+    /// A mesh that is expected to change often, see `update_from`.
+    pub fn new_dynamic(gl: &GL) -> Self {
+        Self::with_usage(gl, DYNAMIC_DRAW)
+    }
+
+    fn with_usage(gl: &GL, usage: u32) -> Self {
         unsafe {
             let vertex_buffer = gl.create_buffer().unwrap();
             let index_buffer = gl.create_buffer().unwrap();
@@ -26,28 +273,72 @@ impl Mesh {
                 vertex_buffer,
                 index_buffer,
                 count: 0,
+                usage,
+                vertex_capacity: 0,
+                index_capacity: 0,
+                index_type: UNSIGNED_SHORT,
+                generation: generation(),
+                vertex: PhantomData,
             };
 
             mesh
         }
     }
 
+    /// If the context was lost since the mesh was made, it has to be given everything again.
+    pub fn is_lost(&self) -> bool {
+        is_lost(self.generation)
+    }
+
+    /// New buffers replace ones from a lost context, empty until the next update.
+    fn recreate_if_lost(&mut self, gl: &GL) {
+        if self.is_lost() {
+            *self = Self::with_usage(gl, self.usage);
+        }
+    }
+
     pub fn draw(&self, gl: &GL) {
+        if self.count == 0 || self.is_lost() {
+            return;
+        }
         unsafe {
             gl.bind_buffer(ARRAY_BUFFER, Some(self.vertex_buffer));
             gl.bind_buffer(ELEMENT_ARRAY_BUFFER, Some(self.index_buffer));
-            gl.vertex_attrib_pointer_f32(0, 3, FLOAT, false, 4 * 3, 0);
-            //gl.vertex_attrib_pointer_f32(1, 2, FLOAT, false, 5 * 4, 3 * 4);
-            gl.enable_vertex_attrib_array(0);
-            // gl.enable_vertex_attrib_array(1);
-
-            gl.draw_elements(TRIANGLES, self.count as i32, UNSIGNED_INT, 0);
+            V::LAYOUT.enable(gl);
+            gl.draw_elements(TRIANGLES, self.count as i32, self.index_type, 0);
+            V::LAYOUT.disable(gl);
         }
     }
 }
 
-unsafe fn slice_to_bytes<T>(t: &[T]) -> &[u8] {
+impl<V: Vertex> Drop for Mesh<V> {
+    fn drop(&mut self) {
+        free(self.generation, Resource::Buffer(self.vertex_buffer));
+        free(self.generation, Resource::Buffer(self.index_buffer));
+    }
+}
+
+pub unsafe fn slice_to_bytes<T>(t: &[T]) -> &[u8] {
     let ptr = t.as_ptr() as *const u8;
     let size = std::mem::size_of::<T>() * t.len();
     std::slice::from_raw_parts(ptr, size)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn indices_are_only_as_wide_as_needed() {
+        assert_eq!(index_type_for(3, false), Ok(UNSIGNED_SHORT));
+        assert_eq!(index_type_for(65536, false), Ok(UNSIGNED_SHORT));
+        assert_eq!(index_type_for(65537, true), Ok(UNSIGNED_INT));
+        assert!(index_type_for(65537, false).is_err());
+    }
+
+    #[test]
+    fn short_indices_are_two_bytes() {
+        assert_eq!(index_bytes(&[1, 2, 3], UNSIGNED_SHORT).len(), 6);
+        assert_eq!(index_bytes(&[1, 2, 3], UNSIGNED_INT).len(), 12);
+    }
+}
@@ -11,6 +11,8 @@ pub struct MousePlayback {
     pub state: Vec<MouseState>,
     pub current_frame: u32,
     pub current_state: usize,
+    /// Counts mouse ups, so strokes can be hidden by `Script::hidden`.
+    pub current_stroke: usize,
     pub current_frame_recording: u32,
     pub playing: bool,
     pub recording: bool,
@@ -22,6 +24,7 @@ impl MousePlayback {
         Self {
             state: Vec::new(),
             current_state: 0,
+            current_stroke: 0,
             current_frame_recording: 0,
             playing: false,
             current_frame: 0,
@@ -35,16 +38,7 @@ impl MousePlayback {
     }
 
     pub fn record_collectible(&mut self, position: Vector2) {
-        let max_frame_difference = 30;
-
         if self.recording {
-            /*
-            if let Some(last_frame) = self.state.last().as_ref() {
-                if self.current_frame - last_frame.frame > max_frame_difference {
-                    self.current_frame = last_frame.frame + max_frame_difference;
-                }
-            }
-            */
             self.state.push(MouseState {
                 position,
                 frame: self.current_frame_recording,
@@ -57,17 +51,7 @@ impl MousePlayback {
     }
 
     pub fn record_mouse(&mut self, position: Vector2) {
-        let max_frame_difference = 10;
-
         if self.recording {
-            /*
-            if let Some(last_frame) = self.state.last().as_ref() {
-                if self.current_frame - last_frame.frame > max_frame_difference {
-                    self.current_frame = last_frame.frame + max_frame_difference;
-                }
-            }
-            */
-
             self.state.push(MouseState {
                 position,
                 frame: self.current_frame_recording,
@@ -101,6 +85,7 @@ impl MousePlayback {
     pub fn reset_playback(&mut self) {
         self.current_frame = 0;
         self.current_state = 0;
+        self.current_stroke = 0;
     }
 
     pub fn erase_rewind(&mut self) {
@@ -112,8 +97,7 @@ impl MousePlayback {
     }
 
     pub fn play_until_end(&mut self, lines: &mut Lines, level: &mut Level) {
-        self.current_state = 0;
-        self.current_frame = 0;
+        self.reset_playback();
         while self.current_state < self.state.len() {
             self.playback(100, lines, level)
         }
@@ -143,14 +127,15 @@ impl MousePlayback {
                 if current_state.collectible_place {
                     level.collectibles.push(Collectible {
                         position: Vector3::new(position.x, position.y, 0.0),
-                        color: Color::new(1.0, 1.0, 1.0, 1.0),
+                        color: level.theme.collectible_color,
                         radius: 0.015,
                         collected: false,
                         alpha: 0.0,
                     })
                 } else if current_state.mouse_up {
                     lines.end_segment();
-                } else {
+                    self.current_stroke += 1;
+                } else if !level.script.is_hidden(self.current_stroke) {
                     lines.add_segment(Vector3::new(position.x, position.y, 0.0));
                 }
                 self.current_state += 1;
@@ -160,4 +145,22 @@ impl MousePlayback {
             self.playing = false;
         }
     }
+
+    /// Draws strokes that playback skipped because they were hidden, all at once.
+    /// Expects playback to be complete, or the stroke it's in the middle of is broken.
+    pub fn reveal(&self, strokes: &[usize], lines: &mut Lines) {
+        lines.end_segment();
+        let mut stroke = 0;
+        for state in &self.state {
+            if state.mouse_up {
+                if strokes.contains(&stroke) {
+                    lines.end_segment();
+                }
+                stroke += 1;
+            } else if !state.collectible_place && strokes.contains(&stroke) {
+                lines.add_segment(Vector3::new(state.position.x, state.position.y, 0.0));
+            }
+        }
+        lines.end_segment();
+    }
 }
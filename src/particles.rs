@@ -0,0 +1,227 @@
+use crate::*;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Particle {
+    pub position: Vector3,
+    pub velocity: Vector3,
+    pub color: Color,
+    pub size: f32,
+    /// Ticks since the particle was emitted.
+    pub age: u32,
+    pub lifetime: u32,
+    /// Added to the velocity every tick.
+    pub gravity: f32,
+}
+
+/// Describes a burst of particles. Ranges are picked between randomly per particle.
+#[derive(Debug, Clone, Copy)]
+pub struct Emitter {
+    pub count: u32,
+    /// World units per tick.
+    pub speed: (f32, f32),
+    pub lifetime: (u32, u32),
+    pub size: (f32, f32),
+    pub gravity: f32,
+    /// How far in radians from the emitted direction particles can go, PI emits in every direction.
+    pub spread: f32,
+    pub color: Color,
+}
+
+impl Emitter {
+    /// A star being collected.
+    pub fn pickup(color: Color) -> Self {
+        Self {
+            count: 16,
+            speed: (0.002, 0.006),
+            lifetime: (30, 60),
+            size: (0.004, 0.008),
+            gravity: 0.00002,
+            spread: PI,
+            color,
+        }
+    }
+
+    /// The ball hitting a line hard.
+    pub fn impact(color: Color) -> Self {
+        Self {
+            count: 6,
+            speed: (0.001, 0.004),
+            lifetime: (10, 25),
+            size: (0.002, 0.005),
+            gravity: 0.0001,
+            spread: 1.2,
+            color,
+        }
+    }
+
+    /// Every star in a level being collected.
+    pub fn level_complete(color: Color) -> Self {
+        Self {
+            count: 40,
+            speed: (0.003, 0.012),
+            lifetime: (60, 120),
+            size: (0.004, 0.01),
+            gravity: 0.00005,
+            spread: PI,
+            color,
+        }
+    }
+}
+
+/// Plenty for every effect at once.
+pub const MAX_PARTICLES: usize = 500;
+
+/// Short lived coloured quads updated on the CPU once per tick.
+/// Randomness comes from `RngStream::Particles` so a replay produces the same particles.
+pub struct Particles {
+    pub particles: Vec<Particle>,
+    /// The oldest particles are replaced once there are this many.
+    pub max_particles: usize,
+}
+
+impl Particles {
+    pub fn new(max_particles: usize) -> Self {
+        Self {
+            particles: Vec::new(),
+            max_particles,
+        }
+    }
+
+    /// Emits a burst from `position` centered on `direction`.
+    pub fn emit(&mut self, emitter: &Emitter, position: Vector3, direction: Vector3) {
+        let base_angle = direction.y.atan2(direction.x);
+        rng::with_stream(RngStream::Particles, |rng| {
+            for _ in 0..emitter.count {
+                let angle = base_angle + rng.range(-emitter.spread, emitter.spread);
+                let speed = rng.range(emitter.speed.0, emitter.speed.1);
+                let lifetime = rng.range(emitter.lifetime.0 as f32, emitter.lifetime.1 as f32);
+                let particle = Particle {
+                    position,
+                    velocity: Vector3::new(angle.cos(), angle.sin(), 0.0) * speed,
+                    color: emitter.color,
+                    size: rng.range(emitter.size.0, emitter.size.1),
+                    age: 0,
+                    lifetime: (lifetime as u32).max(1),
+                    gravity: emitter.gravity,
+                };
+
+                if self.particles.len() < self.max_particles {
+                    self.particles.push(particle);
+                } else if let Some(oldest) = self.particles.iter_mut().max_by_key(|p| p.age) {
+                    *oldest = particle;
+                }
+            }
+        });
+    }
+
+    /// Advances every particle by one tick and removes the ones that have expired.
+    pub fn update(&mut self) {
+        for particle in &mut self.particles {
+            particle.velocity += Vector3::DOWN * particle.gravity;
+            particle.position += particle.velocity;
+            particle.age += 1;
+        }
+        self.particles.retain(|p| p.age < p.lifetime);
+    }
+
+    pub fn clear(&mut self) {
+        self.particles.clear();
+    }
+
+    /// Pushes a quad per particle, stretched along its velocity and faded by its age.
+    pub fn push_to_batch(&self, batch: &mut ShapeBatch) {
+        for particle in &self.particles {
+            let fade = 1.0 - particle.age as f32 / particle.lifetime as f32;
+            let color = Color::new(
+                particle.color.r,
+                particle.color.g,
+                particle.color.b,
+                particle.color.a * fade,
+            );
+
+            let speed = particle.velocity.length();
+            let forward = if speed > 0.0 {
+                particle.velocity / speed
+            } else {
+                Vector3::new(1.0, 0.0, 0.0)
+            };
+            let side = Vector3::new(-forward.y, forward.x, 0.0) * particle.size;
+            let forward = forward * (particle.size + speed);
+
+            let p = particle.position;
+            batch.push_quad(
+                [
+                    p - forward - side,
+                    p + forward - side,
+                    p + forward + side,
+                    p - forward + side,
+                ],
+                [color; 4],
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WHITE: Color = Color::new(1.0, 1.0, 1.0, 1.0);
+
+    #[test]
+    fn particles_expire_after_their_lifetime() {
+        rng::set_seed(1);
+        let mut particles = Particles::new(MAX_PARTICLES);
+        let emitter = Emitter::impact(WHITE);
+        particles.emit(&emitter, Vector3::ZERO, Vector3::UP);
+        assert_eq!(particles.particles.len(), emitter.count as usize);
+        for particle in &particles.particles {
+            assert!(
+                particle.lifetime >= emitter.lifetime.0 && particle.lifetime < emitter.lifetime.1
+            );
+        }
+
+        for tick in 1..=emitter.lifetime.1 {
+            particles.update();
+            for particle in &particles.particles {
+                assert_eq!(particle.age, tick);
+                assert!(particle.age < particle.lifetime);
+            }
+        }
+        assert!(particles.particles.is_empty());
+    }
+
+    #[test]
+    fn same_seed_same_particles() {
+        let emit = || {
+            rng::set_seed(2);
+            let mut particles = Particles::new(MAX_PARTICLES);
+            particles.emit(&Emitter::pickup(WHITE), Vector3::ZERO, Vector3::UP);
+            particles.update();
+            particles
+                .particles
+                .iter()
+                .map(|p| (p.position.x, p.position.y, p.lifetime))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(emit(), emit());
+    }
+
+    #[test]
+    fn oldest_particles_are_replaced_at_the_cap() {
+        rng::set_seed(3);
+        let mut particles = Particles::new(20);
+        let emitter = Emitter::pickup(WHITE);
+        particles.emit(&emitter, Vector3::ZERO, Vector3::UP);
+        particles.update();
+        particles.emit(&emitter, Vector3::ZERO, Vector3::UP);
+
+        assert_eq!(particles.particles.len(), 20);
+        // The second burst of 16 replaced all but 4 of the first.
+        let old = particles.particles.iter().filter(|p| p.age == 1).count();
+        assert_eq!(old, 4);
+
+        particles.clear();
+        assert!(particles.particles.is_empty());
+    }
+}
@@ -0,0 +1,348 @@
+use crate::*;
+
+// kettlewin only reports the mouse, so touches and pens are read from the browser's pointer events.
+#[cfg(target_arch = "wasm32")]
+mod pointer_web {
+    use super::*;
+    use wasm_bindgen::prelude::*;
+
+    #[wasm_bindgen(module = "/src/helpers.js")]
+    extern "C" {
+        fn setupPointerEvents();
+        fn takePointerEvents() -> Box<[f32]>;
+    }
+
+    pub fn setup_pointer_events() {
+        setupPointerEvents();
+    }
+
+    /// Every pointer event since the last call, oldest first.
+    pub fn poll_pointer_events() -> Vec<PointerEvent> {
+        // Each event is packed as phase, id, kind, x, y, pressure, eraser.
+        takePointerEvents()
+            .chunks(7)
+            .map(|e| PointerEvent {
+                phase: match e[0] as u32 {
+                    0 => PointerPhase::Down,
+                    1 => PointerPhase::Move,
+                    2 => PointerPhase::Up,
+                    _ => PointerPhase::Cancel,
+                },
+                id: e[1] as i32,
+                kind: match e[2] as u32 {
+                    0 => PointerKind::Mouse,
+                    1 => PointerKind::Touch,
+                    _ => PointerKind::Pen,
+                },
+                position: Vector2::new(e[3], e[4]),
+                pressure: e[5],
+                eraser: e[6] != 0.0,
+            })
+            .collect()
+    }
+}
+#[cfg(target_arch = "wasm32")]
+pub use pointer_web::*;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn setup_pointer_events() {}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn poll_pointer_events() -> Vec<PointerEvent> {
+    Vec::new()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PointerKind {
+    Mouse,
+    Touch,
+    Pen,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PointerPhase {
+    Down,
+    Move,
+    Up,
+    Cancel,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PointerEvent {
+    pub id: i32,
+    pub kind: PointerKind,
+    pub phase: PointerPhase,
+    /// In window pixels from the top left, like mouse events.
+    pub position: Vector2,
+    /// 0.0 to 1.0, devices without pressure report 0.5 while pressed.
+    pub pressure: f32,
+    /// The pen is flipped over or its eraser button is held.
+    pub eraser: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PointerAction {
+    Draw,
+    Erase,
+}
+
+struct ActivePointer {
+    id: i32,
+    kind: PointerKind,
+    action: PointerAction,
+    position: Vector2,
+    pressure: f32,
+    start_position: Vector2,
+    held_ticks: u32,
+}
+
+/// Pixels a touch can wander and still count as a long press.
+const LONG_PRESS_SLOP: f32 = 10.0;
+
+/// Turns touches and pens into drawing and erasing.
+/// One finger or a pen draws. A second finger, holding a finger still,
+/// or the pen's eraser end erases instead.
+pub struct PointerInput {
+    pointers: Vec<ActivePointer>,
+    /// How long a touch has to be held in place to become an eraser.
+    pub long_press_ticks: u32,
+    stroke_ended: bool,
+}
+
+impl PointerInput {
+    pub fn new() -> Self {
+        Self {
+            pointers: Vec::new(),
+            long_press_ticks: 40,
+            stroke_ended: false,
+        }
+    }
+
+    pub fn handle_event(&mut self, event: &PointerEvent) {
+        match event.phase {
+            PointerPhase::Down => {
+                let mut action = if event.eraser {
+                    PointerAction::Erase
+                } else {
+                    PointerAction::Draw
+                };
+
+                if event.kind == PointerKind::Touch {
+                    let other_touches = self
+                        .pointers
+                        .iter_mut()
+                        .filter(|p| p.kind == PointerKind::Touch);
+                    for pointer in other_touches {
+                        // Two fingers erase, so a stroke started by the first one stops.
+                        if pointer.action == PointerAction::Draw {
+                            self.stroke_ended = true;
+                        }
+                        pointer.action = PointerAction::Erase;
+                        action = PointerAction::Erase;
+                    }
+                }
+
+                self.pointers.push(ActivePointer {
+                    id: event.id,
+                    kind: event.kind,
+                    action,
+                    position: event.position,
+                    pressure: event.pressure,
+                    start_position: event.position,
+                    held_ticks: 0,
+                });
+            }
+            PointerPhase::Move => {
+                if let Some(pointer) = self.pointers.iter_mut().find(|p| p.id == event.id) {
+                    pointer.position = event.position;
+                    pointer.pressure = event.pressure;
+                    // Pens can be flipped over without being lifted.
+                    if pointer.kind == PointerKind::Pen {
+                        let action = if event.eraser {
+                            PointerAction::Erase
+                        } else {
+                            PointerAction::Draw
+                        };
+                        if pointer.action != action {
+                            self.stroke_ended = true;
+                            pointer.action = action;
+                        }
+                    }
+                }
+            }
+            PointerPhase::Up | PointerPhase::Cancel => {
+                if let Some(i) = self.pointers.iter().position(|p| p.id == event.id) {
+                    if self.pointers[i].action == PointerAction::Draw {
+                        self.stroke_ended = true;
+                    }
+                    self.pointers.remove(i);
+                }
+            }
+        }
+    }
+
+    /// Call once per frame, to time long presses.
+    pub fn tick(&mut self) {
+        for pointer in &mut self.pointers {
+            pointer.held_ticks += 1;
+            if pointer.kind == PointerKind::Touch
+                && pointer.action == PointerAction::Draw
+                && pointer.held_ticks == self.long_press_ticks
+                && (pointer.position - pointer.start_position).length() < LONG_PRESS_SLOP
+            {
+                pointer.action = PointerAction::Erase;
+                self.stroke_ended = true;
+            }
+        }
+    }
+
+    /// Where a stroke is being drawn and how hard the pointer is pressed.
+    pub fn drawing(&self) -> Option<(Vector2, f32)> {
+        self.pointers
+            .iter()
+            .find(|p| p.action == PointerAction::Draw)
+            .map(|p| {
+                // Only pens report a meaningful pressure.
+                let pressure = if p.kind == PointerKind::Pen {
+                    p.pressure
+                } else {
+                    0.5
+                };
+                (p.position, pressure)
+            })
+    }
+
+    pub fn erasing(&self) -> impl Iterator<Item = Vector2> + '_ {
+        self.pointers
+            .iter()
+            .filter(|p| p.action == PointerAction::Erase)
+            .map(|p| p.position)
+    }
+
+    /// Whether the current stroke ended since the last call.
+    pub fn take_stroke_ended(&mut self) -> bool {
+        std::mem::replace(&mut self.stroke_ended, false)
+    }
+}
+
+/// Line radius for a pressure, where the default 0.5 draws the usual width.
+pub fn pressure_radius(pressure: f32) -> f32 {
+    LINE_RADIUS * (0.5 + pressure.max(0.0).min(1.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(phase: PointerPhase, id: i32, kind: PointerKind, x: f32) -> PointerEvent {
+        PointerEvent {
+            id,
+            kind,
+            phase,
+            position: Vector2::new(x, 0.0),
+            pressure: 0.5,
+            eraser: false,
+        }
+    }
+
+    fn pen(phase: PointerPhase, eraser: bool, pressure: f32) -> PointerEvent {
+        PointerEvent {
+            eraser,
+            pressure,
+            ..event(phase, 1, PointerKind::Pen, 0.0)
+        }
+    }
+
+    /// Where along x a stroke is drawn and with what pressure.
+    fn drawing(input: &PointerInput) -> Option<(f32, f32)> {
+        input
+            .drawing()
+            .map(|(position, pressure)| (position.x, pressure))
+    }
+
+    #[test]
+    fn one_finger_draws() {
+        let mut input = PointerInput::new();
+        input.handle_event(&event(PointerPhase::Down, 1, PointerKind::Touch, 0.0));
+        input.handle_event(&event(PointerPhase::Move, 1, PointerKind::Touch, 5.0));
+        assert_eq!(drawing(&input), Some((5.0, 0.5)));
+        assert_eq!(input.erasing().count(), 0);
+        assert!(!input.take_stroke_ended());
+
+        input.handle_event(&event(PointerPhase::Up, 1, PointerKind::Touch, 5.0));
+        assert_eq!(drawing(&input), None);
+        assert!(input.take_stroke_ended());
+        // Only reported once.
+        assert!(!input.take_stroke_ended());
+    }
+
+    #[test]
+    fn two_fingers_erase() {
+        let mut input = PointerInput::new();
+        input.handle_event(&event(PointerPhase::Down, 1, PointerKind::Touch, 0.0));
+        input.handle_event(&event(PointerPhase::Down, 2, PointerKind::Touch, 50.0));
+
+        assert_eq!(drawing(&input), None);
+        assert_eq!(input.erasing().count(), 2);
+        // The first finger's stroke stops rather than carrying on after the erasing.
+        assert!(input.take_stroke_ended());
+
+        // Lifting an eraser doesn't end a stroke.
+        input.handle_event(&event(PointerPhase::Cancel, 2, PointerKind::Touch, 50.0));
+        assert!(!input.take_stroke_ended());
+        assert_eq!(input.erasing().count(), 1);
+    }
+
+    #[test]
+    fn holding_a_finger_still_erases() {
+        let mut input = PointerInput::new();
+        input.handle_event(&event(PointerPhase::Down, 1, PointerKind::Touch, 0.0));
+        input.handle_event(&event(PointerPhase::Move, 1, PointerKind::Touch, 9.0));
+        for _ in 0..39 {
+            input.tick();
+        }
+        assert!(input.drawing().is_some());
+
+        input.tick();
+        assert_eq!(drawing(&input), None);
+        assert_eq!(input.erasing().count(), 1);
+        assert!(input.take_stroke_ended());
+    }
+
+    #[test]
+    fn moving_a_finger_is_not_a_long_press() {
+        let mut input = PointerInput::new();
+        input.handle_event(&event(PointerPhase::Down, 1, PointerKind::Touch, 0.0));
+        input.handle_event(&event(PointerPhase::Move, 1, PointerKind::Touch, 10.0));
+        for _ in 0..100 {
+            input.tick();
+        }
+        assert!(input.drawing().is_some());
+        assert!(!input.take_stroke_ended());
+    }
+
+    #[test]
+    fn flipping_a_pen_erases() {
+        let mut input = PointerInput::new();
+        input.handle_event(&pen(PointerPhase::Down, false, 0.8));
+        assert_eq!(drawing(&input), Some((0.0, 0.8)));
+
+        input.handle_event(&pen(PointerPhase::Move, true, 0.8));
+        assert_eq!(drawing(&input), None);
+        assert_eq!(input.erasing().count(), 1);
+        assert!(input.take_stroke_ended());
+
+        input.handle_event(&pen(PointerPhase::Move, false, 0.3));
+        assert_eq!(drawing(&input), Some((0.0, 0.3)));
+        assert!(input.take_stroke_ended());
+    }
+
+    #[test]
+    fn mice_draw_without_pressure() {
+        let mut input = PointerInput::new();
+        let mut down = event(PointerPhase::Down, 1, PointerKind::Mouse, 0.0);
+        down.pressure = 1.0;
+        input.handle_event(&down);
+        assert_eq!(drawing(&input), Some((0.0, 0.5)));
+    }
+}
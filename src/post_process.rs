@@ -0,0 +1,416 @@
+use crate::*;
+
+/// A texture that can be drawn into instead of the screen.
+pub struct RenderTarget {
+    pub framebuffer: Framebuffer,
+    pub texture: Texture,
+    pub width: u32,
+    pub height: u32,
+    internal_format: u32,
+    // Drawn into instead of the texture when multisampling, see `resolve`.
+    multisample: Option<(Framebuffer, Renderbuffer)>,
+    // See `resources::generation`.
+    generation: u32,
+}
+
+impl RenderTarget {
+    /// Uses a floating point texture where it's supported so bright colours aren't clipped before bloom,
+    /// otherwise 8 bits per channel which WebGL1 can always draw into.
+    pub fn new(gl: &GL, width: u32, height: u32) -> Result<Self, String> {
+        if capabilities().float_render_targets {
+            if let Ok(target) = Self::with_format(gl, width, height, RGBA16F, FLOAT) {
+                return Ok(target);
+            }
+        }
+        Self::with_format(gl, width, height, RGBA, UNSIGNED_BYTE)
+    }
+
+    /// Like `new`, but drawn into with `samples` samples per pixel where the context can,
+    /// clamped to what it allows. Call `resolve` before using the texture.
+    pub fn multisampled(gl: &GL, width: u32, height: u32, samples: u8) -> Result<Self, String> {
+        let mut target = Self::new(gl, width, height)?;
+        if samples > 1 && capabilities().msaa_renderbuffers {
+            target.multisample = Some(target.create_multisample(gl, samples)?);
+        }
+        Ok(target)
+    }
+
+    fn create_multisample(
+        &self,
+        gl: &GL,
+        samples: u8,
+    ) -> Result<(Framebuffer, Renderbuffer), String> {
+        // Resolving needs the same format on both sides, and unsized RGBA textures are RGBA8.
+        let format = if self.internal_format == RGBA {
+            RGBA8
+        } else {
+            self.internal_format
+        };
+        unsafe {
+            let samples = (samples as i32).min(gl.get_parameter_i32(MAX_SAMPLES));
+            let renderbuffer = gl.create_renderbuffer()?;
+            gl.bind_renderbuffer(RENDERBUFFER, Some(renderbuffer));
+            gl.renderbuffer_storage_multisample(
+                RENDERBUFFER,
+                samples,
+                format,
+                self.width as i32,
+                self.height as i32,
+            );
+            gl.bind_renderbuffer(RENDERBUFFER, None);
+
+            let framebuffer = gl.create_framebuffer()?;
+            gl.bind_framebuffer(FRAMEBUFFER, Some(framebuffer));
+            gl.framebuffer_renderbuffer(
+                FRAMEBUFFER,
+                COLOR_ATTACHMENT0,
+                RENDERBUFFER,
+                Some(renderbuffer),
+            );
+            let status = gl.check_framebuffer_status(FRAMEBUFFER);
+            gl.bind_framebuffer(FRAMEBUFFER, None);
+
+            if status != FRAMEBUFFER_COMPLETE {
+                gl.delete_framebuffer(framebuffer);
+                gl.delete_renderbuffer(renderbuffer);
+                return Err(format!(
+                    "Multisampled render target is incomplete: {:#x}",
+                    status
+                ));
+            }
+            Ok((framebuffer, renderbuffer))
+        }
+    }
+
+    fn with_format(
+        gl: &GL,
+        width: u32,
+        height: u32,
+        internal_format: u32,
+        data_type: u32,
+    ) -> Result<Self, String> {
+        unsafe {
+            let texture = gl.create_texture()?;
+            gl.bind_texture(TEXTURE_2D, Some(texture));
+            gl.tex_image_2d(
+                TEXTURE_2D,
+                0, /* mip level */
+                internal_format as i32,
+                width as i32,
+                height as i32,
+                0,
+                RGBA,
+                data_type,
+                None,
+            );
+            // WebGL1 can only sample textures that aren't a power of two without mipmaps and with clamping.
+            gl.tex_parameter_i32(TEXTURE_2D, TEXTURE_MIN_FILTER, LINEAR as i32);
+            gl.tex_parameter_i32(TEXTURE_2D, TEXTURE_MAG_FILTER, LINEAR as i32);
+            gl.tex_parameter_i32(TEXTURE_2D, TEXTURE_WRAP_S, CLAMP_TO_EDGE as i32);
+            gl.tex_parameter_i32(TEXTURE_2D, TEXTURE_WRAP_T, CLAMP_TO_EDGE as i32);
+            gl.bind_texture(TEXTURE_2D, None);
+
+            let framebuffer = gl.create_framebuffer()?;
+            gl.bind_framebuffer(FRAMEBUFFER, Some(framebuffer));
+            gl.framebuffer_texture_2d(FRAMEBUFFER, COLOR_ATTACHMENT0, TEXTURE_2D, Some(texture), 0);
+            let status = gl.check_framebuffer_status(FRAMEBUFFER);
+            gl.bind_framebuffer(FRAMEBUFFER, None);
+
+            if status != FRAMEBUFFER_COMPLETE {
+                gl.delete_framebuffer(framebuffer);
+                gl.delete_texture(texture);
+                return Err(format!("Render target is incomplete: {:#x}", status));
+            }
+
+            Ok(Self {
+                framebuffer,
+                texture,
+                width,
+                height,
+                internal_format,
+                multisample: None,
+                generation: generation(),
+            })
+        }
+    }
+
+    pub fn is_multisampled(&self) -> bool {
+        self.multisample.is_some()
+    }
+
+    /// Draws into this target until another is bound, covering all of it.
+    pub fn bind(&self, gl: &GL) {
+        let framebuffer = match self.multisample {
+            Some((framebuffer, _)) => framebuffer,
+            None => self.framebuffer,
+        };
+        unsafe {
+            gl.bind_framebuffer(FRAMEBUFFER, Some(framebuffer));
+            gl.viewport(0, 0, self.width as i32, self.height as i32);
+        }
+    }
+
+    /// Averages the samples into the texture. Does nothing if the target doesn't multisample.
+    pub fn resolve(&self, gl: &GL) {
+        if let Some((framebuffer, _)) = self.multisample {
+            let (width, height) = (self.width as i32, self.height as i32);
+            unsafe {
+                gl.bind_framebuffer(READ_FRAMEBUFFER, Some(framebuffer));
+                gl.bind_framebuffer(DRAW_FRAMEBUFFER, Some(self.framebuffer));
+                gl.blit_framebuffer(
+                    0,
+                    0,
+                    width,
+                    height,
+                    0,
+                    0,
+                    width,
+                    height,
+                    COLOR_BUFFER_BIT,
+                    NEAREST,
+                );
+                gl.bind_framebuffer(FRAMEBUFFER, None);
+            }
+        }
+    }
+}
+
+impl Drop for RenderTarget {
+    fn drop(&mut self) {
+        free(self.generation, Resource::Framebuffer(self.framebuffer));
+        free(self.generation, Resource::Texture(self.texture));
+        if let Some((framebuffer, renderbuffer)) = self.multisample {
+            free(self.generation, Resource::Framebuffer(framebuffer));
+            free(self.generation, Resource::Renderbuffer(renderbuffer));
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PostSettings {
+    /// How bright a colour's brightest channel has to be to start glowing.
+    pub bloom_threshold: f32,
+    pub bloom_intensity: f32,
+    /// More passes spread the glow further.
+    pub blur_passes: u32,
+    pub exposure: f32,
+    pub contrast: f32,
+    /// 0.0 is greyscale.
+    pub saturation: f32,
+    /// Multiplies the final colour.
+    pub tint: Color,
+}
+
+impl Default for PostSettings {
+    fn default() -> Self {
+        Self {
+            bloom_threshold: 0.7,
+            bloom_intensity: 1.2,
+            blur_passes: 3,
+            exposure: 1.0,
+            contrast: 1.05,
+            saturation: 1.1,
+            tint: Color::new(1.0, 1.0, 1.0, 1.0),
+        }
+    }
+}
+
+/// Draws the frame into a texture then to the screen with bloom and colour grading,
+/// so the ball, collectibles and stars glow. Call `begin` before drawing and `end` after.
+pub struct PostProcess {
+    pub settings: PostSettings,
+    scene: Option<RenderTarget>,
+    // Half resolution targets the bloom is blurred back and forth between.
+    bloom: Option<(RenderTarget, RenderTarget)>,
+    bright_program: ShaderProgram,
+    blur_program: ShaderProgram,
+    composite_program: ShaderProgram,
+    quad: Buffer,
+    /// Per pixel of the scene, where the context can multisample render targets.
+    samples: u8,
+    // Of `quad`, see `resources::generation`.
+    generation: u32,
+    width: u32,
+    height: u32,
+}
+
+impl PostProcess {
+    /// The scene is multisampled with `samples` where the context allows, 0 turns it off.
+    pub fn new(gl: &GL, samples: u8) -> Result<Self, ShaderError> {
+        let program = |fragment_file| {
+            ShaderProgram::from_files(gl, "post.vs", fragment_file, ShaderOptions::default())
+        };
+        let bright_program = program("bright.fs")?;
+        let blur_program = program("blur.fs")?;
+        let composite_program = program("composite.fs")?;
+
+        Ok(Self {
+            settings: PostSettings::default(),
+            scene: None,
+            bloom: None,
+            bright_program,
+            blur_program,
+            composite_program,
+            quad: create_quad(gl),
+            samples,
+            generation: generation(),
+            width: 0,
+            height: 0,
+        })
+    }
+
+    /// Makes everything again at the same size after the context was lost.
+    pub fn restore(&mut self, gl: &GL) -> Result<(), String> {
+        for program in &mut [
+            &mut self.bright_program,
+            &mut self.blur_program,
+            &mut self.composite_program,
+        ] {
+            program.reload(gl).map_err(|error| error.to_string())?;
+        }
+        self.quad = create_quad(gl);
+        self.generation = generation();
+        self.resize(gl, self.width, self.height)
+    }
+
+    /// Rebuilds the shaders from their files, keeping the old ones if they don't build.
+    pub fn reload_shaders(&mut self, gl: &GL) {
+        for program in &mut [
+            &mut self.bright_program,
+            &mut self.blur_program,
+            &mut self.composite_program,
+        ] {
+            if let Err(error) = program.reload(gl) {
+                log!("{}", error);
+            }
+        }
+    }
+
+    /// Call when the window changes size. If this fails post processing should be skipped.
+    pub fn resize(&mut self, gl: &GL, width: u32, height: u32) -> Result<(), String> {
+        self.scene = None;
+        self.bloom = None;
+        self.width = width;
+        self.height = height;
+
+        let (bloom_width, bloom_height) = ((width / 2).max(1), (height / 2).max(1));
+        self.scene = Some(RenderTarget::multisampled(
+            gl,
+            width.max(1),
+            height.max(1),
+            self.samples,
+        )?);
+        self.bloom = Some((
+            RenderTarget::new(gl, bloom_width, bloom_height)?,
+            RenderTarget::new(gl, bloom_width, bloom_height)?,
+        ));
+        Ok(())
+    }
+
+    /// If shapes don't need analytic anti-aliasing because the scene multisamples.
+    pub fn multisampled(&self) -> bool {
+        self.scene
+            .as_ref()
+            .map_or(false, |scene| scene.is_multisampled())
+    }
+
+    /// Everything drawn until `end` goes into the scene's texture.
+    pub fn begin(&self, gl: &GL) {
+        if let Some(scene) = &self.scene {
+            scene.bind(gl);
+        }
+    }
+
+    /// Draws the scene to the screen with bloom.
+    pub fn end(&self, gl: &GL) {
+        let (scene, (a, b)) = match (&self.scene, &self.bloom) {
+            (Some(scene), Some(bloom)) => (scene, bloom),
+            _ => return,
+        };
+        let settings = &self.settings;
+        scene.resolve(gl);
+
+        unsafe {
+            gl.disable(BLEND);
+
+            // Keep only what's bright enough to glow.
+            a.bind(gl);
+            self.bright_program.use_program(gl);
+            self.bright_program
+                .set_float(gl, "u_threshold", settings.bloom_threshold);
+            self.draw_texture(gl, &self.bright_program, "u_texture", scene);
+
+            // Then blur it back and forth between the bloom targets.
+            self.blur_program.use_program(gl);
+            for _ in 0..settings.blur_passes {
+                b.bind(gl);
+                self.blur_program.set_vector2(
+                    gl,
+                    "u_direction",
+                    Vector2::new(1.0 / a.width as f32, 0.0),
+                );
+                self.draw_texture(gl, &self.blur_program, "u_texture", a);
+
+                a.bind(gl);
+                self.blur_program.set_vector2(
+                    gl,
+                    "u_direction",
+                    Vector2::new(0.0, 1.0 / b.height as f32),
+                );
+                self.draw_texture(gl, &self.blur_program, "u_texture", b);
+            }
+
+            gl.bind_framebuffer(FRAMEBUFFER, None);
+            gl.viewport(0, 0, self.width as i32, self.height as i32);
+            let program = &self.composite_program;
+            program.use_program(gl);
+            gl.active_texture(TEXTURE1);
+            gl.bind_texture(TEXTURE_2D, Some(a.texture));
+            program.set_int(gl, "u_bloom", 1);
+            program.set_float(gl, "u_bloom_intensity", settings.bloom_intensity);
+            program.set_float(gl, "u_exposure", settings.exposure);
+            program.set_float(gl, "u_contrast", settings.contrast);
+            program.set_float(gl, "u_saturation", settings.saturation);
+            program.set_color(gl, "u_tint", &settings.tint);
+            self.draw_texture(gl, program, "u_scene", scene);
+
+            gl.active_texture(TEXTURE1);
+            gl.bind_texture(TEXTURE_2D, None);
+            gl.active_texture(TEXTURE0);
+            gl.enable(BLEND);
+        }
+    }
+
+    /// Draws the quad with `target`'s texture bound to `name` on the first texture unit.
+    /// Expects `program` to already be in use.
+    fn draw_texture(&self, gl: &GL, program: &ShaderProgram, name: &str, target: &RenderTarget) {
+        unsafe {
+            gl.active_texture(TEXTURE0);
+            gl.bind_texture(TEXTURE_2D, Some(target.texture));
+            program.set_int(gl, name, 0);
+
+            gl.bind_buffer(ARRAY_BUFFER, Some(self.quad));
+            gl.vertex_attrib_pointer_f32(0, 2, FLOAT, false, 2 * 4, 0);
+            gl.enable_vertex_attrib_array(0);
+            gl.draw_arrays(TRIANGLE_STRIP, 0, 4);
+            gl.bind_texture(TEXTURE_2D, None);
+        }
+    }
+}
+
+impl Drop for PostProcess {
+    fn drop(&mut self) {
+        free(self.generation, Resource::Buffer(self.quad));
+    }
+}
+
+/// Two triangles covering the screen.
+fn create_quad(gl: &GL) -> Buffer {
+    let corners: [f32; 8] = [-1.0, -1.0, 1.0, -1.0, -1.0, 1.0, 1.0, 1.0];
+    unsafe {
+        let quad = gl.create_buffer().unwrap();
+        gl.bind_buffer(ARRAY_BUFFER, Some(quad));
+        gl.buffer_data_u8_slice(ARRAY_BUFFER, slice_to_bytes(&corners), STATIC_DRAW);
+        quad
+    }
+}
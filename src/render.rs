@@ -0,0 +1,307 @@
+use crate::*;
+use std::collections::HashMap;
+
+/// Per frame values shared by everything drawn in the frame.
+#[derive(Debug, Clone, Copy)]
+pub struct FrameSettings {
+    pub view: Matrix4x4,
+    pub projection: Matrix4x4,
+    /// Where on the screen the world is drawn, the rest is cleared.
+    pub viewport: Viewport,
+    /// The part of the world inside the viewport.
+    pub visible_bounds: Bounds,
+    /// Multiplies every colour, used to fade whole levels in and out.
+    pub fade: f32,
+    /// World units covered by one pixel, 0.0 disables anti-aliasing.
+    pub pixel_size: f32,
+}
+
+impl FrameSettings {
+    pub fn new(camera: &Camera, fade: f32, pixel_size: f32) -> Self {
+        Self {
+            view: camera.view,
+            projection: camera.projection,
+            viewport: camera.viewport,
+            visible_bounds: camera.visible_bounds(),
+            fade,
+            pixel_size,
+        }
+    }
+}
+
+/// Something that can draw the game's frames, either to a GL context or to memory.
+pub trait RenderBackend {
+    fn begin_frame(&mut self, clear_color: Color, settings: &FrameSettings);
+    fn draw_lines(&mut self, lines: &mut Lines, color: Color);
+    fn draw_batch(&mut self, batch: &mut ShapeBatch);
+    /// Draws the texture loaded as `image` over `corners`, counter-clockwise from the bottom left.
+    /// `uvs` are where each corner is in the image, from 0.0 to 1.0 starting at its top left,
+    /// and past that where it wraps. Does nothing if it wasn't loaded.
+    fn draw_sprite(&mut self, image: &str, corners: [Vector3; 4], uvs: [Vector2; 4], color: Color);
+    /// Does nothing if the font's atlas wasn't loaded.
+    fn draw_text(&mut self, font: &Font, text: &PlacedText, color: Color);
+}
+
+pub struct GlBackend<'a> {
+    gl: &'a GL,
+    line_program: ShaderProgram,
+    batch_program: ShaderProgram,
+    sprite_program: ShaderProgram,
+    text_program: ShaderProgram,
+    // Rebuilt for every sprite and piece of text.
+    textured_mesh: Mesh<TexturedVertex>,
+    textures: HashMap<String, Image>,
+    settings: Option<FrameSettings>,
+}
+
+impl<'a> GlBackend<'a> {
+    pub fn new(gl: &'a GL) -> Result<Self, ShaderError> {
+        let line_program =
+            ShaderProgram::from_files(gl, "vert.vs", "frag.fs", ShaderOptions::default())?;
+        let batch_program =
+            ShaderProgram::from_files(gl, "batch.vs", "batch.fs", ShaderOptions::default())?;
+        let sprite_program =
+            ShaderProgram::from_files(gl, "sprite.vs", "sprite.fs", ShaderOptions::default())?;
+        let text_program =
+            ShaderProgram::from_files(gl, "sprite.vs", "text.fs", ShaderOptions::default())?;
+
+        set_blending(gl);
+
+        Ok(Self {
+            gl,
+            line_program,
+            batch_program,
+            sprite_program,
+            text_program,
+            textured_mesh: Mesh::new_dynamic(gl),
+            textures: HashMap::new(),
+            settings: None,
+        })
+    }
+
+    /// Makes everything again after the context was lost.
+    /// Shaders or textures that can't be made again are logged and left out.
+    pub fn restore(&mut self) {
+        set_blending(self.gl);
+        self.reload_shaders();
+        for (name, image) in &mut self.textures {
+            if let Err(error) = image.restore(self.gl) {
+                log!("Could not restore {}: {}", name, error);
+            }
+        }
+    }
+
+    /// Makes `image` available to `draw_sprite` as `name`.
+    pub fn add_texture(&mut self, name: &str, image: Image) {
+        self.textures.insert(name.to_owned(), image);
+    }
+
+    /// Rebuilds the shaders from their files, keeping the old ones if they don't build.
+    pub fn reload_shaders(&mut self) {
+        for program in &mut [
+            &mut self.line_program,
+            &mut self.batch_program,
+            &mut self.sprite_program,
+            &mut self.text_program,
+        ] {
+            if let Err(error) = program.reload(self.gl) {
+                log!("{}", error);
+            }
+        }
+    }
+
+    /// Draws `textured_mesh` with `texture`. Expects `program` to already be bound by `bind_settings`.
+    fn draw_textured(&self, program: &ShaderProgram, texture: Texture, color: Color) {
+        program.set_matrix(self.gl, "u_model", &Matrix4x4::IDENTITY);
+        program.set_color(self.gl, "u_color", &color);
+        unsafe {
+            self.gl.active_texture(TEXTURE0);
+            self.gl.bind_texture(TEXTURE_2D, Some(texture));
+            program.set_int(self.gl, "u_texture", 0);
+            self.textured_mesh.draw(self.gl);
+            self.gl.bind_texture(TEXTURE_2D, None);
+        }
+    }
+
+    fn bind_settings(&self, program: &ShaderProgram) {
+        let settings = self.settings.as_ref().expect("begin_frame was not called");
+        program.use_program(self.gl);
+        program.set_float(self.gl, "u_fade", settings.fade);
+        program.set_float(self.gl, "u_pixel_size", settings.pixel_size);
+        program.set_matrix(self.gl, "u_view", &settings.view);
+        program.set_matrix(self.gl, "u_projection", &settings.projection);
+    }
+}
+
+fn set_blending(gl: &GL) {
+    unsafe {
+        gl.enable(BLEND);
+        gl.blend_func(SRC_ALPHA, ONE_MINUS_SRC_ALPHA);
+    }
+}
+
+impl<'a> RenderBackend for GlBackend<'a> {
+    fn begin_frame(&mut self, clear_color: Color, settings: &FrameSettings) {
+        self.settings = Some(*settings);
+        delete_freed(self.gl);
+        unsafe {
+            self.gl
+                .clear_color(clear_color.r, clear_color.g, clear_color.b, clear_color.a);
+            // Clearing ignores the viewport, so bars around it are cleared too.
+            self.gl.clear(COLOR_BUFFER_BIT | DEPTH_BUFFER_BIT);
+            let viewport = settings.viewport;
+            self.gl.viewport(
+                viewport.x as i32,
+                viewport.y as i32,
+                viewport.width as i32,
+                viewport.height as i32,
+            );
+        }
+    }
+
+    fn draw_lines(&mut self, lines: &mut Lines, color: Color) {
+        self.bind_settings(&self.line_program);
+        self.line_program
+            .set_matrix(self.gl, "u_model", &Matrix4x4::IDENTITY);
+        self.line_program.set_color(self.gl, "u_color", &color);
+
+        // Only updates if necessary
+        lines.update_mesh(self.gl);
+        if let Some(mesh) = &lines.mesh {
+            mesh.draw(self.gl);
+        }
+    }
+
+    fn draw_batch(&mut self, batch: &mut ShapeBatch) {
+        self.bind_settings(&self.batch_program);
+        batch.draw(self.gl);
+    }
+
+    fn draw_sprite(&mut self, image: &str, corners: [Vector3; 4], uvs: [Vector2; 4], color: Color) {
+        let texture = match self.textures.get(image) {
+            Some(image) => image.texture,
+            None => return,
+        };
+        let vertices: Vec<_> = corners
+            .iter()
+            .zip(&uvs)
+            .map(|(&position, &uv)| TexturedVertex { position, uv })
+            .collect();
+        self.textured_mesh
+            .update(self.gl, &vertices, &[[0, 1, 2], [0, 2, 3]]);
+
+        self.bind_settings(&self.sprite_program);
+        self.draw_textured(&self.sprite_program, texture, color);
+    }
+
+    fn draw_text(&mut self, font: &Font, text: &PlacedText, color: Color) {
+        let texture = match self.textures.get(&font.image) {
+            Some(image) => image.texture,
+            None => return,
+        };
+        let mut vertices = Vec::with_capacity(text.glyphs.len() * 4);
+        let mut indices = Vec::with_capacity(text.glyphs.len() * 2);
+        for glyph in &text.glyphs {
+            let (min, max) = (glyph.uv_min, glyph.uv_max);
+            let uvs = [
+                Vector2::new(min.x, max.y),
+                Vector2::new(max.x, max.y),
+                Vector2::new(max.x, min.y),
+                Vector2::new(min.x, min.y),
+            ];
+            let first = vertices.len() as u32;
+            vertices.extend(
+                glyph
+                    .corners
+                    .iter()
+                    .zip(&uvs)
+                    .map(|(&position, &uv)| TexturedVertex { position, uv }),
+            );
+            indices.push([first, first + 1, first + 2]);
+            indices.push([first, first + 2, first + 3]);
+        }
+        self.textured_mesh.update(self.gl, &vertices, &indices);
+
+        let settings = self.settings.as_ref().expect("begin_frame was not called");
+        let smoothing = font.smoothing(text, settings);
+        self.bind_settings(&self.text_program);
+        self.text_program
+            .set_float(self.gl, "u_smoothing", smoothing);
+        self.draw_textured(&self.text_program, texture, color);
+    }
+}
+
+/// Draws the level, the player's lines, the ball, the collectibles and particles.
+/// `theme` is usually the level's, but can be a blend of two while changing levels.
+pub fn draw_scene(
+    backend: &mut impl RenderBackend,
+    settings: &FrameSettings,
+    batch: &mut ShapeBatch,
+    level: &Level,
+    theme: &Theme,
+    lines: &mut Lines,
+    user_lines: &mut Lines,
+    ball: &Ball,
+    particles: &Particles,
+) {
+    backend.begin_frame(theme.background_bottom, settings);
+
+    // The background covers the whole screen behind everything else.
+    let background = &level.background;
+    let visible = &settings.visible_bounds;
+    batch.clear();
+    background.push_gradient(batch, theme, visible);
+    backend.draw_batch(batch);
+    for decal in &background.decals {
+        backend.draw_sprite(
+            &decal.image,
+            decal.corners(visible),
+            decal.uvs(),
+            Color::new(1.0, 1.0, 1.0, 1.0),
+        );
+    }
+    batch.clear();
+    background.push_layers(batch, visible);
+    backend.draw_batch(batch);
+
+    // First render the level lines
+    backend.draw_lines(lines, theme.line_color);
+    backend.draw_lines(user_lines, theme.user_line_color);
+
+    // Shapes are accumulated and drawn together after the lines.
+    batch.clear();
+
+    // Render the circle placeholder
+    batch.push_circle(
+        level.start_position,
+        ball.radius,
+        Color::new(ball.color.r, ball.color.g, ball.color.b, 0.1),
+    );
+
+    // Then render the ball over its trail
+    let style = &theme.ball_style;
+    ball.trail
+        .push_to_batch(batch, ball.radius, ball.color, style);
+    let (x_axis, y_axis) = ball.squash.axes(ball.velocity, ball.radius, style);
+    batch.push_ellipse(ball.position, x_axis, y_axis, ball.color);
+
+    // Draw collectibles
+    for collectible in &level.collectibles {
+        let alpha = if collectible.collected {
+            0.1
+        } else {
+            collectible.alpha
+        };
+        let color = Color::new(
+            collectible.color.r,
+            collectible.color.g,
+            collectible.color.b,
+            alpha,
+        );
+        batch.push_circle(collectible.position, collectible.radius, color);
+    }
+
+    particles.push_to_batch(batch);
+
+    backend.draw_batch(batch);
+}
@@ -0,0 +1,175 @@
+use crate::*;
+use std::cell::RefCell;
+
+/// A GL object. Whatever creates one owns it and hands it to `free` when it's dropped.
+#[derive(Debug, Clone, Copy)]
+pub enum Resource {
+    Buffer(Buffer),
+    Texture(Texture),
+    Program(Program),
+    Framebuffer(Framebuffer),
+    Renderbuffer(Renderbuffer),
+}
+
+/// Tracks which context GL objects belong to and which are waiting to be deleted.
+/// Objects remember the `generation` they were made in. When the context is restored
+/// the generation moves on, and anything older is made again from what's kept on the CPU.
+struct Registry {
+    generation: u32,
+    lost: bool,
+    freed: Vec<Resource>,
+}
+
+thread_local! {
+    static REGISTRY: RefCell<Registry> = RefCell::new(Registry {
+        generation: 0,
+        lost: false,
+        freed: Vec::new(),
+    });
+}
+
+/// The context objects made now belong to.
+pub fn generation() -> u32 {
+    REGISTRY.with(|registry| registry.borrow().generation)
+}
+
+/// Whether an object made in `generation` went with a lost context and has to be made again.
+pub fn is_lost(generation: u32) -> bool {
+    generation != self::generation()
+}
+
+/// Nothing can be drawn or created while the context is lost.
+pub fn context_lost() -> bool {
+    REGISTRY.with(|registry| registry.borrow().lost)
+}
+
+/// Queues `resource`, made in `generation`, to be deleted by `delete_freed`.
+/// Objects from a lost context are already gone, so they're ignored.
+pub fn free(generation: u32, resource: Resource) {
+    REGISTRY.with(|registry| {
+        let mut registry = registry.borrow_mut();
+        if generation == registry.generation && !registry.lost {
+            registry.freed.push(resource);
+        }
+    });
+}
+
+/// Call once a frame.
+pub fn delete_freed(gl: &GL) {
+    let freed =
+        REGISTRY.with(|registry| std::mem::replace(&mut registry.borrow_mut().freed, Vec::new()));
+    for resource in freed {
+        unsafe {
+            match resource {
+                Resource::Buffer(buffer) => gl.delete_buffer(buffer),
+                Resource::Texture(texture) => gl.delete_texture(texture),
+                Resource::Program(program) => gl.delete_program(program),
+                Resource::Framebuffer(framebuffer) => gl.delete_framebuffer(framebuffer),
+                Resource::Renderbuffer(renderbuffer) => gl.delete_renderbuffer(renderbuffer),
+            }
+        }
+    }
+}
+
+// Only the browser loses contexts, native builds never see `Lost`.
+#[cfg_attr(not(target_arch = "wasm32"), allow(dead_code))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ContextEvent {
+    Lost,
+    /// Everything made before this has to be made again.
+    Restored,
+}
+
+pub fn handle_context_event(event: ContextEvent) {
+    REGISTRY.with(|registry| {
+        let mut registry = registry.borrow_mut();
+        match event {
+            ContextEvent::Lost => {
+                registry.lost = true;
+                registry.freed.clear();
+            }
+            ContextEvent::Restored => {
+                registry.lost = false;
+                registry.generation += 1;
+            }
+        }
+    });
+}
+
+// The browser can take the context away at any time, like when the GPU resets.
+#[cfg(target_arch = "wasm32")]
+mod resources_web {
+    use super::*;
+    use wasm_bindgen::prelude::*;
+
+    #[wasm_bindgen(module = "/src/helpers.js")]
+    extern "C" {
+        fn setupContextEvents();
+        fn takeContextEvents() -> Box<[u8]>;
+    }
+
+    pub fn setup_context_events() {
+        setupContextEvents();
+    }
+
+    /// Every context event since the last call, oldest first.
+    pub fn poll_context_events() -> Vec<ContextEvent> {
+        takeContextEvents()
+            .iter()
+            .map(|&event| {
+                if event == 0 {
+                    ContextEvent::Lost
+                } else {
+                    ContextEvent::Restored
+                }
+            })
+            .collect()
+    }
+}
+#[cfg(target_arch = "wasm32")]
+pub use resources_web::*;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn setup_context_events() {}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn poll_context_events() -> Vec<ContextEvent> {
+    Vec::new()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn freed() -> usize {
+        REGISTRY.with(|registry| registry.borrow().freed.len())
+    }
+
+    #[test]
+    fn objects_from_a_lost_context_are_remade_not_deleted() {
+        let before = generation();
+        free(before, Resource::Buffer(1));
+        assert_eq!(freed(), 1);
+
+        // Losing the context takes everything with it, even what was waiting to be deleted.
+        handle_context_event(ContextEvent::Lost);
+        assert!(context_lost());
+        assert_eq!(freed(), 0);
+        free(before, Resource::Buffer(2));
+        assert_eq!(freed(), 0);
+
+        handle_context_event(ContextEvent::Restored);
+        assert!(!context_lost());
+        assert_eq!(generation(), before + 1);
+
+        // Meshes made before are uploaded again, and dropping them deletes nothing.
+        assert!(is_lost(before));
+        free(before, Resource::Texture(3));
+        assert_eq!(freed(), 0);
+
+        let after = generation();
+        assert!(!is_lost(after));
+        free(after, Resource::Program(4));
+        assert_eq!(freed(), 1);
+    }
+}
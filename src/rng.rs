@@ -0,0 +1,189 @@
+use std::cell::RefCell;
+
+/// A small seeded random number generator (PCG32) that gives the same
+/// numbers on every platform, so anything random can be reproduced from its seed.
+/// Generators with the same seed and different streams give unrelated sequences.
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+    increment: u64,
+}
+
+const MULTIPLIER: u64 = 6_364_136_223_846_793_005;
+
+impl Rng {
+    pub fn with_stream(seed: u64, stream: u64) -> Self {
+        // The increment has to be odd.
+        let mut rng = Self {
+            state: 0,
+            increment: (stream << 1) | 1,
+        };
+        rng.next_u32();
+        rng.state = rng.state.wrapping_add(seed);
+        rng.next_u32();
+        rng
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let old = self.state;
+        self.state = old.wrapping_mul(MULTIPLIER).wrapping_add(self.increment);
+        let xorshifted = (((old >> 18) ^ old) >> 27) as u32;
+        let rotation = (old >> 59) as u32;
+        xorshifted.rotate_right(rotation)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        ((self.next_u32() as u64) << 32) | self.next_u32() as u64
+    }
+
+    /// In 0.0 up to but not including 1.0.
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u32() >> 8) as f32 / (1u32 << 24) as f32
+    }
+
+    /// In 0.0 up to but not including 1.0.
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// In `min` up to but not including `max`.
+    pub fn range(&mut self, min: f32, max: f32) -> f32 {
+        min + (max - min) * self.next_f32()
+    }
+}
+
+/// What the numbers are for. Each gets its own stream so using more
+/// random numbers in one place doesn't change what another gets.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RngStream {
+    Audio,
+    Particles,
+    Procedural,
+}
+
+impl RngStream {
+    const COUNT: usize = 3;
+}
+
+struct Streams {
+    seed: u64,
+    streams: Vec<Rng>,
+}
+
+impl Streams {
+    fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            streams: (0..RngStream::COUNT as u64)
+                .map(|stream| Rng::with_stream(seed, stream))
+                .collect(),
+        }
+    }
+}
+
+thread_local! {
+    static STREAMS: RefCell<Streams> = RefCell::new(Streams::new(0));
+}
+
+/// Restarts every stream from `seed`.
+pub fn set_seed(seed: u64) {
+    STREAMS.with(|streams| *streams.borrow_mut() = Streams::new(seed));
+}
+
+/// The seed every stream was last started from.
+pub fn seed() -> u64 {
+    STREAMS.with(|streams| streams.borrow().seed)
+}
+
+pub fn with_stream<T>(stream: RngStream, f: impl FnOnce(&mut Rng) -> T) -> T {
+    STREAMS.with(|streams| f(&mut streams.borrow_mut().streams[stream as usize]))
+}
+
+/// FNV-1a, for seeds that come from data and have to be the same on every platform and build.
+pub fn hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// A seed that's different every run.
+#[cfg(target_arch = "wasm32")]
+pub fn seed_from_time() -> u64 {
+    js_sys::Date::now() as u64
+}
+
+/// A seed that's different every run.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn seed_from_time() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn draw(stream: RngStream, count: usize) -> Vec<u32> {
+        with_stream(stream, |rng| (0..count).map(|_| rng.next_u32()).collect())
+    }
+
+    #[test]
+    fn matches_reference_pcg32() {
+        // From the PCG reference implementation's demo, seeded with 42 on stream 54.
+        let mut rng = Rng::with_stream(42, 54);
+        let numbers: Vec<u32> = (0..6).map(|_| rng.next_u32()).collect();
+        assert_eq!(
+            numbers,
+            [0xa15c02b7, 0x7b47f409, 0xba1d3330, 0x83d2f293, 0xbfa4784b, 0xcbed606e]
+        );
+    }
+
+    #[test]
+    fn same_seed_same_sequence() {
+        let (mut a, mut b) = (Rng::with_stream(7, 3), Rng::with_stream(7, 3));
+        for _ in 0..100 {
+            assert_eq!(a.next_u32(), b.next_u32());
+        }
+
+        let mut other = Rng::with_stream(8, 3);
+        let mut a = Rng::with_stream(7, 3);
+        assert!((0..100).any(|_| a.next_u32() != other.next_u32()));
+    }
+
+    #[test]
+    fn streams_are_independent() {
+        set_seed(11);
+        let audio = draw(RngStream::Audio, 8);
+        let procedural = draw(RngStream::Procedural, 8);
+
+        set_seed(11);
+        draw(RngStream::Particles, 100);
+        assert_eq!(draw(RngStream::Audio, 8), audio);
+        assert_eq!(draw(RngStream::Procedural, 8), procedural);
+    }
+
+    #[test]
+    fn set_seed_resets_every_stream() {
+        const STREAMS: [RngStream; RngStream::COUNT] = [
+            RngStream::Audio,
+            RngStream::Particles,
+            RngStream::Procedural,
+        ];
+        set_seed(3);
+        let first: Vec<Vec<u32>> = STREAMS.iter().map(|&stream| draw(stream, 4)).collect();
+        assert_ne!(
+            first,
+            STREAMS
+                .iter()
+                .map(|&stream| draw(stream, 4))
+                .collect::<Vec<_>>()
+        );
+
+        set_seed(3);
+        assert_eq!(seed(), 3);
+        let again: Vec<Vec<u32>> = STREAMS.iter().map(|&stream| draw(stream, 4)).collect();
+        assert_eq!(again, first);
+    }
+}
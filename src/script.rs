@@ -0,0 +1,287 @@
+use crate::*;
+use std::fmt;
+use std::ops::Range;
+
+/// How strongly the ball is pulled, multiplied by `Script::gravity`.
+pub const GRAVITY: f32 = 0.0001;
+
+/// Something that happened in a level that rules can react to.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScriptEvent {
+    /// The collectible at this index, counted from 0 in the order they're placed, was collected.
+    Collect(usize),
+    /// The ball moved into the trigger with this name.
+    Enter(String),
+}
+
+#[derive(Debug, Clone)]
+pub enum ScriptAction {
+    /// Plays a sound loaded from its file name.
+    Sound { file: String, rate: f64, gain: f64 },
+    /// Draws hidden strokes, see `Script::hidden`.
+    Reveal { first: usize, count: usize },
+    /// Relative to normal gravity, so `0 -1` is normal and `0 1` makes the ball fall upwards.
+    Gravity(Vector2),
+    /// Finishes the level as if every collectible was collected.
+    Complete,
+}
+
+/// What the rest of the game has to do for an action.
+/// Changes to gravity are kept by the script itself.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Effect {
+    Sound {
+        file: String,
+        rate: f64,
+        gain: f64,
+    },
+    /// The strokes to draw, see `MousePlayback::reveal`.
+    Reveal(Vec<usize>),
+    Complete,
+}
+
+/// A region of the level that fires `ScriptEvent::Enter` each time the ball moves into it.
+#[derive(Debug, Clone)]
+pub struct Trigger {
+    pub name: String,
+    pub bounds: Bounds,
+    inside: bool,
+}
+
+impl Trigger {
+    pub fn new(name: &str, bounds: Bounds) -> Self {
+        Self {
+            name: name.to_owned(),
+            bounds,
+            inside: false,
+        }
+    }
+}
+
+/// Does `action` whenever `event` happens.
+#[derive(Debug, Clone)]
+pub struct Rule {
+    pub event: ScriptEvent,
+    pub action: ScriptAction,
+}
+
+/// A level's triggers and rules, and what they've changed so far.
+/// Nothing here touches the GPU or audio, so it runs the same in replays as in the game.
+pub struct Script {
+    /// Collectibles can only be collected in the order they were placed.
+    pub ordered: bool,
+    pub triggers: Vec<Trigger>,
+    pub rules: Vec<Rule>,
+    /// Strokes, counted from 0 in the order they're drawn, that are skipped until revealed.
+    pub hidden: Vec<Range<usize>>,
+    // Revealed strokes stay drawn until the level is loaded again.
+    revealed: Vec<usize>,
+    gravity: Vector2,
+}
+
+impl Script {
+    pub fn new() -> Self {
+        Self {
+            ordered: false,
+            triggers: Vec::new(),
+            rules: Vec::new(),
+            hidden: Vec::new(),
+            revealed: Vec::new(),
+            gravity: Vector2::new(0.0, -1.0),
+        }
+    }
+
+    /// Call when the ball is reset. Gravity goes back to normal and triggers fire again.
+    pub fn reset(&mut self) {
+        self.gravity = Vector2::new(0.0, -1.0);
+        for trigger in &mut self.triggers {
+            trigger.inside = false;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::new();
+    }
+
+    pub fn is_hidden(&self, stroke: usize) -> bool {
+        self.hidden.iter().any(|range| range.contains(&stroke)) && !self.revealed.contains(&stroke)
+    }
+
+    /// `next` is the index of the collectible that would be collected next in order.
+    pub fn can_collect(&self, index: usize, next: usize) -> bool {
+        !self.ordered || index == next
+    }
+
+    /// To add to the ball's velocity each tick.
+    pub fn gravity(&self) -> Vector3 {
+        Vector3::new(self.gravity.x, self.gravity.y, 0.0) * GRAVITY
+    }
+
+    /// Call each tick with where the ball is.
+    pub fn update(&mut self, ball_position: Vector3) -> Vec<Effect> {
+        let mut entered = Vec::new();
+        for trigger in &mut self.triggers {
+            let inside = trigger.bounds.contains(ball_position);
+            if inside && !trigger.inside {
+                entered.push(ScriptEvent::Enter(trigger.name.clone()));
+            }
+            trigger.inside = inside;
+        }
+
+        let mut effects = Vec::new();
+        for event in &entered {
+            effects.extend(self.run(event));
+        }
+        effects
+    }
+
+    /// Call when the collectible at `index` is collected.
+    pub fn collect(&mut self, index: usize) -> Vec<Effect> {
+        self.run(&ScriptEvent::Collect(index))
+    }
+
+    /// Does the action of every rule for `event`, in the order they're listed.
+    pub fn run(&mut self, event: &ScriptEvent) -> Vec<Effect> {
+        let actions: Vec<ScriptAction> = self
+            .rules
+            .iter()
+            .filter(|rule| rule.event == *event)
+            .map(|rule| rule.action.clone())
+            .collect();
+
+        let mut effects = Vec::new();
+        for action in actions {
+            match action {
+                ScriptAction::Sound { file, rate, gain } => {
+                    effects.push(Effect::Sound { file, rate, gain })
+                }
+                ScriptAction::Reveal { first, count } => {
+                    // `editor::load` rejects these, but scripts can be made in code too.
+                    let end = match first.checked_add(count) {
+                        Some(end) => end,
+                        None => continue,
+                    };
+                    let strokes: Vec<usize> = (first..end)
+                        .filter(|&stroke| self.is_hidden(stroke))
+                        .collect();
+                    if !strokes.is_empty() {
+                        self.revealed.extend(&strokes);
+                        effects.push(Effect::Reveal(strokes));
+                    }
+                }
+                ScriptAction::Gravity(gravity) => self.gravity = gravity,
+                ScriptAction::Complete => effects.push(Effect::Complete),
+            }
+        }
+        effects
+    }
+}
+
+/// Written the way levels list it after `on`.
+impl fmt::Display for ScriptEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ScriptEvent::Collect(index) => write!(f, "collect {}", index),
+            ScriptEvent::Enter(name) => write!(f, "enter {}", name),
+        }
+    }
+}
+
+/// Written the way levels list it after the event.
+impl fmt::Display for ScriptAction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ScriptAction::Sound { file, rate, gain } => {
+                write!(f, "sound {} {} {}", file, rate, gain)
+            }
+            ScriptAction::Reveal { first, count } => write!(f, "reveal {} {}", first, count),
+            ScriptAction::Gravity(gravity) => write!(f, "gravity {} {}", gravity.x, gravity.y),
+            ScriptAction::Complete => write!(f, "complete"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(event: ScriptEvent, action: ScriptAction) -> Rule {
+        Rule { event, action }
+    }
+
+    #[test]
+    fn triggers_fire_each_time_they_are_entered() {
+        let mut script = Script::new();
+        let bounds = Bounds::new(Vector3::ZERO, Vector3::new(1.0, 1.0, 0.0));
+        script.triggers.push(Trigger::new("goal", bounds));
+        let enter = ScriptEvent::Enter("goal".to_owned());
+        script.rules.push(rule(enter, ScriptAction::Complete));
+
+        let inside = Vector3::new(0.5, 0.5, 0.0);
+        let outside = Vector3::new(2.0, 2.0, 0.0);
+        assert_eq!(script.update(outside), []);
+        assert_eq!(script.update(inside), [Effect::Complete]);
+        // Staying inside doesn't fire again, leaving and coming back does.
+        assert_eq!(script.update(inside), []);
+        assert_eq!(script.update(outside), []);
+        assert_eq!(script.update(inside), [Effect::Complete]);
+
+        // So does being reset while inside.
+        script.reset();
+        assert_eq!(script.update(inside), [Effect::Complete]);
+    }
+
+    #[test]
+    fn ordered_collectibles_must_be_collected_in_order() {
+        let mut script = Script::new();
+        assert!(script.can_collect(2, 0));
+
+        script.ordered = true;
+        assert!(script.can_collect(0, 0));
+        assert!(!script.can_collect(2, 0));
+        assert!(script.can_collect(2, 2));
+    }
+
+    #[test]
+    fn reset_restores_gravity() {
+        let mut script = Script::new();
+        let gravity = ScriptAction::Gravity(Vector2::new(0.0, 1.0));
+        script.rules.push(rule(ScriptEvent::Collect(0), gravity));
+        let normal = script.gravity();
+
+        script.collect(0);
+        assert!(script.gravity().y > 0.0);
+        script.reset();
+        assert_eq!(script.gravity().y, normal.y);
+    }
+
+    #[test]
+    fn reveal_draws_hidden_strokes_once() {
+        let mut script = Script::new();
+        script.hidden.push(1..3);
+        let reveal = ScriptAction::Reveal { first: 0, count: 4 };
+        script.rules.push(rule(ScriptEvent::Collect(0), reveal));
+        assert!(script.is_hidden(1));
+
+        assert_eq!(script.collect(0), [Effect::Reveal(vec![1, 2])]);
+        assert!(!script.is_hidden(1));
+        assert!(!script.is_hidden(2));
+        assert_eq!(script.collect(0), []);
+
+        // Revealed strokes stay revealed when the ball is reset.
+        script.reset();
+        assert!(!script.is_hidden(1));
+    }
+
+    #[test]
+    fn reveals_past_the_last_stroke_do_nothing() {
+        let mut script = Script::new();
+        script.hidden.push(0..1);
+        let reveal = ScriptAction::Reveal {
+            first: usize::MAX,
+            count: 2,
+        };
+        script.rules.push(rule(ScriptEvent::Collect(0), reveal));
+        assert_eq!(script.collect(0), []);
+    }
+}
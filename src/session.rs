@@ -0,0 +1,403 @@
+use crate::*;
+
+/// Input the game got, in the same form it's given to `Game`, so a replay goes through exactly the same code.
+#[derive(Debug, Clone, Copy)]
+pub enum SessionEvent {
+    /// The window's new size in pixels.
+    Resize(u32, u32),
+    /// The mouse moved to a position in window pixels.
+    MouseMove(Vector2),
+    /// An action was pressed or released with the mouse at a position in window pixels.
+    Action(InputChange, Vector2),
+    Pointer(PointerEvent),
+    /// The gamepad's state, only recorded when it changes.
+    Gamepad(Option<GamepadState>),
+}
+
+/// Records everything the player does along with the tick it happened on,
+/// so a whole session can be replayed without a window to reproduce bugs exactly.
+/// Edits made with the level editor aren't recorded, so sessions that use it won't replay the same.
+pub struct Session {
+    pub seed: u64,
+    pub scaling: ScalingPolicy,
+    pub events: Vec<(u32, SessionEvent)>,
+    /// How many times the world has been updated.
+    pub tick: u32,
+    gamepad: Option<GamepadState>,
+}
+
+impl Session {
+    pub fn new(seed: u64, scaling: ScalingPolicy) -> Self {
+        Self {
+            seed,
+            scaling,
+            events: Vec::new(),
+            tick: 0,
+            gamepad: None,
+        }
+    }
+
+    pub fn record(&mut self, event: SessionEvent) {
+        self.events.push((self.tick, event));
+    }
+
+    /// Call before every tick of the game with the gamepad it's given.
+    pub fn record_gamepad(&mut self, gamepad: Option<GamepadState>) {
+        if gamepad != self.gamepad {
+            self.gamepad = gamepad;
+            self.record(SessionEvent::Gamepad(gamepad));
+        }
+    }
+
+    /// Call after every tick of the game.
+    pub fn end_tick(&mut self) {
+        self.tick += 1;
+    }
+
+    /// A few lines with the seed, scaling and tick count followed by a line per event, starting with its tick.
+    pub fn to_text(&self) -> String {
+        let mut string = format!(
+            "seed {}\nscaling {}\nticks {}\n",
+            self.seed,
+            self.scaling.name(),
+            self.tick
+        );
+        for (tick, event) in &self.events {
+            let line = match event {
+                SessionEvent::Resize(width, height) => format!("resize {} {}", width, height),
+                SessionEvent::MouseMove(p) => format!("mouse {} {}", p.x, p.y),
+                SessionEvent::Action(change, p) => {
+                    let (name, action) = match change {
+                        InputChange::Pressed(action) => ("press", action),
+                        InputChange::Released(action) => ("release", action),
+                    };
+                    format!("{} {:?} {} {}", name, action, p.x, p.y)
+                }
+                SessionEvent::Pointer(e) => {
+                    let phase = match e.phase {
+                        PointerPhase::Down => "down",
+                        PointerPhase::Move => "move",
+                        PointerPhase::Up => "up",
+                        PointerPhase::Cancel => "cancel",
+                    };
+                    let kind = match e.kind {
+                        PointerKind::Mouse => "mouse",
+                        PointerKind::Touch => "touch",
+                        PointerKind::Pen => "pen",
+                    };
+                    format!(
+                        "pointer {} {} {} {} {} {} {}",
+                        phase, e.id, kind, e.position.x, e.position.y, e.pressure, e.eraser as u8
+                    )
+                }
+                SessionEvent::Gamepad(None) => "gamepad none".to_owned(),
+                SessionEvent::Gamepad(Some(g)) => format!(
+                    "gamepad {} {} {} {} {}",
+                    g.stick.x, g.stick.y, g.pen as u8, g.erase as u8, g.launch as u8
+                ),
+            };
+            string += &format!("{} {}\n", tick, line);
+        }
+        string
+    }
+
+    pub fn from_text(text: &str) -> Result<Self, String> {
+        let mut lines = text.lines().enumerate();
+        let mut header = |number: usize, name: &str| match lines.next() {
+            Some((_, line)) if line.starts_with(name) && line[name.len()..].starts_with(' ') => {
+                Ok(line[name.len()..].trim().to_owned())
+            }
+            _ => Err(format!("Line {}: expected the {}", number, name)),
+        };
+        let seed = header(1, "seed")?
+            .parse()
+            .map_err(|_| "Line 1: invalid seed".to_owned())?;
+        let scaling = ScalingPolicy::from_name(&header(2, "scaling")?)
+            .map_err(|e| format!("Line 2: {}", e))?;
+        let ticks = header(3, "ticks")?
+            .parse()
+            .map_err(|_| "Line 3: invalid tick count".to_owned())?;
+
+        let mut session = Session::new(seed, scaling);
+        session.tick = ticks;
+        for (number, line) in lines {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let error = || format!("Line {}: could not read {:?}", number + 1, line);
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let tick: u32 = words
+                .get(0)
+                .and_then(|w| w.parse().ok())
+                .ok_or_else(error)?;
+            let number = |i: usize| -> Result<f32, String> {
+                words.get(i).and_then(|w| w.parse().ok()).ok_or_else(error)
+            };
+            let flag = |i: usize| -> Result<bool, String> {
+                match words.get(i) {
+                    Some(&"0") => Ok(false),
+                    Some(&"1") => Ok(true),
+                    _ => Err(error()),
+                }
+            };
+            let count = |expected: usize| {
+                if words.len() == expected {
+                    Ok(())
+                } else {
+                    Err(error())
+                }
+            };
+
+            let event = match words.get(1).copied() {
+                Some("resize") => {
+                    count(4)?;
+                    let size = |i: usize| words[i].parse().map_err(|_| error());
+                    SessionEvent::Resize(size(2)?, size(3)?)
+                }
+                Some("mouse") => {
+                    count(4)?;
+                    SessionEvent::MouseMove(Vector2::new(number(2)?, number(3)?))
+                }
+                Some(name @ "press") | Some(name @ "release") => {
+                    count(5)?;
+                    let action = Action::from_name(words[2]).ok_or_else(error)?;
+                    let change = if name == "press" {
+                        InputChange::Pressed(action)
+                    } else {
+                        InputChange::Released(action)
+                    };
+                    SessionEvent::Action(change, Vector2::new(number(3)?, number(4)?))
+                }
+                Some("pointer") => {
+                    count(9)?;
+                    let phase = match words[2] {
+                        "down" => PointerPhase::Down,
+                        "move" => PointerPhase::Move,
+                        "up" => PointerPhase::Up,
+                        "cancel" => PointerPhase::Cancel,
+                        _ => return Err(error()),
+                    };
+                    let kind = match words[4] {
+                        "mouse" => PointerKind::Mouse,
+                        "touch" => PointerKind::Touch,
+                        "pen" => PointerKind::Pen,
+                        _ => return Err(error()),
+                    };
+                    SessionEvent::Pointer(PointerEvent {
+                        id: words[3].parse().map_err(|_| error())?,
+                        kind,
+                        phase,
+                        position: Vector2::new(number(5)?, number(6)?),
+                        pressure: number(7)?,
+                        eraser: flag(8)?,
+                    })
+                }
+                Some("gamepad") if words.get(2) == Some(&"none") => {
+                    count(3)?;
+                    SessionEvent::Gamepad(None)
+                }
+                Some("gamepad") => {
+                    count(7)?;
+                    SessionEvent::Gamepad(Some(GamepadState {
+                        stick: Vector2::new(number(2)?, number(3)?),
+                        pen: flag(4)?,
+                        erase: flag(5)?,
+                        launch: flag(6)?,
+                    }))
+                }
+                _ => return Err(error()),
+            };
+            session.events.push((tick, event));
+        }
+        Ok(session)
+    }
+}
+
+/// Plays a session's input back into a new `Game` in the order it happened, one tick at a time.
+/// `on_tick` is called after every tick with the game as the player saw it.
+/// Returns the levels completed and the tick each one was completed on.
+pub fn replay(
+    session: &Session,
+    levels: &[&str],
+    mut on_tick: impl FnMut(&mut Game),
+) -> Vec<(u32, u32)> {
+    // Actions are recorded rather than keys, so no bindings are needed.
+    let mut game = Game::new(levels, Input::new(Vec::new()), session.scaling);
+    let mut gamepad = None;
+    let mut pointer_events = Vec::new();
+    let mut completed = Vec::new();
+    rng::set_seed(session.seed);
+
+    let mut events = session.events.iter().peekable();
+    for tick in 0..session.tick {
+        pointer_events.clear();
+        while let Some(&&(_, event)) = events.peek().filter(|(t, _)| *t == tick) {
+            match event {
+                SessionEvent::Resize(width, height) => game.resize(width, height),
+                SessionEvent::MouseMove(position) => {
+                    let previous = game.input.mouse_position;
+                    game.input.mouse_position = position;
+                    game.mouse_moved(previous);
+                }
+                SessionEvent::Action(change, position) => {
+                    game.input.mouse_position = position;
+                    if game.input.apply(change) {
+                        game.action_changed(change);
+                    }
+                }
+                SessionEvent::Pointer(event) => pointer_events.push(event),
+                SessionEvent::Gamepad(state) => gamepad = state,
+            }
+            events.next();
+        }
+
+        let outcome = game.tick(&pointer_events, gamepad.as_ref());
+        if outcome.completed {
+            completed.push((game.current_level, tick));
+        }
+        on_tick(&mut game);
+    }
+    completed
+}
+
+/// `replay <session file> [output .gif]`
+#[cfg(not(target_arch = "wasm32"))]
+pub fn run_replay_cli(args: &[String]) {
+    if args.is_empty() {
+        log!("Usage: replay <session file> [output .gif]");
+        std::process::exit(1);
+    }
+    if let Err(error) = replay_file(&args[0], args.get(1)) {
+        log!("{}", error);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn replay_file(path: &str, output: Option<&String>) -> Result<(), String> {
+    let text =
+        std::fs::read_to_string(path).map_err(|e| format!("Could not read {}: {}", path, e))?;
+    let session = Session::from_text(&text)?;
+
+    // Drawn at a fixed size, looking where the player's camera looked.
+    let (width, height) = (480, 480);
+    let mut camera = Camera::game(width, height);
+    camera.design.policy = session.scaling;
+    let mut renderer = SoftwareRenderer::new(width, height);
+    let mut batch = ShapeBatch::new(30);
+    let mut capture = Capture::new(4);
+    if output.is_some() {
+        capture.start();
+    }
+
+    let completed = replay(&session, &LEVELS, |game| {
+        game.camera_controller.apply(&mut camera);
+        capture.tick(|| {
+            let settings = FrameSettings::new(
+                &camera,
+                game.level_alpha as f32,
+                camera.world_units_per_pixel(),
+            );
+            draw_scene(
+                &mut renderer,
+                &settings,
+                &mut batch,
+                &game.level,
+                &game.theme(),
+                &mut game.lines,
+                &mut game.user_lines,
+                &game.ball,
+                &game.particles,
+            );
+            CapturedFrame::from(&renderer)
+        });
+    });
+
+    for (level, tick) in completed {
+        log!("Completed level {} on tick {}", level, tick);
+    }
+    if let (Some(output), Some(gif)) = (output, capture.stop()) {
+        std::fs::write(output, &gif).map_err(|e| format!("Could not write {}: {}", output, e))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_round_trips() {
+        let mut session = Session::new(7, ScalingPolicy::FitWidth);
+        session.record(SessionEvent::Resize(640, 480));
+        session.end_tick();
+        session.record(SessionEvent::MouseMove(Vector2::new(10.5, 20.25)));
+        session.record(SessionEvent::Action(
+            InputChange::Pressed(Action::Draw),
+            Vector2::new(11.0, 20.0),
+        ));
+        session.record(SessionEvent::Pointer(PointerEvent {
+            id: 2,
+            kind: PointerKind::Pen,
+            phase: PointerPhase::Move,
+            position: Vector2::new(1.0, 2.0),
+            pressure: 0.75,
+            eraser: true,
+        }));
+        session.record_gamepad(Some(GamepadState {
+            stick: Vector2::new(-0.5, 1.0),
+            pen: true,
+            erase: false,
+            launch: false,
+        }));
+        session.end_tick();
+        // Unchanged gamepads aren't recorded again.
+        session.record_gamepad(session.gamepad);
+        session.record_gamepad(None);
+        session.end_tick();
+
+        let read = Session::from_text(&session.to_text()).unwrap();
+        assert_eq!(read.seed, 7);
+        assert_eq!(read.scaling, ScalingPolicy::FitWidth);
+        assert_eq!(read.tick, 3);
+        assert_eq!(read.events.len(), 6);
+        assert_eq!(read.to_text(), session.to_text());
+
+        assert!(Session::from_text("seed 1\nscaling letterbox\n").is_err());
+        assert!(
+            Session::from_text("seed 1\nscaling letterbox\nticks 1\n0 press Jump 0 0").is_err()
+        );
+    }
+
+    #[test]
+    fn replays_complete_levels_on_the_same_tick() {
+        // The ball starts right above the level's only collectible.
+        let level = "0.5 1.0 b 0.5 0.8 0";
+        let levels = [level, level];
+
+        // Play and record, the way the game's loop does.
+        let mut session = Session::new(1, ScalingPolicy::Letterbox);
+        let mut game = Game::new(&levels, Input::new(Vec::new()), session.scaling);
+        let mut played = Vec::new();
+        session.record(SessionEvent::Resize(800, 600));
+        game.resize(800, 600);
+        while played.is_empty() && session.tick < 1000 {
+            if game.level.setup && !game.ball.moving {
+                let change = InputChange::Pressed(Action::LaunchBall);
+                let position = game.input.mouse_position;
+                assert!(game.input.apply(change));
+                session.record(SessionEvent::Action(change, position));
+                game.action_changed(change);
+            }
+            session.record_gamepad(None);
+            if game.tick(&[], None).completed {
+                played.push((game.current_level, session.tick));
+            }
+            session.end_tick();
+        }
+        assert_eq!(played.len(), 1);
+
+        let read = Session::from_text(&session.to_text()).unwrap();
+        assert_eq!(replay(&read, &levels, |_| {}), played);
+    }
+}
@@ -1,50 +1,406 @@
 use crate::*;
-pub struct ShaderProgram {
-    program: Program,
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Every file in `src/shaders`, so shaders can be made and `#include` each other by name.
+const SHADER_FILES: [(&str, &str); 12] = [
+    ("batch.fs", include_str!("shaders/batch.fs")),
+    ("batch.vs", include_str!("shaders/batch.vs")),
+    ("blur.fs", include_str!("shaders/blur.fs")),
+    ("bright.fs", include_str!("shaders/bright.fs")),
+    ("common.glsl", include_str!("shaders/common.glsl")),
+    ("composite.fs", include_str!("shaders/composite.fs")),
+    ("frag.fs", include_str!("shaders/frag.fs")),
+    ("post.vs", include_str!("shaders/post.vs")),
+    ("sprite.fs", include_str!("shaders/sprite.fs")),
+    ("sprite.vs", include_str!("shaders/sprite.vs")),
+    ("text.fs", include_str!("shaders/text.fs")),
+    ("vert.vs", include_str!("shaders/vert.vs")),
+];
+
+/// Where shaders are read from while developing natively, relative to where the game is run.
+#[cfg(all(not(target_arch = "wasm32"), debug_assertions))]
+const SHADER_DIRECTORY: &str = "src/shaders";
+
+/// Native debug builds read shaders from disk so they can be edited while the game runs.
+pub fn shader_file(name: &str) -> Option<String> {
+    #[cfg(all(not(target_arch = "wasm32"), debug_assertions))]
+    {
+        let path = std::path::Path::new(SHADER_DIRECTORY).join(name);
+        if let Ok(source) = std::fs::read_to_string(path) {
+            return Some(source);
+        }
+    }
+    SHADER_FILES
+        .iter()
+        .find(|(file, _)| *file == name)
+        .map(|(_, source)| (*source).to_owned())
+}
+
+/// A line from the driver's log, pointed back at the file and line it came from.
+#[derive(Debug, Clone)]
+pub struct ShaderErrorLine {
+    pub file: String,
+    pub line: u32,
+    pub message: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct ShaderError {
+    /// `"vertex"`, `"fragment"`, `"link"` or `"preprocess"`.
+    pub stage: &'static str,
+    /// The log as the driver wrote it.
+    pub log: String,
+    /// Whatever lines of the log could be understood.
+    pub lines: Vec<ShaderErrorLine>,
+}
+
+impl fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.lines.is_empty() {
+            return write!(f, "Shader {} error: {}", self.stage, self.log.trim());
+        }
+        for line in &self.lines {
+            writeln!(f, "{}:{}: {}", line.file, line.line, line.message)?;
+        }
+        Ok(())
+    }
+}
+
+/// How a program is built. Attributes are bound to locations in the order they're listed.
+#[derive(Debug, Clone)]
+pub struct ShaderOptions {
+    /// Added as `#define name value` before the source.
+    pub defines: Vec<(String, String)>,
+    pub attributes: Vec<String>,
+}
+
+impl Default for ShaderOptions {
+    /// The attributes meshes and batches expect.
+    fn default() -> Self {
+        Self {
+            defines: Vec::new(),
+            attributes: vec![
+                "position".to_owned(),
+                "color".to_owned(),
+                "edge".to_owned(),
+                "uv".to_owned(),
+            ],
+        }
+    }
+}
+
+/// Preprocessed source along with the file and line each of its lines came from.
+struct Preprocessed {
+    source: String,
+    lines: Vec<(String, u32)>,
+    /// If lines from files need `translate`.
+    translate: bool,
+    shader_type: u32,
+}
+
+/// Adds the version and defines, then expands `#include "file"` lines.
+/// Sources are written in GLSL ES 1.00 and translated for the context, see `translate`.
+fn preprocess(
+    file: &str,
+    source: &str,
+    defines: &[(String, String)],
+    shader_type: u32,
+) -> Result<Preprocessed, ShaderError> {
+    let version = capabilities().version;
+    let (header, glsl_version) = match version {
+        GlVersion::WebGl1 => ("", 100),
+        GlVersion::WebGl2 => ("#version 300 es", 300),
+        GlVersion::Desktop => ("#version 410", 410),
+    };
+
+    let mut preprocessed = Preprocessed {
+        source: String::new(),
+        lines: Vec::new(),
+        translate: version != GlVersion::WebGl1,
+        shader_type,
+    };
+    preprocessed.push(header, "<preamble>", 0);
+    preprocessed.push(
+        &format!("#define GLSL_VERSION {}", glsl_version),
+        "<preamble>",
+        0,
+    );
+    if preprocessed.translate && shader_type == FRAGMENT_SHADER {
+        // Stands in for `gl_FragColor`. Declared before the source's default precision, so it has its own.
+        preprocessed.push("out highp vec4 frag_color;", "<preamble>", 0);
+    }
+    for (name, value) in defines {
+        preprocessed.push(&format!("#define {} {}", name, value), "<preamble>", 0);
+    }
+    preprocessed.include(file, source, &mut vec![file.to_owned()])?;
+    Ok(preprocessed)
+}
+
+/// Rewrites a line of GLSL ES 1.00 for GLSL ES 3.00 and desktop GLSL, which share these keywords.
+/// Only whole words are replaced, so the line keeps its place for error messages.
+fn translate(line: &str, shader_type: u32) -> String {
+    let varying = if shader_type == VERTEX_SHADER {
+        "out"
+    } else {
+        "in"
+    };
+    let replacement = |word: &str| match word {
+        "attribute" => Some("in"),
+        "varying" => Some(varying),
+        "texture2D" => Some("texture"),
+        "gl_FragColor" => Some("frag_color"),
+        _ => None,
+    };
+
+    let mut translated = String::with_capacity(line.len());
+    let mut word_start = None;
+    for (i, c) in line
+        .char_indices()
+        .chain(std::iter::once((line.len(), ' ')))
+    {
+        let is_word = c.is_ascii_alphanumeric() || c == '_';
+        match (word_start, is_word) {
+            (None, true) => word_start = Some(i),
+            (Some(start), false) => {
+                let word = &line[start..i];
+                translated += replacement(word).unwrap_or(word);
+                word_start = None;
+            }
+            _ => {}
+        }
+        if !is_word && i < line.len() {
+            translated.push(c);
+        }
+    }
+    translated
 }
 
-fn compile_shader(gl: &Context, shader_type: u32, source: &str) -> <Context as HasContext>::Shader {
-    #[cfg(all(target_arch = "wasm32"))]
-    let version = ""; // No version for WebGL1
-                      // let version = "#version 300 es";
-    #[cfg(all(not(target_arch = "wasm32")))]
-    let version = "#version 410";
+impl Preprocessed {
+    fn push(&mut self, line: &str, file: &str, number: u32) {
+        self.source += line;
+        self.source += "\n";
+        self.lines.push((file.to_owned(), number));
+    }
+
+    fn include(
+        &mut self,
+        file: &str,
+        source: &str,
+        stack: &mut Vec<String>,
+    ) -> Result<(), ShaderError> {
+        for (i, line) in source.lines().enumerate() {
+            let number = i as u32 + 1;
+            let trimmed = line.trim();
+            if !trimmed.starts_with("#include") {
+                if self.translate {
+                    self.push(&translate(line, self.shader_type), file, number);
+                } else {
+                    self.push(line, file, number);
+                }
+                continue;
+            }
+
+            let error = |message: String| ShaderError {
+                stage: "preprocess",
+                log: message.clone(),
+                lines: vec![ShaderErrorLine {
+                    file: file.to_owned(),
+                    line: number,
+                    message,
+                }],
+            };
+            let name = trimmed["#include".len()..].trim().trim_matches('"');
+            if stack.iter().any(|f| f == name) {
+                return Err(error(format!("{} includes itself", name)));
+            }
+            let included =
+                shader_file(name).ok_or_else(|| error(format!("Could not find {}", name)))?;
+            stack.push(name.to_owned());
+            self.include(name, &included, stack)?;
+            stack.pop();
+        }
+        Ok(())
+    }
+
+    /// Understands the common ways drivers write errors:
+    /// `ERROR: 0:12: message`, `0:12(5): error: message` and `0(12) : error C0000: message`.
+    fn error(&self, stage: &'static str, log: String) -> ShaderError {
+        let lines = log
+            .lines()
+            .filter_map(|line| {
+                let rest = line
+                    .trim_start_matches("ERROR: ")
+                    .trim_start_matches("WARNING: ");
+                let rest = rest.trim_start_matches(|c: char| c.is_ascii_digit());
+                let rest = rest.strip_prefix(':').or_else(|| rest.strip_prefix('('))?;
+                let end = rest.find(|c: char| !c.is_ascii_digit())?;
+                let number: usize = rest[..end].parse().ok()?;
+                let message = rest[end..].trim_start_matches(|c: char| {
+                    c.is_ascii_digit() || "():".contains(c) || c == ' '
+                });
+                let (file, line) = self.lines.get(number.checked_sub(1)?)?.clone();
+                Some(ShaderErrorLine {
+                    file,
+                    line,
+                    message: message.to_owned(),
+                })
+            })
+            .collect();
+        ShaderError { stage, log, lines }
+    }
+}
 
-    let source = &format!("{}\n{}", version, source);
+fn compile_shader(
+    gl: &Context,
+    shader_type: u32,
+    file: &str,
+    source: &str,
+    defines: &[(String, String)],
+) -> Result<<Context as HasContext>::Shader, ShaderError> {
+    let preprocessed = preprocess(file, source, defines, shader_type)?;
     unsafe {
         let shader = gl.create_shader(shader_type).unwrap();
-        gl.shader_source(shader, source);
+        gl.shader_source(shader, &preprocessed.source);
         gl.compile_shader(shader);
 
         if !gl.get_shader_compile_status(shader) {
-            log!("Type: {:?}", shader_type);
-            log!("{}", source);
-            log!("{}", gl.get_shader_info_log(shader));
-            panic!();
+            let stage = if shader_type == VERTEX_SHADER {
+                "vertex"
+            } else {
+                "fragment"
+            };
+            let error = preprocessed.error(stage, gl.get_shader_info_log(shader));
+            gl.delete_shader(shader);
+            return Err(error);
         }
 
-        shader
+        Ok(shader)
     }
 }
 
-impl ShaderProgram {
-    pub fn new(gl: &GL, vertex_source: &str, fragment_source: &str) -> Self {
-        let vertex_shader = compile_shader(gl, VERTEX_SHADER, vertex_source);
-        let fragment_shader = compile_shader(gl, FRAGMENT_SHADER, fragment_source);
+/// Where a program's sources came from so it can be rebuilt when they change or the context is lost.
+#[derive(Debug, Clone)]
+struct ShaderFiles {
+    vertex: String,
+    fragment: String,
+}
 
-        unsafe {
-            let program = gl.create_program().unwrap();
-            gl.attach_shader(program, vertex_shader);
-            gl.attach_shader(program, fragment_shader);
-            gl.link_program(program);
-
-            if !gl.get_program_link_status(program) {
-                println!("{}", gl.get_program_info_log(program));
-                panic!();
-            }
+pub struct ShaderProgram {
+    program: Program,
+    // Looking up uniforms by string every frame is slow, so cache them.
+    uniform_locations: RefCell<HashMap<String, Option<UniformLocation>>>,
+    options: ShaderOptions,
+    files: ShaderFiles,
+    // See `resources::generation`.
+    generation: u32,
+}
+
+fn link_program(
+    gl: &GL,
+    vertex: (&str, &str),
+    fragment: (&str, &str),
+    options: &ShaderOptions,
+) -> Result<Program, ShaderError> {
+    let vertex_shader = compile_shader(gl, VERTEX_SHADER, vertex.0, vertex.1, &options.defines)?;
+    let fragment_shader = match compile_shader(
+        gl,
+        FRAGMENT_SHADER,
+        fragment.0,
+        fragment.1,
+        &options.defines,
+    ) {
+        Ok(shader) => shader,
+        Err(error) => {
+            unsafe { gl.delete_shader(vertex_shader) };
+            return Err(error);
+        }
+    };
+
+    unsafe {
+        let program = gl.create_program().unwrap();
+        gl.attach_shader(program, vertex_shader);
+        gl.attach_shader(program, fragment_shader);
+
+        // Fixed locations so meshes and batches can set up attributes without querying.
+        for (location, name) in options.attributes.iter().enumerate() {
+            gl.bind_attrib_location(program, location as u32, name);
+        }
+        gl.link_program(program);
+
+        // The program keeps what it needs.
+        gl.detach_shader(program, vertex_shader);
+        gl.detach_shader(program, fragment_shader);
+        gl.delete_shader(vertex_shader);
+        gl.delete_shader(fragment_shader);
 
-            ShaderProgram { program }
+        if !gl.get_program_link_status(program) {
+            let log = gl.get_program_info_log(program);
+            gl.delete_program(program);
+            return Err(ShaderError {
+                stage: "link",
+                log,
+                lines: Vec::new(),
+            });
         }
+        Ok(program)
+    }
+}
+
+impl ShaderProgram {
+    /// Builds a program from files in `src/shaders`, which `reload` can build again.
+    pub fn from_files(
+        gl: &GL,
+        vertex_file: &str,
+        fragment_file: &str,
+        options: ShaderOptions,
+    ) -> Result<Self, ShaderError> {
+        let files = ShaderFiles {
+            vertex: vertex_file.to_owned(),
+            fragment: fragment_file.to_owned(),
+        };
+        let program = Self::link_files(gl, &files, &options)?;
+        Ok(ShaderProgram {
+            program,
+            uniform_locations: RefCell::new(HashMap::new()),
+            options,
+            files,
+            generation: generation(),
+        })
+    }
+
+    fn link_files(
+        gl: &GL,
+        files: &ShaderFiles,
+        options: &ShaderOptions,
+    ) -> Result<Program, ShaderError> {
+        let read = |file: &str| {
+            shader_file(file).ok_or_else(|| ShaderError {
+                stage: "preprocess",
+                log: format!("Could not find {}", file),
+                lines: Vec::new(),
+            })
+        };
+        let vertex_source = read(&files.vertex)?;
+        let fragment_source = read(&files.fragment)?;
+        link_program(
+            gl,
+            (&files.vertex, &vertex_source),
+            (&files.fragment, &fragment_source),
+            options,
+        )
+    }
+
+    /// Rebuilds the program from its files, when they change or after the context was lost.
+    /// The old program is kept if this fails.
+    pub fn reload(&mut self, gl: &GL) -> Result<(), ShaderError> {
+        let program = Self::link_files(gl, &self.files, &self.options)?;
+        free(self.generation, Resource::Program(self.program));
+        self.program = program;
+        self.generation = generation();
+        self.uniform_locations.borrow_mut().clear();
+        Ok(())
     }
 
     pub fn use_program(&self, gl: &GL) {
@@ -53,24 +409,215 @@ impl ShaderProgram {
         }
     }
 
+    fn uniform_location(&self, gl: &GL, name: &str) -> Option<UniformLocation> {
+        let mut uniform_locations = self.uniform_locations.borrow_mut();
+        if let Some(location) = uniform_locations.get(name) {
+            return location.clone();
+        }
+        let location = unsafe { gl.get_uniform_location(self.program, name) };
+        uniform_locations.insert(name.to_owned(), location.clone());
+        location
+    }
+
     pub fn set_matrix(&self, gl: &GL, name: &str, m: &Matrix4x4) {
         unsafe {
-            let location = gl.get_uniform_location(self.program, name);
+            let location = self.uniform_location(gl, name);
             gl.uniform_matrix_4_f32_slice(location.as_ref(), false, &m.0);
         }
     }
 
     pub fn set_float(&self, gl: &GL, name: &str, f: f32) {
         unsafe {
-            let location = gl.get_uniform_location(self.program, name);
+            let location = self.uniform_location(gl, name);
             gl.uniform_1_f32(location.as_ref(), f);
         }
     }
 
+    pub fn set_int(&self, gl: &GL, name: &str, i: i32) {
+        unsafe {
+            let location = self.uniform_location(gl, name);
+            gl.uniform_1_i32(location.as_ref(), i);
+        }
+    }
+
+    pub fn set_vector2(&self, gl: &GL, name: &str, v: Vector2) {
+        unsafe {
+            let location = self.uniform_location(gl, name);
+            gl.uniform_2_f32(location.as_ref(), v.x, v.y);
+        }
+    }
+
     pub fn set_color(&self, gl: &GL, name: &str, color: &Color) {
         unsafe {
-            let location = gl.get_uniform_location(self.program, name);
+            let location = self.uniform_location(gl, name);
             gl.uniform_4_f32(location.as_ref(), color.r, color.g, color.b, color.a);
         }
     }
 }
+
+impl Drop for ShaderProgram {
+    fn drop(&mut self) {
+        free(self.generation, Resource::Program(self.program));
+    }
+}
+
+/// Notices when files in `src/shaders` change so programs can be reloaded while the game runs.
+/// Only native debug builds watch, elsewhere nothing ever changes.
+#[cfg(all(not(target_arch = "wasm32"), debug_assertions))]
+pub struct ShaderWatcher {
+    modified: HashMap<std::path::PathBuf, std::time::SystemTime>,
+    frames: u32,
+}
+
+#[cfg(all(not(target_arch = "wasm32"), debug_assertions))]
+impl ShaderWatcher {
+    /// Checking every frame isn't needed to feel instant.
+    const FRAMES_BETWEEN_CHECKS: u32 = 30;
+
+    pub fn new() -> Self {
+        let mut watcher = Self {
+            modified: HashMap::new(),
+            frames: 0,
+        };
+        watcher.scan();
+        watcher
+    }
+
+    /// Returns if anything changed since it was last scanned.
+    fn scan(&mut self) -> bool {
+        let entries = match std::fs::read_dir(SHADER_DIRECTORY) {
+            Ok(entries) => entries,
+            Err(_) => return false,
+        };
+        let mut changed = false;
+        for entry in entries.flatten() {
+            if let Ok(modified) = entry.metadata().and_then(|m| m.modified()) {
+                if self.modified.insert(entry.path(), modified) != Some(modified) {
+                    changed = true;
+                }
+            }
+        }
+        changed
+    }
+
+    /// Call once per frame.
+    pub fn changed(&mut self) -> bool {
+        self.frames += 1;
+        if self.frames < Self::FRAMES_BETWEEN_CHECKS {
+            return false;
+        }
+        self.frames = 0;
+        self.scan()
+    }
+}
+
+#[cfg(not(all(not(target_arch = "wasm32"), debug_assertions)))]
+pub struct ShaderWatcher;
+
+#[cfg(not(all(not(target_arch = "wasm32"), debug_assertions)))]
+impl ShaderWatcher {
+    pub fn new() -> Self {
+        ShaderWatcher
+    }
+
+    pub fn changed(&mut self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn includes_are_expanded_in_place() {
+        let source = "void a();\n#include \"common.glsl\"\nvoid b();";
+        let preprocessed = preprocess("test.fs", source, &[], VERTEX_SHADER).unwrap();
+
+        let common = shader_file("common.glsl").unwrap();
+        assert!(preprocessed.source.contains(&common));
+        assert!(!preprocessed.source.contains("#include"));
+        // The version and `GLSL_VERSION`, the line before, the included file and the line after.
+        let common_lines = common.lines().count();
+        assert_eq!(preprocessed.lines.len(), 2 + 1 + common_lines + 1);
+        assert_eq!(preprocessed.lines[2], ("test.fs".to_owned(), 1));
+        assert_eq!(preprocessed.lines[3], ("common.glsl".to_owned(), 1));
+        assert_eq!(
+            preprocessed.lines.last().unwrap(),
+            &("test.fs".to_owned(), 3)
+        );
+    }
+
+    #[test]
+    fn include_cycles_and_missing_files_are_errors() {
+        let error = preprocess("test.fs", "\n#include \"test.fs\"", &[], VERTEX_SHADER)
+            .err()
+            .unwrap();
+        assert_eq!(error.stage, "preprocess");
+        assert_eq!(error.lines[0].file, "test.fs");
+        assert_eq!(error.lines[0].line, 2);
+
+        let error = preprocess("test.fs", "#include \"missing.glsl\"", &[], VERTEX_SHADER)
+            .err()
+            .unwrap();
+        assert!(error.log.contains("missing.glsl"));
+    }
+
+    #[test]
+    fn defines_come_before_the_source() {
+        let defines = vec![("SAMPLES".to_owned(), "4".to_owned())];
+        let preprocessed =
+            preprocess("test.fs", "void main() {}", &defines, VERTEX_SHADER).unwrap();
+
+        let define = preprocessed.source.find("#define SAMPLES 4").unwrap();
+        assert!(define < preprocessed.source.find("void main").unwrap());
+        assert_eq!(preprocessed.lines[2], ("<preamble>".to_owned(), 0));
+        assert_eq!(preprocessed.lines[3], ("test.fs".to_owned(), 1));
+    }
+
+    #[test]
+    fn driver_errors_point_at_the_original_line() {
+        let source = "void a();\n#include \"common.glsl\"\nvoid b();";
+        let preprocessed = preprocess("test.fs", source, &[], VERTEX_SHADER).unwrap();
+        // The last line of the preprocessed source is `void b();`.
+        let last = preprocessed.lines.len();
+
+        for log in &[
+            format!("ERROR: 0:{}: 'b' : syntax error", last),
+            format!("0:{}(5): error: syntax error", last),
+            format!("0({}) : error C0000: syntax error", last),
+        ] {
+            let error = preprocessed.error("fragment", log.clone());
+            assert_eq!(error.lines.len(), 1, "{}", log);
+            assert_eq!(error.lines[0].file, "test.fs");
+            assert_eq!(error.lines[0].line, 3);
+            assert!(error.lines[0].message.contains("syntax error"), "{}", log);
+        }
+
+        let error = preprocessed.error("fragment", "ERROR: 0:4: in common".to_owned());
+        assert_eq!(error.lines[0].file, "common.glsl");
+        assert_eq!(error.lines[0].line, 1);
+
+        // Lines that can't be understood are kept in the log but not pointed anywhere.
+        let error = preprocessed.error("fragment", "Something went wrong".to_owned());
+        assert!(error.lines.is_empty());
+        assert!(error.to_string().contains("Something went wrong"));
+    }
+
+    #[test]
+    fn glsl_100_is_translated_word_by_word() {
+        assert_eq!(
+            translate("attribute vec2 uv; varying vec2 v_uv;", VERTEX_SHADER),
+            "in vec2 uv; out vec2 v_uv;"
+        );
+        assert_eq!(
+            translate("  gl_FragColor = texture2D(image, v_uv);", FRAGMENT_SHADER),
+            "  frag_color = texture(image, v_uv);"
+        );
+        // Only whole words, so names that contain them are left alone.
+        assert_eq!(
+            translate("float varying_width = attributes.x;", FRAGMENT_SHADER),
+            "float varying_width = attributes.x;"
+        );
+    }
+}
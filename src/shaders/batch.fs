@@ -0,0 +1,15 @@
+precision mediump float;
+
+#include "common.glsl"
+
+uniform float u_fade;
+// World units covered by one pixel, 0.0 disables anti-aliasing.
+uniform float u_pixel_size;
+
+varying vec4 v_color;
+varying float v_edge;
+
+void main() {
+    float coverage = edge_coverage(v_edge, u_pixel_size);
+    gl_FragColor = v_color * u_fade * coverage;
+}
\ No newline at end of file
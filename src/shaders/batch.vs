@@ -0,0 +1,15 @@
+attribute vec3 position;
+attribute vec4 color;
+attribute float edge;
+
+uniform mat4 u_view;
+uniform mat4 u_projection;
+
+varying vec4 v_color;
+varying float v_edge;
+
+void main() {
+    v_color = color;
+    v_edge = edge;
+    gl_Position = u_projection * u_view * vec4(position, 1.0);
+}
\ No newline at end of file
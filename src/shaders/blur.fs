@@ -0,0 +1,21 @@
+precision mediump float;
+
+uniform sampler2D u_texture;
+// One texel along the direction being blurred.
+uniform vec2 u_direction;
+
+varying vec2 v_uv;
+
+void main() {
+    // A 9 tap gaussian, applied once horizontally and once vertically.
+    vec3 color = texture2D(u_texture, v_uv).rgb * 0.227027;
+    color += texture2D(u_texture, v_uv + u_direction * 1.0).rgb * 0.1945946;
+    color += texture2D(u_texture, v_uv - u_direction * 1.0).rgb * 0.1945946;
+    color += texture2D(u_texture, v_uv + u_direction * 2.0).rgb * 0.1216216;
+    color += texture2D(u_texture, v_uv - u_direction * 2.0).rgb * 0.1216216;
+    color += texture2D(u_texture, v_uv + u_direction * 3.0).rgb * 0.054054;
+    color += texture2D(u_texture, v_uv - u_direction * 3.0).rgb * 0.054054;
+    color += texture2D(u_texture, v_uv + u_direction * 4.0).rgb * 0.016216;
+    color += texture2D(u_texture, v_uv - u_direction * 4.0).rgb * 0.016216;
+    gl_FragColor = vec4(color, 1.0);
+}
\ No newline at end of file
@@ -0,0 +1,14 @@
+precision mediump float;
+
+uniform sampler2D u_texture;
+// How bright a pixel's brightest channel has to be to start glowing.
+uniform float u_threshold;
+
+varying vec2 v_uv;
+
+void main() {
+    vec3 color = texture2D(u_texture, v_uv).rgb;
+    float brightness = max(color.r, max(color.g, color.b));
+    float amount = clamp((brightness - u_threshold) / max(1.0 - u_threshold, 0.0001), 0.0, 1.0);
+    gl_FragColor = vec4(color * amount, 1.0);
+}
\ No newline at end of file
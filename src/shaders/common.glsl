@@ -0,0 +1,6 @@
+// Fades the last pixel of a shape using its distance to the edge, in world units.
+// Eases in and out so edges don't look hard where the fade starts and stops.
+// A pixel size of 0.0 disables anti-aliasing.
+float edge_coverage(float edge, float pixel_size) {
+    return pixel_size > 0.0 ? smoothstep(0.0, pixel_size, edge) : 1.0;
+}
\ No newline at end of file
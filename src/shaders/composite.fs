@@ -0,0 +1,24 @@
+precision mediump float;
+
+uniform sampler2D u_scene;
+uniform sampler2D u_bloom;
+uniform float u_bloom_intensity;
+uniform float u_exposure;
+uniform float u_contrast;
+uniform float u_saturation;
+uniform vec4 u_tint;
+
+varying vec2 v_uv;
+
+void main() {
+    vec3 color = texture2D(u_scene, v_uv).rgb + texture2D(u_bloom, v_uv).rgb * u_bloom_intensity;
+
+    // Colour grading
+    color *= u_exposure;
+    float luma = dot(color, vec3(0.299, 0.587, 0.114));
+    color = mix(vec3(luma), color, u_saturation);
+    color = (color - 0.5) * u_contrast + 0.5;
+    color *= u_tint.rgb;
+
+    gl_FragColor = vec4(clamp(color, 0.0, 1.0), 1.0);
+}
\ No newline at end of file
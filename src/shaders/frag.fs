@@ -1,8 +1,15 @@
 precision mediump float;
 
+#include "common.glsl"
+
 uniform vec4 u_color;
 uniform float u_fade;
+// World units covered by one pixel, 0.0 disables anti-aliasing.
+uniform float u_pixel_size;
+
+varying float v_edge;
 
 void main() {
-    gl_FragColor = u_color * u_fade;
+    float coverage = edge_coverage(v_edge, u_pixel_size);
+    gl_FragColor = u_color * u_fade * coverage;
 }
\ No newline at end of file
@@ -0,0 +1,11 @@
+precision mediump float;
+
+uniform sampler2D u_texture;
+uniform vec4 u_color;
+uniform float u_fade;
+
+varying vec2 v_uv;
+
+void main() {
+    gl_FragColor = texture2D(u_texture, v_uv) * u_color * u_fade;
+}
\ No newline at end of file
@@ -0,0 +1,13 @@
+attribute vec3 position;
+attribute vec2 uv;
+
+uniform mat4 u_model;
+uniform mat4 u_view;
+uniform mat4 u_projection;
+
+varying vec2 v_uv;
+
+void main() {
+    v_uv = uv;
+    gl_Position = u_projection * u_view * u_model * vec4(position, 1.0);
+}
\ No newline at end of file
@@ -0,0 +1,17 @@
+precision mediump float;
+
+uniform sampler2D u_texture;
+uniform vec4 u_color;
+uniform float u_fade;
+// Half the distance field values covered by one pixel, 0.5 for bitmap fonts.
+uniform float u_smoothing;
+
+varying vec2 v_uv;
+
+void main() {
+    // Works for greyscale atlases and for white glyphs on a transparent atlas.
+    vec4 texel = texture2D(u_texture, v_uv);
+    float field = texel.r * texel.a;
+    float coverage = smoothstep(0.5 - u_smoothing, 0.5 + u_smoothing, field);
+    gl_FragColor = u_color * u_fade * coverage;
+}
\ No newline at end of file
@@ -1,9 +1,13 @@
 attribute vec3 position;
+attribute float edge;
 
 uniform mat4 u_model;
 uniform mat4 u_view;
 uniform mat4 u_projection;
 
+varying float v_edge;
+
 void main() {
+    v_edge = edge;
     gl_Position = u_projection * u_view * u_model * vec4(position, 1.0);
 }
\ No newline at end of file
@@ -0,0 +1,645 @@
+use crate::*;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// Rasterizes frames on the CPU so they can be rendered without a window or GL context,
+/// for example to compare a level against a golden image on a headless machine.
+/// Matches the GL path's `SRC_ALPHA, ONE_MINUS_SRC_ALPHA` blending and its shaders.
+pub struct SoftwareRenderer {
+    pub width: u32,
+    pub height: u32,
+    /// Rows from top to bottom, RGBA in 0.0 to 1.0.
+    pub pixels: Vec<[f32; 4]>,
+    settings: Option<FrameSettings>,
+    textures: HashMap<String, Rc<SoftwareTexture>>,
+}
+
+/// An image sampled the way GL samples it with the same `TextureParameters`.
+/// Mipmaps aren't made, textures are always sampled with their `mag_filter`.
+pub struct SoftwareTexture {
+    width: u32,
+    height: u32,
+    /// Rows from top to bottom, RGBA in 0.0 to 1.0.
+    pixels: Vec<[f32; 4]>,
+    parameters: TextureParameters,
+}
+
+impl SoftwareTexture {
+    /// `rgba` is 8 bit RGBA rows starting at the top, like `decode_png` returns.
+    pub fn from_rgba(width: u32, height: u32, rgba: &[u8], parameters: &TextureParameters) -> Self {
+        let pixels = rgba
+            .chunks(4)
+            .map(|p| {
+                [
+                    p[0] as f32 / 255.0,
+                    p[1] as f32 / 255.0,
+                    p[2] as f32 / 255.0,
+                    p[3] as f32 / 255.0,
+                ]
+            })
+            .collect();
+        Self {
+            width,
+            height,
+            pixels,
+            parameters: *parameters,
+        }
+    }
+
+    /// `uv` is 0.0 to 1.0 across the image, with v = 0.0 at the top row like GL uploads.
+    fn sample(&self, uv: Vector2) -> [f32; 4] {
+        if self.width == 0 || self.height == 0 {
+            return [0.0; 4];
+        }
+        let x = uv.x * self.width as f32;
+        let y = uv.y * self.height as f32;
+        if self.parameters.mag_filter == Filter::Nearest {
+            return self.texel(x.floor(), y.floor());
+        }
+
+        // Texel centers are half a texel in, so blend between the four nearest.
+        let (x, y) = (x - 0.5, y - 0.5);
+        let (x0, y0) = (x.floor(), y.floor());
+        let (tx, ty) = (x - x0, y - y0);
+        let (a, b) = (self.texel(x0, y0), self.texel(x0 + 1.0, y0));
+        let (c, d) = (self.texel(x0, y0 + 1.0), self.texel(x0 + 1.0, y0 + 1.0));
+        let mut result = [0.0; 4];
+        for i in 0..4 {
+            let top = a[i] + (b[i] - a[i]) * tx;
+            let bottom = c[i] + (d[i] - c[i]) * tx;
+            result[i] = top + (bottom - top) * ty;
+        }
+        result
+    }
+
+    /// The texel at `x y`, which are wrapped back into the image first.
+    fn texel(&self, x: f32, y: f32) -> [f32; 4] {
+        let wrap = |i: f32, size: u32| {
+            let size = size as i64;
+            let i = i as i64;
+            (match self.parameters.wrap {
+                Wrap::Clamp => i.max(0).min(size - 1),
+                Wrap::Repeat => i.rem_euclid(size),
+                Wrap::Mirror => {
+                    let i = i.rem_euclid(size * 2);
+                    if i < size {
+                        i
+                    } else {
+                        size * 2 - 1 - i
+                    }
+                }
+            }) as u32
+        };
+        let (x, y) = (wrap(x, self.width), wrap(y, self.height));
+        self.pixels[(y * self.width + x) as usize]
+    }
+}
+
+impl SoftwareRenderer {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![[0.0; 4]; (width * height) as usize],
+            settings: None,
+            textures: HashMap::new(),
+        }
+    }
+
+    /// Makes `texture` available to `draw_sprite` and `draw_text` as `name`.
+    pub fn add_texture(&mut self, name: &str, texture: SoftwareTexture) {
+        self.textures.insert(name.to_owned(), Rc::new(texture));
+    }
+
+    /// Reads the images a level's decals use, the same ones the game loads for GL.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load_decals(&mut self, level_data: &str) -> Result<(), String> {
+        for (image, parameters) in editor::decal_images(level_data) {
+            if self.textures.contains_key(&image) {
+                continue;
+            }
+            let (width, height, rgba) = read_png(&image)?;
+            let texture = SoftwareTexture::from_rgba(width, height, &rgba, &parameters);
+            self.add_texture(&image, texture);
+        }
+        Ok(())
+    }
+
+    /// Transforms a world position into pixel coordinates, with y pointing down.
+    fn to_screen(&self, position: Vector3) -> (f32, f32) {
+        let settings = self.settings.as_ref().expect("begin_frame was not called");
+        let p = mat4_transform_point(
+            &settings.projection,
+            mat4_transform_point(&settings.view, position),
+        );
+        let viewport = settings.viewport;
+        (
+            viewport.x as f32 + (p.x + 1.0) * 0.5 * viewport.width as f32,
+            self.height as f32 - viewport.y as f32 - (p.y + 1.0) * 0.5 * viewport.height as f32,
+        )
+    }
+
+    /// Each vertex is a screen position, an edge distance and a colour.
+    fn fill_triangle(&mut self, vertices: [((f32, f32), f32, Color); 3]) {
+        let settings = *self.settings.as_ref().unwrap();
+        let [(a, edge_a, color_a), (b, edge_b, color_b), (c, edge_c, color_c)] = vertices;
+        self.rasterize([a, b, c], |[w_a, w_b, w_c]| {
+            let edge = edge_a * w_a + edge_b * w_b + edge_c * w_c;
+            // The same smoothstep as `edge_coverage` in the shaders.
+            let coverage = if settings.pixel_size > 0.0 {
+                smoothstep(0.0, settings.pixel_size, edge)
+            } else {
+                1.0
+            };
+            let scale = settings.fade * coverage;
+            [
+                (color_a.r * w_a + color_b.r * w_b + color_c.r * w_c) * scale,
+                (color_a.g * w_a + color_b.g * w_b + color_c.g * w_c) * scale,
+                (color_a.b * w_a + color_b.b * w_b + color_c.b * w_c) * scale,
+                (color_a.a * w_a + color_b.a * w_b + color_c.a * w_c) * scale,
+            ]
+        });
+    }
+
+    /// Draws the quad `corners`, counter-clockwise from the bottom left, as two triangles.
+    /// `shade` turns the texel at each pixel into the colour to blend, like a fragment shader.
+    fn fill_textured_quad(
+        &mut self,
+        corners: [Vector3; 4],
+        uvs: [Vector2; 4],
+        texture: &SoftwareTexture,
+        shade: impl Fn([f32; 4]) -> [f32; 4],
+    ) {
+        let screen: Vec<_> = corners.iter().map(|&p| self.to_screen(p)).collect();
+        for &[i, j, k] in &[[0, 1, 2], [0, 2, 3]] {
+            let (uv_a, uv_b, uv_c) = (uvs[i], uvs[j], uvs[k]);
+            self.rasterize([screen[i], screen[j], screen[k]], |[w_a, w_b, w_c]| {
+                let uv = Vector2::new(
+                    uv_a.x * w_a + uv_b.x * w_b + uv_c.x * w_c,
+                    uv_a.y * w_a + uv_b.y * w_b + uv_c.y * w_c,
+                );
+                shade(texture.sample(uv))
+            });
+        }
+    }
+
+    /// Blends the colour `shade` returns for the barycentric weights of each pixel
+    /// whose center is inside the triangle `a b c`, given in screen coordinates.
+    fn rasterize(&mut self, vertices: [(f32, f32); 3], shade: impl Fn([f32; 3]) -> [f32; 4]) {
+        let settings = *self.settings.as_ref().unwrap();
+        let [a, mut b, mut c] = vertices;
+
+        let area = edge_function(a, b, c);
+        if area.abs() < std::f32::EPSILON {
+            return;
+        }
+        // Wind every triangle the same way so shared edges can be assigned to just one of them.
+        let flipped = area < 0.0;
+        if flipped {
+            std::mem::swap(&mut b, &mut c);
+        }
+        let area = area.abs();
+
+        // Like GL, nothing is drawn outside the viewport, so letterbox bars stay clear.
+        let viewport = settings.viewport;
+        let left = viewport.x.min(self.width);
+        let right = (viewport.x + viewport.width).min(self.width);
+        let top = self.height.saturating_sub(viewport.y + viewport.height);
+        let bottom = self.height.saturating_sub(viewport.y);
+
+        let min_x = (a.0.min(b.0).min(c.0).floor().max(0.0) as u32).max(left);
+        let min_y = (a.1.min(b.1).min(c.1).floor().max(0.0) as u32).max(top);
+        let max_x = (a.0.max(b.0).max(c.0).ceil().max(0.0) as u32).min(right);
+        let max_y = (a.1.max(b.1).max(c.1).ceil().max(0.0) as u32).min(bottom);
+
+        for y in min_y..max_y {
+            for x in min_x..max_x {
+                // Sample at the pixel center like GL does.
+                let p = (x as f32 + 0.5, y as f32 + 0.5);
+                let w_a = edge_function(b, c, p);
+                let w_b = edge_function(c, a, p);
+                let w_c = edge_function(a, b, p);
+
+                // Pixels exactly on a shared edge only belong to one of the triangles,
+                // otherwise translucent shapes would be blended twice along their seams.
+                if !(owns(w_a, b, c) && owns(w_b, c, a) && owns(w_c, a, b)) {
+                    continue;
+                }
+                let (w_a, w_b, w_c) = (w_a / area, w_b / area, w_c / area);
+                // Weights go back to the order the vertices were given in.
+                let source = if flipped {
+                    shade([w_a, w_c, w_b])
+                } else {
+                    shade([w_a, w_b, w_c])
+                };
+
+                let destination = &mut self.pixels[(y * self.width + x) as usize];
+                let alpha = source[3].max(0.0).min(1.0);
+                for i in 0..4 {
+                    destination[i] = source[i] * alpha + destination[i] * (1.0 - alpha);
+                }
+            }
+        }
+    }
+
+    /// Pixels as 8 bit RGBA, fully opaque like the canvas they'd be shown on.
+    pub fn to_rgba8(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.pixels.len() * 4);
+        for pixel in &self.pixels {
+            for channel in &pixel[0..3] {
+                bytes.push((channel.max(0.0).min(1.0) * 255.0).round() as u8);
+            }
+            bytes.push(255);
+        }
+        bytes
+    }
+
+    pub fn to_png(&self) -> Vec<u8> {
+        encode_png(self.width, self.height, &self.to_rgba8())
+    }
+
+    #[cfg(test)]
+    pub fn save_png(&self, path: &str) -> std::io::Result<()> {
+        std::fs::write(path, self.to_png())
+    }
+
+    /// Compares against the PNG at `path`, allowing each channel to differ by `tolerance`.
+    /// If `UPDATE_GOLDEN` is set the golden image is written instead.
+    #[cfg(test)]
+    pub fn compare_to_golden(&self, path: &str, tolerance: u8) -> Result<(), String> {
+        if std::env::var("UPDATE_GOLDEN").is_ok() {
+            return self.save_png(path).map_err(|e| e.to_string());
+        }
+
+        let golden = std::fs::read(path).map_err(|e| {
+            format!(
+                "Could not read golden image {}: {}, run with UPDATE_GOLDEN=1 to write it",
+                path, e
+            )
+        })?;
+        let (width, height, golden) = decode_png(&golden)?;
+        if width != self.width || height != self.height {
+            return Err(format!(
+                "Golden image is {}x{} but the frame is {}x{}",
+                width, height, self.width, self.height
+            ));
+        }
+
+        let differing = self
+            .to_rgba8()
+            .chunks(4)
+            .zip(golden.chunks(4))
+            .filter(|(a, b)| {
+                a.iter()
+                    .zip(b.iter())
+                    .any(|(a, b)| (*a as i16 - *b as i16).abs() > tolerance as i16)
+            })
+            .count();
+
+        if differing > 0 {
+            // Keep the failing frame around to look at.
+            let _ = self.save_png(&format!("{}.actual.png", path));
+            Err(format!("{} pixels differ from {}", differing, path))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl RenderBackend for SoftwareRenderer {
+    fn begin_frame(&mut self, clear_color: Color, settings: &FrameSettings) {
+        self.settings = Some(*settings);
+        let clear = [clear_color.r, clear_color.g, clear_color.b, clear_color.a];
+        for pixel in &mut self.pixels {
+            *pixel = clear;
+        }
+    }
+
+    fn draw_lines(&mut self, lines: &mut Lines, color: Color) {
+        let (vertices, indices) = lines.geometry();
+        let vertices: Vec<_> = vertices
+            .iter()
+            .map(|v| (self.to_screen(v.position), v.edge, color))
+            .collect();
+        for tri in indices {
+            self.fill_triangle([
+                vertices[tri[0] as usize],
+                vertices[tri[1] as usize],
+                vertices[tri[2] as usize],
+            ]);
+        }
+    }
+
+    fn draw_batch(&mut self, batch: &mut ShapeBatch) {
+        let vertices: Vec<_> = batch
+            .vertices
+            .iter()
+            .map(|v| (self.to_screen(v.position), v.edge, v.color))
+            .collect();
+        for tri in batch.indices.chunks(3) {
+            self.fill_triangle([
+                vertices[tri[0] as usize],
+                vertices[tri[1] as usize],
+                vertices[tri[2] as usize],
+            ]);
+        }
+    }
+
+    fn draw_sprite(&mut self, image: &str, corners: [Vector3; 4], uvs: [Vector2; 4], color: Color) {
+        let texture = match self.textures.get(image) {
+            Some(texture) => texture.clone(),
+            None => return,
+        };
+        let fade = self
+            .settings
+            .as_ref()
+            .expect("begin_frame was not called")
+            .fade;
+        // The same as `sprite.fs`.
+        self.fill_textured_quad(corners, uvs, &texture, |texel| {
+            [
+                texel[0] * color.r * fade,
+                texel[1] * color.g * fade,
+                texel[2] * color.b * fade,
+                texel[3] * color.a * fade,
+            ]
+        });
+    }
+
+    fn draw_text(&mut self, font: &Font, text: &PlacedText, color: Color) {
+        let texture = match self.textures.get(&font.image) {
+            Some(texture) => texture.clone(),
+            None => return,
+        };
+        let settings = *self.settings.as_ref().expect("begin_frame was not called");
+        let smoothing = font.smoothing(text, &settings);
+        for glyph in &text.glyphs {
+            let (min, max) = (glyph.uv_min, glyph.uv_max);
+            let uvs = [
+                Vector2::new(min.x, max.y),
+                Vector2::new(max.x, max.y),
+                Vector2::new(max.x, min.y),
+                Vector2::new(min.x, min.y),
+            ];
+            // The same as `text.fs`.
+            self.fill_textured_quad(glyph.corners, uvs, &texture, |texel| {
+                let field = texel[0] * texel[3];
+                let scale = settings.fade * smoothstep(0.5 - smoothing, 0.5 + smoothing, field);
+                [
+                    color.r * scale,
+                    color.g * scale,
+                    color.b * scale,
+                    color.a * scale,
+                ]
+            });
+        }
+    }
+}
+
+/// Plays a level's intro drawing through to the end and renders the result,
+/// which is what the player sees right before they start drawing.
+pub fn render_level(level_data: &str, width: u32, height: u32) -> Result<SoftwareRenderer, String> {
+    let mut level = Level::new(Vector3::ZERO);
+    let mut lines = Lines::new();
+    let mut user_lines = Lines::new();
+    let mut mouse_playback = MousePlayback::new();
+
+    editor::load(&mut mouse_playback, &mut level, level_data)?;
+    lines.style.join = level.line_join;
+    user_lines.style.join = level.line_join;
+    mouse_playback.play_until_end(&mut lines, &mut level);
+    for collectible in &mut level.collectibles {
+        collectible.alpha = 1.0;
+    }
+    let ball = Ball::new(level.start_position);
+
+    // Zoom all the way out so levels bigger than a screen are shown whole.
+    let mut camera = Camera::game(width, height);
+    let mut camera_controller = CameraController::new(level.bounds);
+    camera_controller.zoom = std::f32::MAX;
+    camera_controller.apply(&mut camera);
+    let settings = FrameSettings::new(&camera, 1.0, camera.world_units_per_pixel());
+
+    let mut renderer = SoftwareRenderer::new(width, height);
+    #[cfg(not(target_arch = "wasm32"))]
+    renderer.load_decals(level_data)?;
+    draw_scene(
+        &mut renderer,
+        &settings,
+        &mut ShapeBatch::new(30),
+        &level,
+        &level.theme,
+        &mut lines,
+        &mut user_lines,
+        &ball,
+        &Particles::new(0),
+    );
+    Ok(renderer)
+}
+
+/// Like GLSL's `smoothstep`.
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).max(0.0).min(1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+fn edge_function(a: (f32, f32), b: (f32, f32), p: (f32, f32)) -> f32 {
+    (b.0 - a.0) * (p.1 - a.1) - (b.1 - a.1) * (p.0 - a.0)
+}
+
+/// Whether a pixel with edge weight `w` for the edge `from` to `to` is inside the triangle.
+/// Neighbouring triangles walk a shared edge in opposite directions, so only one owns it.
+fn owns(w: f32, from: (f32, f32), to: (f32, f32)) -> bool {
+    let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+    w > 0.0 || (w == 0.0 && (dy < 0.0 || (dy == 0.0 && dx > 0.0)))
+}
+
+pub fn encode_png(width: u32, height: u32, rgba: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut bytes, width, height);
+        encoder.set_color(png::ColorType::RGBA);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(rgba).unwrap();
+    }
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn level_matches_golden() {
+        let renderer = render_level(include_str!("levels/level0.txt"), 320, 240).unwrap();
+        let golden = concat!(env!("CARGO_MANIFEST_DIR"), "/src/goldens/level0.png");
+        renderer.compare_to_golden(golden, 2).unwrap();
+    }
+
+    #[test]
+    fn background_matches_golden() {
+        // Stars shouldn't depend on what else used random numbers first.
+        rng::with_stream(RngStream::Procedural, |rng| rng.next_u32());
+        let level = include_str!("levels/distant_mountains.txt");
+        let renderer = render_level(level, 320, 240).unwrap();
+        let golden = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/src/goldens/distant_mountains.png"
+        );
+        renderer.compare_to_golden(golden, 2).unwrap();
+    }
+
+    #[test]
+    fn triangles_stay_inside_the_viewport() {
+        let mut camera = Camera::game(8, 4);
+        camera.resize(8, 4);
+        let settings = FrameSettings::new(&camera, 1.0, 0.0);
+        let mut renderer = SoftwareRenderer::new(8, 4);
+        renderer.begin_frame(Color::new(0.0, 0.0, 0.0, 1.0), &settings);
+
+        // Covers the whole screen, including the letterbox bars on the sides.
+        let white = Color::new(1.0, 1.0, 1.0, 1.0);
+        renderer.fill_triangle([
+            ((-10.0, -10.0), 1.0, white),
+            ((30.0, -10.0), 1.0, white),
+            ((-10.0, 30.0), 1.0, white),
+        ]);
+
+        let lit: Vec<bool> = renderer.pixels[0..8].iter().map(|p| p[0] > 0.5).collect();
+        assert_eq!(lit, [false, false, true, true, true, true, false, false]);
+    }
+
+    #[test]
+    fn decals_match_golden() {
+        let renderer = render_level(include_str!("levels/level1.txt"), 320, 240).unwrap();
+        let golden = concat!(env!("CARGO_MANIFEST_DIR"), "/src/goldens/level1.png");
+        renderer.compare_to_golden(golden, 2).unwrap();
+    }
+
+    #[test]
+    fn sprites_are_textured() {
+        let mut camera = Camera::game(4, 4);
+        camera.resize(4, 4);
+        let settings = FrameSettings::new(&camera, 1.0, 0.0);
+        let mut renderer = SoftwareRenderer::new(4, 4);
+        renderer.begin_frame(Color::new(0.0, 0.0, 0.0, 1.0), &settings);
+
+        // Red and green on the top row, blue and white on the bottom.
+        let rgba = [
+            255, 0, 0, 255, 0, 255, 0, 255, 0, 0, 255, 255, 255, 255, 255, 255,
+        ];
+        let texture = SoftwareTexture::from_rgba(2, 2, &rgba, &TextureParameters::default());
+        renderer.add_texture("test.png", texture);
+        let corners = screen_corners(&settings);
+        let uvs = repeated_uvs(1.0);
+        renderer.draw_sprite("test.png", corners, uvs, Color::new(1.0, 1.0, 1.0, 1.0));
+
+        let rgb = |x: usize, y: usize| {
+            let p = renderer.pixels[y * 4 + x];
+            (p[0].round(), p[1].round(), p[2].round())
+        };
+        assert_eq!(rgb(0, 0), (1.0, 0.0, 0.0));
+        assert_eq!(rgb(3, 0), (0.0, 1.0, 0.0));
+        assert_eq!(rgb(0, 3), (0.0, 0.0, 1.0));
+        assert_eq!(rgb(3, 3), (1.0, 1.0, 1.0));
+
+        // Images that weren't loaded are skipped.
+        renderer.begin_frame(Color::new(0.0, 0.0, 0.0, 1.0), &settings);
+        renderer.draw_sprite("missing.png", corners, uvs, Color::new(1.0, 1.0, 1.0, 1.0));
+        assert!(renderer.pixels.iter().all(|p| p[0] == 0.0));
+    }
+
+    /// Corners covering the whole of a square screen.
+    fn screen_corners(settings: &FrameSettings) -> [Vector3; 4] {
+        let (min, max) = (settings.visible_bounds.min, settings.visible_bounds.max);
+        [
+            Vector3::new(min.x, min.y, 0.0),
+            Vector3::new(max.x, min.y, 0.0),
+            Vector3::new(max.x, max.y, 0.0),
+            Vector3::new(min.x, max.y, 0.0),
+        ]
+    }
+
+    /// Like `Decal::uvs`, with the image drawn `repeat` times across and up.
+    fn repeated_uvs(repeat: f32) -> [Vector2; 4] {
+        [
+            Vector2::new(0.0, repeat),
+            Vector2::new(repeat, repeat),
+            Vector2::new(repeat, 0.0),
+            Vector2::new(0.0, 0.0),
+        ]
+    }
+
+    #[test]
+    fn sprites_wrap_and_filter_like_gl() {
+        let mut camera = Camera::game(4, 4);
+        camera.resize(4, 4);
+        let settings = FrameSettings::new(&camera, 1.0, 0.0);
+        let mut renderer = SoftwareRenderer::new(4, 4);
+
+        // Red then green, drawn twice across the screen so each texel covers a pixel.
+        let rgba = [255, 0, 0, 255, 0, 255, 0, 255];
+        let row = |renderer: &mut SoftwareRenderer, wrap| {
+            let parameters = TextureParameters {
+                min_filter: Filter::Nearest,
+                mag_filter: Filter::Nearest,
+                wrap,
+                mipmaps: false,
+            };
+            renderer.add_texture(
+                "test.png",
+                SoftwareTexture::from_rgba(2, 1, &rgba, &parameters),
+            );
+            renderer.begin_frame(Color::new(0.0, 0.0, 0.0, 1.0), &settings);
+            let corners = screen_corners(&settings);
+            let color = Color::new(1.0, 1.0, 1.0, 1.0);
+            renderer.draw_sprite("test.png", corners, repeated_uvs(2.0), color);
+            renderer.pixels[0..4]
+                .iter()
+                .map(|p| if p[0] > 0.5 { 'r' } else { 'g' })
+                .collect::<String>()
+        };
+
+        assert_eq!(row(&mut renderer, Wrap::Clamp), "rggg");
+        assert_eq!(row(&mut renderer, Wrap::Repeat), "rgrg");
+        assert_eq!(row(&mut renderer, Wrap::Mirror), "rggr");
+    }
+
+    #[test]
+    fn text_is_drawn_from_the_font_atlas() {
+        let mut camera = Camera::game(64, 64);
+        camera.resize(64, 64);
+        let settings = FrameSettings::new(&camera, 1.0, camera.world_units_per_pixel());
+        let mut renderer = SoftwareRenderer::new(64, 64);
+        renderer.begin_frame(Color::new(0.0, 0.0, 0.0, 1.0), &settings);
+
+        let font = Font::parse(include_str!("fonts/dejavu_sans.fnt")).unwrap();
+        let (width, height, rgba) = read_png(&font.image).unwrap();
+        renderer.add_texture(
+            &font.image,
+            SoftwareTexture::from_rgba(width, height, &rgba, &TextureParameters::default()),
+        );
+        let bounds = settings.visible_bounds;
+        let mut text = font.layout(
+            "H",
+            &TextLayout {
+                size: bounds.size().y,
+                align: Align::Center,
+                max_width: None,
+            },
+        );
+        text.translate(Vector3::new(bounds.center().x, bounds.max.y, 0.0));
+        renderer.draw_text(&font, &text, Color::new(1.0, 1.0, 1.0, 1.0));
+
+        // Some of the frame is covered by the letter, but not all of it.
+        let lit = renderer.pixels.iter().filter(|p| p[0] > 0.5).count();
+        assert!(lit > 64 && lit < 64 * 64 / 2, "{} pixels lit", lit);
+    }
+
+    #[test]
+    fn missing_golden_is_an_error() {
+        let renderer = SoftwareRenderer::new(4, 4);
+        assert!(renderer.compare_to_golden("does_not_exist.png", 0).is_err());
+    }
+}
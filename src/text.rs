@@ -0,0 +1,464 @@
+use crate::*;
+use std::collections::HashMap;
+
+/// How a title card fades, in ticks.
+const TITLE_FADE_IN: u32 = 60;
+const TITLE_HOLD: u32 = 150;
+const TITLE_FADE_OUT: u32 = 90;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+/// Where a character is in a font's atlas and how it's placed, in the atlas's pixels.
+#[derive(Debug, Clone, Copy)]
+pub struct Glyph {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    /// From the pen to the left of the glyph.
+    pub x_offset: f32,
+    /// From the top of the line down to the top of the glyph.
+    pub y_offset: f32,
+    pub x_advance: f32,
+}
+
+/// A font in the BMFont text format with a single page.
+/// Its atlas is loaded separately, like any other image, as `image`.
+pub struct Font {
+    pub image: String,
+    /// Atlas pixels from one line to the next.
+    pub line_height: f32,
+    pub atlas_size: Vector2,
+    /// How many atlas pixels the distance field spans from fully outside to fully inside a glyph,
+    /// 0.0 for bitmap fonts.
+    pub distance_range: f32,
+    glyphs: HashMap<char, Glyph>,
+    kerning: HashMap<(char, char), f32>,
+}
+
+impl Font {
+    /// How far either side of the edge, in distance field values, to soften text over,
+    /// which is about a pixel however big the text is on screen. 0.5 for bitmap fonts.
+    pub fn smoothing(&self, text: &PlacedText, settings: &FrameSettings) -> f32 {
+        if self.distance_range > 0.0 {
+            let pixel_size =
+                settings.visible_bounds.size().x / settings.viewport.width.max(1) as f32;
+            let atlas_pixels = pixel_size * self.line_height / text.size;
+            (atlas_pixels / self.distance_range / 2.0).max(0.001)
+        } else {
+            0.5
+        }
+    }
+
+    pub fn parse(s: &str) -> Result<Font, String> {
+        let mut image = None;
+        let mut line_height = None;
+        let mut atlas_size = None;
+        let mut distance_range = 0.0;
+        let mut glyphs = HashMap::new();
+        let mut kerning = HashMap::new();
+
+        for line in s.lines() {
+            let tag = line.split_whitespace().next().unwrap_or("");
+            let values = attributes(line);
+            let number = |key: &str| -> Result<f32, String> {
+                let value = values
+                    .get(key)
+                    .ok_or_else(|| format!("Font's {} is missing {}", tag, key))?;
+                value
+                    .parse()
+                    .map_err(|_| format!("Invalid {} {:?} in font's {}", key, value, tag))
+            };
+            let character = |key: &str| -> Result<char, String> {
+                let id = number(key)? as u32;
+                std::char::from_u32(id).ok_or_else(|| format!("Invalid character {} in font", id))
+            };
+
+            match tag {
+                "common" => {
+                    line_height = Some(number("lineHeight")?);
+                    atlas_size = Some(Vector2::new(number("scaleW")?, number("scaleH")?));
+                    if number("pages")? != 1.0 {
+                        return Err("Only fonts with one page are supported".to_owned());
+                    }
+                }
+                "page" => {
+                    image = values.get("file").map(|file| file.to_string());
+                }
+                "distanceField" => {
+                    distance_range = number("distanceRange")?;
+                }
+                "char" => {
+                    glyphs.insert(
+                        character("id")?,
+                        Glyph {
+                            x: number("x")?,
+                            y: number("y")?,
+                            width: number("width")?,
+                            height: number("height")?,
+                            x_offset: number("xoffset")?,
+                            y_offset: number("yoffset")?,
+                            x_advance: number("xadvance")?,
+                        },
+                    );
+                }
+                "kerning" => {
+                    kerning.insert(
+                        (character("first")?, character("second")?),
+                        number("amount")?,
+                    );
+                }
+                _ => {}
+            }
+        }
+
+        Ok(Font {
+            image: image.ok_or("Font has no page")?,
+            line_height: line_height.ok_or("Font has no common line")?,
+            atlas_size: atlas_size.ok_or("Font has no common line")?,
+            distance_range,
+            glyphs,
+            kerning,
+        })
+    }
+
+    /// Characters the font doesn't have are drawn as '?', or skipped if it doesn't have that either.
+    fn glyph(&self, character: char) -> Option<(char, &Glyph)> {
+        self.glyphs
+            .get(&character)
+            .map(|glyph| (character, glyph))
+            .or_else(|| self.glyphs.get(&'?').map(|glyph| ('?', glyph)))
+    }
+
+    /// Calls `f` with each glyph and where its pen is, in atlas pixels from the start of the line.
+    fn for_each_glyph(&self, line: &str, mut f: impl FnMut(f32, &Glyph)) -> f32 {
+        let mut pen = 0.0;
+        let mut previous = None;
+        for (character, glyph) in line.chars().filter_map(|c| self.glyph(c)) {
+            if let Some(previous) = previous {
+                pen += self.kerning.get(&(previous, character)).unwrap_or(&0.0);
+            }
+            f(pen, glyph);
+            pen += glyph.x_advance;
+            previous = Some(character);
+        }
+        pen
+    }
+
+    /// The width of a line in atlas pixels.
+    fn measure(&self, line: &str) -> f32 {
+        self.for_each_glyph(line, |_, _| {})
+    }
+
+    /// Splits `text` into lines at newlines and, if they're too wide, between words.
+    fn wrap(&self, text: &str, max_width: Option<f32>) -> Vec<String> {
+        let mut lines = Vec::new();
+        for paragraph in text.split('\n') {
+            let mut line = String::new();
+            for word in paragraph.split(' ') {
+                let longer = if line.is_empty() {
+                    word.to_owned()
+                } else {
+                    format!("{} {}", line, word)
+                };
+                if !line.is_empty() && max_width.map_or(false, |max| self.measure(&longer) > max) {
+                    lines.push(line);
+                    line = word.to_owned();
+                } else {
+                    line = longer;
+                }
+            }
+            lines.push(line);
+        }
+        lines
+    }
+
+    /// Places `text` with the top of its first line at 0.0,
+    /// and its lines starting, centred on or ending at 0.0 depending on `layout.align`.
+    pub fn layout(&self, text: &str, layout: &TextLayout) -> PlacedText {
+        let scale = layout.size / self.line_height;
+        let lines = self.wrap(text, layout.max_width.map(|width| width / scale));
+
+        let mut placed = PlacedText {
+            glyphs: Vec::new(),
+            size: layout.size,
+            width: 0.0,
+            height: lines.len() as f32 * layout.size,
+        };
+        for (i, line) in lines.iter().enumerate() {
+            let width = self.measure(line) * scale;
+            placed.width = placed.width.max(width);
+            let left = match layout.align {
+                Align::Left => 0.0,
+                Align::Center => -width / 2.0,
+                Align::Right => -width,
+            };
+            let top = -(i as f32) * layout.size;
+
+            let atlas_size = self.atlas_size;
+            let glyphs = &mut placed.glyphs;
+            self.for_each_glyph(line, |pen, glyph| {
+                if glyph.width == 0.0 || glyph.height == 0.0 {
+                    return;
+                }
+                let x = left + (pen + glyph.x_offset) * scale;
+                let y = top - glyph.y_offset * scale;
+                let (width, height) = (glyph.width * scale, glyph.height * scale);
+                glyphs.push(GlyphQuad {
+                    corners: [
+                        Vector3::new(x, y - height, 0.0),
+                        Vector3::new(x + width, y - height, 0.0),
+                        Vector3::new(x + width, y, 0.0),
+                        Vector3::new(x, y, 0.0),
+                    ],
+                    uv_min: Vector2::new(glyph.x / atlas_size.x, glyph.y / atlas_size.y),
+                    uv_max: Vector2::new(
+                        (glyph.x + glyph.width) / atlas_size.x,
+                        (glyph.y + glyph.height) / atlas_size.y,
+                    ),
+                });
+            });
+        }
+        placed
+    }
+}
+
+/// Splits a BMFont line into its `key=value` pairs, where values can be quoted.
+fn attributes(line: &str) -> HashMap<&str, &str> {
+    let mut values = HashMap::new();
+    let mut rest = line;
+    while let Some(equals) = rest.find('=') {
+        let key = rest[..equals].rsplit(' ').next().unwrap_or("");
+        rest = &rest[equals + 1..];
+        let (value, remaining) = if rest.starts_with('"') {
+            let end = rest[1..].find('"').map_or(rest.len(), |end| end + 1);
+            (&rest[1..end], rest.get(end + 1..).unwrap_or(""))
+        } else {
+            let end = rest.find(' ').unwrap_or(rest.len());
+            (&rest[..end], &rest[end..])
+        };
+        values.insert(key, value);
+        rest = remaining;
+    }
+    values
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TextLayout {
+    /// World units from one line to the next.
+    pub size: f32,
+    pub align: Align,
+    /// Lines are wrapped between words to fit, if set.
+    pub max_width: Option<f32>,
+}
+
+/// Corners are counter-clockwise from the bottom left, like `ShapeBatch::push_quad`.
+/// Texture coordinates start at the top left of the atlas.
+#[derive(Debug, Clone, Copy)]
+pub struct GlyphQuad {
+    pub corners: [Vector3; 4],
+    pub uv_min: Vector2,
+    pub uv_max: Vector2,
+}
+
+/// Text laid out in world units, ready to draw with `RenderBackend::draw_text`.
+#[derive(Debug, Clone)]
+pub struct PlacedText {
+    pub glyphs: Vec<GlyphQuad>,
+    /// The `TextLayout::size` it was placed with.
+    pub size: f32,
+    /// Of the widest line.
+    pub width: f32,
+    pub height: f32,
+}
+
+impl PlacedText {
+    pub fn translate(&mut self, offset: Vector3) {
+        for glyph in &mut self.glyphs {
+            for corner in &mut glyph.corners {
+                *corner += offset;
+            }
+        }
+    }
+}
+
+/// A level's name, shown over the level as it fades in and then faded out.
+pub struct TitleCard {
+    title: String,
+    /// Ticks since it was shown.
+    age: u32,
+}
+
+impl TitleCard {
+    pub fn new() -> Self {
+        Self {
+            title: String::new(),
+            age: 0,
+        }
+    }
+
+    /// An empty title hides the card.
+    pub fn show(&mut self, title: &str) {
+        self.title = title.to_owned();
+        self.age = 0;
+    }
+
+    /// Call once per tick.
+    pub fn update(&mut self) {
+        self.age = self.age.saturating_add(1);
+    }
+
+    fn alpha(&self) -> f32 {
+        if self.age < TITLE_FADE_IN {
+            self.age as f32 / TITLE_FADE_IN as f32
+        } else if self.age < TITLE_FADE_IN + TITLE_HOLD {
+            1.0
+        } else {
+            let fading = self.age - TITLE_FADE_IN - TITLE_HOLD;
+            1.0 - (fading as f32 / TITLE_FADE_OUT as f32).min(1.0)
+        }
+    }
+
+    /// Draws the title a little above the middle of what's visible.
+    pub fn draw(&self, backend: &mut impl RenderBackend, font: &Font, visible: &Bounds) {
+        let alpha = self.alpha();
+        if self.title.is_empty() || alpha <= 0.0 {
+            return;
+        }
+        let size = visible.size();
+        let mut text = font.layout(
+            &self.title,
+            &TextLayout {
+                size: size.y * 0.08,
+                align: Align::Center,
+                max_width: Some(size.x * 0.8),
+            },
+        );
+        let center = visible.center();
+        text.translate(Vector3::new(
+            center.x,
+            center.y + size.y * 0.2 + text.height / 2.0,
+            0.0,
+        ));
+        backend.draw_text(font, &text, Color::new(1.0, 1.0, 1.0, alpha));
+    }
+}
+
+/// Labels the top corners while the editor is open, so it's clear edits are being made and to which level.
+pub fn draw_editor_labels(
+    backend: &mut impl RenderBackend,
+    font: &Font,
+    visible: &Bounds,
+    level: u32,
+    name: &str,
+) {
+    let size = visible.size().y * 0.04;
+    let label = |text: &str, align| {
+        font.layout(
+            text,
+            &TextLayout {
+                size,
+                align,
+                max_width: None,
+            },
+        )
+    };
+    let top = visible.max.y - size / 2.0;
+    let mut editing = label("Editing", Align::Left);
+    editing.translate(Vector3::new(visible.min.x + size / 2.0, top, 0.0));
+    let title = if name.is_empty() {
+        format!("Level {}", level)
+    } else {
+        format!("Level {}, {}", level, name)
+    };
+    let mut title = label(&title, Align::Right);
+    title.translate(Vector3::new(visible.max.x - size / 2.0, top, 0.0));
+
+    let color = Color::new(1.0, 1.0, 1.0, 0.6);
+    backend.draw_text(font, &editing, color);
+    backend.draw_text(font, &title, color);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn font() -> Font {
+        Font::parse(include_str!("fonts/dejavu_sans.fnt")).unwrap()
+    }
+
+    /// Laid out a line height tall, so world units are atlas pixels.
+    fn layout(font: &Font, text: &str, align: Align, max_width: Option<f32>) -> PlacedText {
+        font.layout(
+            text,
+            &TextLayout {
+                size: font.line_height,
+                align,
+                max_width,
+            },
+        )
+    }
+
+    fn left_edge(text: &PlacedText) -> f32 {
+        text.glyphs
+            .iter()
+            .map(|glyph| glyph.corners[0].x)
+            .fold(f32::INFINITY, f32::min)
+    }
+
+    #[test]
+    fn lines_start_centre_or_end_at_the_origin() {
+        let font = font();
+        let left = layout(&font, "Summer", Align::Left, None);
+        let center = layout(&font, "Summer", Align::Center, None);
+        let right = layout(&font, "Summer", Align::Right, None);
+
+        assert_eq!(left.width, center.width);
+        assert_eq!(left.width, right.width);
+        // Glyphs can hang a little past the pen, so compare against the left aligned line.
+        let x = left_edge(&left);
+        assert!((left_edge(&center) - (x - left.width / 2.0)).abs() < 0.001);
+        assert!((left_edge(&right) - (x - left.width)).abs() < 0.001);
+    }
+
+    #[test]
+    fn long_lines_wrap_between_words() {
+        let font = font();
+        let one_line = layout(&font, "Summer breeze", Align::Left, None);
+        assert_eq!(one_line.height, font.line_height);
+
+        // Wide enough for either word but not both.
+        let summer = font.measure("Summer");
+        let wrapped = layout(&font, "Summer breeze", Align::Left, Some(summer + 1.0));
+        assert_eq!(wrapped.height, font.line_height * 2.0);
+        assert_eq!(wrapped.width, summer.max(font.measure("breeze")));
+        let tops: Vec<f32> = wrapped.glyphs.iter().map(|g| g.corners[3].y).collect();
+        assert!(tops.iter().any(|&top| top < -font.line_height / 2.0));
+
+        // Words wider than the line aren't split, and newlines always start a new line.
+        let narrow = layout(&font, "Summer", Align::Left, Some(1.0));
+        assert_eq!(narrow.height, font.line_height);
+        let broken = layout(&font, "Summer\nbreeze", Align::Left, None);
+        assert_eq!(broken.height, font.line_height * 2.0);
+    }
+
+    #[test]
+    fn pairs_are_kerned() {
+        let font = font();
+        // The bundled font moves V 3 pixels towards A and o 8 pixels towards T.
+        let advance = |c| font.glyphs[&c].x_advance;
+        assert_eq!(font.measure("AV"), advance('A') + advance('V') - 3.0);
+        assert_eq!(font.measure("To"), advance('T') + advance('o') - 8.0);
+        assert_eq!(
+            layout(&font, "To", Align::Left, None).width,
+            font.measure("To")
+        );
+        // No kerning between pairs the font doesn't list.
+        assert_eq!(font.measure("oT"), advance('o') + advance('T'));
+    }
+}
@@ -0,0 +1,86 @@
+use crate::*;
+
+/// A level's colours, declared in its data and blended between levels while fading.
+#[derive(Debug, Clone, Copy)]
+pub struct Theme {
+    /// The same as `background_bottom` for a single colour.
+    pub background_top: Color,
+    pub background_bottom: Color,
+    pub line_color: Color,
+    pub user_line_color: Color,
+    pub ball_color: Color,
+    pub collectible_color: Color,
+    pub ball_style: BallStyle,
+}
+
+pub const BACKGROUND_COLOR: Color = Color::new(19.0 / 255.0, 12.0 / 255.0, 61.0 / 255.0, 1.0);
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            background_top: BACKGROUND_COLOR,
+            background_bottom: BACKGROUND_COLOR,
+            line_color: LEVEL_LINE_COLOR,
+            user_line_color: USER_LINE_COLOR,
+            ball_color: Color::new(1.0, 1.0, 1.0, 1.0),
+            collectible_color: Color::new(1.0, 1.0, 1.0, 1.0),
+            ball_style: BallStyle::default(),
+        }
+    }
+}
+
+impl Theme {
+    /// `amount` of 0.0 is `self` and 1.0 is `other`. The ball's style isn't blended.
+    pub fn lerp(&self, other: &Theme, amount: f32) -> Theme {
+        let amount = amount.max(0.0).min(1.0);
+        Theme {
+            background_top: lerp_color(self.background_top, other.background_top, amount),
+            background_bottom: lerp_color(self.background_bottom, other.background_bottom, amount),
+            line_color: lerp_color(self.line_color, other.line_color, amount),
+            user_line_color: lerp_color(self.user_line_color, other.user_line_color, amount),
+            ball_color: lerp_color(self.ball_color, other.ball_color, amount),
+            collectible_color: lerp_color(self.collectible_color, other.collectible_color, amount),
+            ball_style: other.ball_style,
+        }
+    }
+}
+
+pub fn lerp_color(a: Color, b: Color, amount: f32) -> Color {
+    Color::new(
+        a.r + (b.r - a.r) * amount,
+        a.g + (b.g - a.g) * amount,
+        a.b + (b.b - a.b) * amount,
+        a.a + (b.a - a.a) * amount,
+    )
+}
+
+/// Parses `#rrggbb` or `#rrggbbaa`.
+pub fn parse_color(s: &str) -> Result<Color, String> {
+    let error = || format!("Invalid colour {:?}, expected #rrggbb or #rrggbbaa", s);
+    if !s.starts_with('#') || !(s.len() == 7 || s.len() == 9) {
+        return Err(error());
+    }
+    let channel = |i: usize| {
+        s.get(1 + i * 2..3 + i * 2)
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            .map(|value| value as f32 / 255.0)
+            .ok_or_else(error)
+    };
+    let alpha = if s.len() == 9 { channel(3)? } else { 1.0 };
+    Ok(Color::new(channel(0)?, channel(1)?, channel(2)?, alpha))
+}
+
+/// The inverse of `parse_color`, leaving out the alpha when it's opaque.
+pub fn color_to_hex(color: Color) -> String {
+    let channel = |value: f32| (value.max(0.0).min(1.0) * 255.0).round() as u8;
+    let mut hex = format!(
+        "#{:02x}{:02x}{:02x}",
+        channel(color.r),
+        channel(color.g),
+        channel(color.b)
+    );
+    if channel(color.a) != 255 {
+        hex += &format!("{:02x}", channel(color.a));
+    }
+    hex
+}
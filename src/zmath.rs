@@ -23,7 +23,7 @@ impl Vector4 {
     }
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub struct Vector2 {
     pub x: f32,
     pub y: f32,
@@ -156,7 +156,7 @@ pub type Point3 = Vector3;
 pub type Direction3 = Vector3;
 
 // ----------------- Vector3 -----------------------
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub struct Vector3 {
     pub x: f32,
     pub y: f32,